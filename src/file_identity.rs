@@ -0,0 +1,185 @@
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+
+/// How many bytes are hashed from the start and from the end of a file
+const HASH_SAMPLE: u64 = 64 * 1024;
+
+/// What a file looked like when it was renamed, so an undo can tell whether
+/// the file now at the new name is still the one that was renamed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileIdentity {
+    /// Device and inode; only known on Unix
+    pub device: Option<u64>,
+    pub inode: Option<u64>,
+    /// Size in bytes; `None` for directories
+    pub size: Option<u64>,
+    /// Modification time in nanoseconds since the Unix epoch; `None` for directories
+    pub modified: Option<i64>,
+    /// Hash of the first and last 64 KiB of a regular file
+    pub content_hash: Option<String>,
+}
+
+/// Why the file at a path is not the one a record describes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdentityMismatch {
+    /// Another file (different device or inode) has the name now
+    DifferentFile,
+    /// The file has a different size
+    SizeChanged,
+    /// The file has the same size but different content
+    ContentChanged,
+    /// The file looks the same but was modified
+    Modified,
+}
+
+impl IdentityMismatch {
+    pub fn describe(self) -> &'static str {
+        match self {
+            IdentityMismatch::DifferentFile => "a different file has this name now",
+            IdentityMismatch::SizeChanged => "the file size changed since the rename",
+            IdentityMismatch::ContentChanged => "the file content changed since the rename",
+            IdentityMismatch::Modified => "the file was modified since the rename",
+        }
+    }
+}
+
+impl FileIdentity {
+    /// Capture the identity of the file at `path` without following symlinks.
+    /// Returns `None` if the path does not exist.
+    pub fn of(path: &Path) -> Option<Self> {
+        let metadata = path.symlink_metadata().ok()?;
+        let (device, inode) = device_and_inode(&metadata);
+        if metadata.is_dir() {
+            return Some(FileIdentity { device, inode, size: None, modified: None, content_hash: None });
+        }
+        
+        let modified = metadata.modified().ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .and_then(|duration| i64::try_from(duration.as_nanos()).ok());
+        let content_hash = if metadata.is_file() { content_hash(path, metadata.len()) } else { None };
+        
+        Some(FileIdentity { device, inode, size: Some(metadata.len()), modified, content_hash })
+    }
+    
+    /// Compare with the identity of the file as it is now. Only fields known
+    /// on both sides are compared, so records written without some of them
+    /// still match.
+    pub fn mismatch(&self, current: &FileIdentity) -> Option<IdentityMismatch> {
+        fn differs<T: PartialEq>(recorded: &Option<T>, current: &Option<T>) -> bool {
+            matches!((recorded, current), (Some(a), Some(b)) if a != b)
+        }
+        
+        if differs(&self.device, &current.device) || differs(&self.inode, &current.inode) {
+            Some(IdentityMismatch::DifferentFile)
+        } else if differs(&self.size, &current.size) {
+            Some(IdentityMismatch::SizeChanged)
+        } else if differs(&self.content_hash, &current.content_hash) {
+            Some(IdentityMismatch::ContentChanged)
+        } else if differs(&self.modified, &current.modified) {
+            Some(IdentityMismatch::Modified)
+        } else {
+            None
+        }
+    }
+    
+    /// Check the file now at `path` against this identity
+    pub fn verify(&self, path: &Path) -> Option<IdentityMismatch> {
+        match FileIdentity::of(path) {
+            Some(current) => self.mismatch(&current),
+            // A missing file is reported by the rename itself
+            None => None,
+        }
+    }
+}
+
+#[cfg(unix)]
+fn device_and_inode(metadata: &fs::Metadata) -> (Option<u64>, Option<u64>) {
+    use std::os::unix::fs::MetadataExt;
+    (Some(metadata.dev()), Some(metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn device_and_inode(_metadata: &fs::Metadata) -> (Option<u64>, Option<u64>) {
+    (None, None)
+}
+
+/// FNV-1a over the first and last `HASH_SAMPLE` bytes (the whole file when it
+/// is smaller), as 16 hex digits. Fast enough to run on every rename while
+/// still telling apart files that were rewritten in place.
+fn content_hash(path: &Path, len: u64) -> Option<String> {
+    let mut file = File::open(path).ok()?;
+    let mut sample = Vec::new();
+    (&mut file).take(HASH_SAMPLE).read_to_end(&mut sample).ok()?;
+    if len > 2 * HASH_SAMPLE {
+        file.seek(SeekFrom::Start(len - HASH_SAMPLE)).ok()?;
+    }
+    file.take(HASH_SAMPLE).read_to_end(&mut sample).ok()?;
+    
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in sample {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    
+    Some(format!("{:016x}", hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    
+    #[test]
+    fn test_identity_detects_changes() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("file.txt");
+        fs::write(&path, "original")?;
+        let identity = FileIdentity::of(&path).ok_or("no identity")?;
+        assert!(identity.content_hash.is_some());
+        assert_eq!(identity.verify(&path), None);
+        
+        // Same size, different bytes
+        fs::write(&path, "modified")?;
+        let mut rewritten = identity.clone();
+        rewritten.modified = None;
+        assert_eq!(rewritten.verify(&path), Some(IdentityMismatch::ContentChanged));
+        
+        fs::write(&path, "much longer content")?;
+        assert_eq!(identity.verify(&path), Some(IdentityMismatch::SizeChanged));
+        
+        // A new file moved over the old name
+        let replacement = temp_dir.path().join("replacement.txt");
+        fs::write(&replacement, "original")?;
+        fs::rename(&replacement, &path)?;
+        #[cfg(unix)]
+        assert_eq!(identity.verify(&path), Some(IdentityMismatch::DifferentFile));
+        
+        // Missing fields never cause a mismatch
+        let legacy = FileIdentity { device: None, inode: None, size: Some(8), modified: None, content_hash: None };
+        assert_eq!(legacy.verify(&path), None);
+        Ok(())
+    }
+    
+    #[test]
+    fn test_content_hash_samples_large_files() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let path = temp_dir.path().join("large.bin");
+        let mut data = vec![0u8; (3 * HASH_SAMPLE) as usize];
+        fs::write(&path, &data)?;
+        let before = content_hash(&path, data.len() as u64);
+        
+        // The middle is not sampled, the end is
+        data[(HASH_SAMPLE + 10) as usize] = 1;
+        fs::write(&path, &data)?;
+        assert_eq!(content_hash(&path, data.len() as u64), before);
+        
+        let last = data.len() - 1;
+        data[last] = 1;
+        fs::write(&path, &data)?;
+        assert_ne!(content_hash(&path, data.len() as u64), before);
+        Ok(())
+    }
+}
@@ -0,0 +1,346 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use crate::os_names::name_to_bytes;
+use crate::rename_db::{CollisionStrategy, OperationKind, OperationRecord, RenameDatabase, RenameRecord};
+
+/// Version written to history exports; newer exports are rejected by `HistoryExport::load`
+pub const EXPORT_VERSION: u32 = 1;
+
+/// Output formats of `ftmi history export`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// Everything recorded, readable by `ftmi history import`
+    Json,
+    /// One row per rename, for spreadsheets
+    Csv,
+    /// A shell script of `mv` commands
+    Shell,
+}
+
+impl ExportFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(ExportFormat::Json),
+            "csv" => Some(ExportFormat::Csv),
+            "sh" => Some(ExportFormat::Shell),
+            _ => None,
+        }
+    }
+}
+
+/// Whether an exported script repeats the renames or reverses them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptDirection {
+    Replay,
+    Revert,
+}
+
+/// Operations taken out of a history to be archived, inspected or merged
+/// into the history of another machine
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryExport {
+    pub version: u32,
+    pub exported: DateTime<Utc>,
+    /// Oldest first
+    pub operations: Vec<ExportedOperation>,
+}
+
+/// One operation with everything recorded about it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExportedOperation {
+    pub id: String,
+    /// `None` for operations recorded before operations had details
+    pub details: Option<OperationRecord>,
+    /// In the order they were made
+    pub renames: Vec<RenameRecord>,
+}
+
+/// What `RenameDatabase::import_history` merged
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImportSummary {
+    pub operations: usize,
+    pub renames: usize,
+    /// Operations left out because the history already has them
+    pub skipped: Vec<String>,
+}
+
+impl ExportedOperation {
+    pub fn kind(&self) -> OperationKind {
+        self.details.as_ref().map_or(OperationKind::Rename, |details| details.kind)
+    }
+    
+    /// When the operation started
+    pub fn timestamp(&self) -> Option<DateTime<Utc>> {
+        self.details.as_ref().map(|details| details.timestamp)
+            .or_else(|| self.renames.first().map(|record| record.timestamp))
+    }
+}
+
+impl HistoryExport {
+    /// Collect `operation_ids` (unknown IDs are an error) from `db`, oldest first
+    pub fn from_database(db: &RenameDatabase, operation_ids: &[String]) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut operations = Vec::new();
+        for id in operation_ids {
+            let details = db.operation(id)?;
+            let renames = db.get_operation_renames(id)?;
+            if details.is_none() && renames.is_empty() {
+                return Err(format!("Operation not found: {}", id).into());
+            }
+            if !operations.iter().any(|operation: &ExportedOperation| operation.id == *id) {
+                operations.push(ExportedOperation { id: id.clone(), details, renames });
+            }
+        }
+        operations.sort_by(|a, b| a.timestamp().cmp(&b.timestamp()).then_with(|| a.id.cmp(&b.id)));
+        
+        Ok(Self {
+            version: EXPORT_VERSION,
+            exported: Utc::now(),
+            operations,
+        })
+    }
+    
+    pub fn rename_count(&self) -> usize {
+        self.operations.iter().map(|operation| operation.renames.len()).sum()
+    }
+    
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+    
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+    
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let export = Self::from_json(&fs::read_to_string(path)?)?;
+        if export.version > EXPORT_VERSION {
+            return Err(format!(
+                "Export version {} is newer than the supported version {}",
+                export.version, EXPORT_VERSION
+            ).into());
+        }
+        
+        for operation in &export.operations {
+            let reverts = operation.details.as_ref().and_then(|details| details.reverts.as_ref());
+            let reverted_by = operation.renames.iter().filter_map(|record| record.reverted_by.as_ref());
+            for id in std::iter::once(&operation.id).chain(reverts).chain(reverted_by) {
+                if !is_valid_operation_id(id) {
+                    return Err(format!("Invalid operation ID: {:?}", id).into());
+                }
+            }
+        }
+        Ok(export)
+    }
+    
+    /// One row per rename with a header row. Names that are not valid UTF-8
+    /// are written lossily.
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "operation_id,kind,timestamp,old_path,new_path,prefix_removed,collision,reverted_by,user,hostname,note,tags\n"
+        );
+        for operation in &self.operations {
+            let context = operation.details.as_ref().map(|details| &details.context);
+            for record in &operation.renames {
+                let fields = [
+                    operation.id.clone(),
+                    operation.kind().name().to_string(),
+                    record.timestamp.to_rfc3339(),
+                    record.old_path.to_string_lossy().into_owned(),
+                    record.new_path.to_string_lossy().into_owned(),
+                    record.prefix_removed.clone(),
+                    record.collision.map(CollisionStrategy::name).unwrap_or_default().to_string(),
+                    record.reverted_by.clone().unwrap_or_default(),
+                    context.and_then(|context| context.user.clone()).unwrap_or_default(),
+                    context.and_then(|context| context.hostname.clone()).unwrap_or_default(),
+                    context.and_then(|context| context.note.clone()).unwrap_or_default(),
+                    context.map(|context| context.tags.join(" ")).unwrap_or_default(),
+                ];
+                let row: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+                csv.push_str(&row.join(","));
+                csv.push('\n');
+            }
+        }
+        csv
+    }
+    
+    /// A POSIX shell script with one `mv` per rename. `Replay` repeats the
+    /// operations in order; `Revert` renames every file back, newest first.
+    /// Files are never moved over an existing name: such renames are skipped
+    /// and the script exits with status 2, like ftmi itself. Paths are
+    /// written byte for byte, so the script is not necessarily valid UTF-8.
+    pub fn to_shell(&self, direction: ScriptDirection) -> Vec<u8> {
+        let mut script: Vec<u8> = Vec::new();
+        let action = match direction {
+            ScriptDirection::Replay => "Replays",
+            ScriptDirection::Revert => "Reverts",
+        };
+        script.extend_from_slice(format!(
+            "#!/bin/sh\n# {} {} operation(s) ({} renames) exported from the ftmi history on {}\n\n",
+            action,
+            self.operations.len(),
+            self.rename_count(),
+            self.exported.format("%Y-%m-%d %H:%M:%S UTC"),
+        ).as_bytes());
+        script.extend_from_slice(concat!(
+            "status=0\n",
+            "move() {\n",
+            "    if [ -e \"$2\" ] || [ -L \"$2\" ]; then\n",
+            "        echo \"skipped: $2 already exists\" >&2\n",
+            "        status=2\n",
+            "    elif ! mv -- \"$1\" \"$2\"; then\n",
+            "        status=2\n",
+            "    fi\n",
+            "}\n",
+        ).as_bytes());
+        
+        let operations: Vec<&ExportedOperation> = match direction {
+            ScriptDirection::Replay => self.operations.iter().collect(),
+            ScriptDirection::Revert => self.operations.iter().rev().collect(),
+        };
+        for operation in operations {
+            script.extend_from_slice(format!(
+                "\n# {} ({}{})\n",
+                operation.id.replace(char::is_control, "?"),
+                operation.kind().name(),
+                operation.timestamp().map(|timestamp| format!(", {}", timestamp.format("%Y-%m-%d %H:%M:%S UTC"))).unwrap_or_default(),
+            ).as_bytes());
+            
+            let renames: Vec<&RenameRecord> = match direction {
+                ScriptDirection::Replay => operation.renames.iter().collect(),
+                ScriptDirection::Revert => operation.renames.iter().rev().collect(),
+            };
+            for record in renames {
+                let (from, to) = match direction {
+                    ScriptDirection::Replay => (&record.old_path, &record.new_path),
+                    ScriptDirection::Revert => (&record.new_path, &record.old_path),
+                };
+                if direction == ScriptDirection::Replay && record.collision == Some(CollisionStrategy::Subfolder) {
+                    if let Some(parent) = to.parent() {
+                        script.extend_from_slice(b"mkdir -p -- ");
+                        script.extend_from_slice(&shell_quote(parent));
+                        script.push(b'\n');
+                    }
+                }
+                script.extend_from_slice(b"move ");
+                script.extend_from_slice(&shell_quote(from));
+                script.push(b' ');
+                script.extend_from_slice(&shell_quote(to));
+                script.push(b'\n');
+            }
+        }
+        
+        script.extend_from_slice(b"\nexit $status\n");
+        script
+    }
+}
+
+/// Whether `id` looks like an ID made by `generate_operation_id` (or by older
+/// versions, `op_` followed by a timestamp)
+fn is_valid_operation_id(id: &str) -> bool {
+    id.strip_prefix("op_")
+        .is_some_and(|rest| !rest.is_empty() && rest.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'))
+}
+
+/// Quote a CSV field when it contains a separator, quote or line break
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Single-quote a path for `sh`; a quote inside is written as `'\''`
+fn shell_quote(path: &Path) -> Vec<u8> {
+    let mut quoted = vec![b'\''];
+    for &byte in name_to_bytes(path.as_os_str()).iter() {
+        if byte == b'\'' {
+            quoted.extend_from_slice(b"'\\''");
+        } else {
+            quoted.push(byte);
+        }
+    }
+    quoted.push(b'\'');
+    quoted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rename_db::tracked_rename_batch;
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+    
+    fn exported_operation(temp_dir: &TempDir) -> Result<(RenameDatabase, HistoryExport), Box<dyn std::error::Error>> {
+        let db = RenameDatabase::new(temp_dir.path().join("renames.db"));
+        db.initialize()?;
+        let old_path = temp_dir.path().join("[Artist] It's.mp3");
+        let new_path = temp_dir.path().join("It's.mp3");
+        fs::write(&old_path, "")?;
+        tracked_rename_batch(&db, &[(old_path, new_path)], &[], "[Artist]", "op_export");
+        let export = HistoryExport::from_database(&db, &["op_export".to_string()])?;
+        Ok((db, export))
+    }
+    
+    #[test]
+    fn test_export_round_trip() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let (db, export) = exported_operation(&temp_dir)?;
+        assert_eq!(export.operations.len(), 1);
+        assert_eq!(export.rename_count(), 1);
+        assert_eq!(HistoryExport::from_json(&export.to_json()?)?, export);
+        
+        assert!(HistoryExport::from_database(&db, &["op_unknown".to_string()]).is_err());
+        Ok(())
+    }
+    
+    #[test]
+    fn test_export_csv_and_shell() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let (_db, export) = exported_operation(&temp_dir)?;
+        
+        let csv = export.to_csv();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[1].starts_with("op_export,rename,"));
+        assert!(lines[1].contains(",[Artist],"));
+        assert_eq!(csv_field("a, \"b\""), "\"a, \"\"b\"\"\"");
+        
+        let old_path = shell_quote(&temp_dir.path().join("[Artist] It's.mp3"));
+        let new_path = shell_quote(&temp_dir.path().join("It's.mp3"));
+        assert!(String::from_utf8_lossy(&old_path).ends_with("/[Artist] It'\\''s.mp3'"));
+        
+        let replay = export.to_shell(ScriptDirection::Replay);
+        let expected = [b"move ".as_slice(), &old_path, b" ", &new_path, b"\n"].concat();
+        assert!(replay.windows(expected.len()).any(|window| window == expected));
+        let revert = export.to_shell(ScriptDirection::Revert);
+        let expected = [b"move ".as_slice(), &new_path, b" ", &old_path, b"\n"].concat();
+        assert!(revert.windows(expected.len()).any(|window| window == expected));
+        assert!(revert.starts_with(b"#!/bin/sh\n"));
+        
+        assert_eq!(shell_quote(&PathBuf::from("plain")), b"'plain'");
+        Ok(())
+    }
+    
+    #[test]
+    fn test_load_rejects_invalid_operation_ids() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let (_db, mut export) = exported_operation(&temp_dir)?;
+        let path = temp_dir.path().join("export.json");
+        fs::write(&path, export.to_json()?)?;
+        assert!(HistoryExport::load(&path).is_ok());
+        
+        export.operations[0].id = "op_x\nrm -rf ~".to_string();
+        fs::write(&path, export.to_json()?)?;
+        assert!(HistoryExport::load(&path).is_err());
+        assert!(!export.to_shell(ScriptDirection::Replay).windows(6).any(|window| window == b"\nrm -r"));
+        
+        assert!(is_valid_operation_id("op_01HZX3V4Q8W5M2N7P9R0S1T2U3"));
+        assert!(is_valid_operation_id("op_1700000000"));
+        assert!(!is_valid_operation_id("op_"));
+        assert!(!is_valid_operation_id("rename_1"));
+        Ok(())
+    }
+}
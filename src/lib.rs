@@ -1,19 +1,40 @@
+pub mod file_identity;
+pub mod history_export;
+pub mod migrations;
+pub mod os_names;
 pub mod path_extraction;
 pub mod prefix_finder;
 pub mod rename_db;
+pub mod rename_edit;
+pub mod rename_plan;
+pub mod selection;
 pub mod subcommands;
+pub mod suffix_finder;
 
 use std::io::{self, BufRead};
 use std::path::Path;
 
+pub use file_identity::{FileIdentity, IdentityMismatch};
+pub use history_export::{ExportFormat, ExportedOperation, HistoryExport, ImportSummary, ScriptDirection};
+pub use os_names::display_name;
 pub use path_extraction::{extract_paths_from_text, deduplicate_paths};
 pub use prefix_finder::{
     find_common_prefix, find_longest_prefix, PrefixOptions, CommonPrefix, PrefixedPath, PrefixMode,
-    extract_prefix_from_filename, remove_prefix, remove_prefix_with_delimiter
+    extract_prefix_from_filename, remove_prefix, remove_prefix_with_delimiter,
+    directories_to_analyze, Recursion, TreeGrouping, UnicodeNormalization
+};
+pub use suffix_finder::{
+    find_common_suffix, find_longest_suffix, CommonSuffix, SuffixedPath,
+    remove_suffix, remove_suffix_with_delimiter, remove_suffix_from_name
 };
 pub use rename_db::{
-    RenameDatabase, RenameRecord, generate_operation_id, tracked_rename
+    CollisionStrategy, DatabaseSource, HistoryQuery, HistoryStats, JournalEntry, OperationContext, OperationKind, OperationRecord,
+    PruneOptions, PruneSummary, Recovery, RenameDatabase, RenameRecord, RenameOutcome, UndoOptions,
+    batch_conflicts, generate_operation_id, is_legacy_operation_id, rename_batch, resolve_collisions, tracked_rename, tracked_rename_batch
 };
+pub use rename_edit::{apply_edits, edit_buffer, EditError};
+pub use rename_plan::{plan_conflicts, plan_directory, Affix, PlanGroup, PlannedRename, RenamePlan};
+pub use selection::{parse_ranges, parse_selection_command, Selection, SelectionCommand, SelectionError};
 
 
 /// Main application logic for processing directories from stdin
@@ -55,7 +76,7 @@ pub fn process_directories_from_stdin() -> Result<(), Box<dyn std::error::Error>
                             println!("Prefix: {} - {} files", prefix.prefix, prefix.occurrences);
                         }
                         for file in &prefix.files {
-                            println!("  - {}", file);
+                            println!("  - {}", display_name(file.as_os_str()));
                         }
                         println!();
                     }
@@ -103,11 +124,11 @@ pub fn process_directories_longest_prefix() -> Result<(), Box<dyn std::error::Er
                 } else {
                     println!("Directory: {}", dir_path);
                     for prefixed_path in &prefixed_paths {
-                        println!("Prefix: {}", prefixed_path.prefix);
+                        println!("Prefix: {}", prefixed_path.literal_prefix());
                         println!("Files ({}):", prefixed_path.paths.len());
                         for path in &prefixed_path.paths {
-                            if let Some(filename) = path.file_name().and_then(|s| s.to_str()) {
-                                println!("  {}", filename);
+                            if let Some(filename) = path.file_name() {
+                                println!("  {}", display_name(filename));
                             }
                         }
                         println!();
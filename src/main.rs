@@ -10,6 +10,18 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             "rename" => {
                 return ftmi::subcommands::rename_command(args);
             }
+            "apply" => {
+                return ftmi::subcommands::apply_command(args);
+            }
+            "tui" => {
+                return ftmi::subcommands::tui_command(args);
+            }
+            "recover" => {
+                return ftmi::subcommands::recover_command(args);
+            }
+            "history" => {
+                return ftmi::subcommands::history_command(args);
+            }
             "analyze" => {
                 // Execute the analysis functionality
                 return process_directories_longest_prefix();
@@ -55,6 +67,10 @@ fn print_help() {
     println!();
     println!("SUBCOMMANDS:");
     println!("    rename              Interactive prefix removal tool (main functionality)");
+    println!("    apply               Execute a rename plan saved with 'rename --plan'");
+    println!("    tui                 Full-screen terminal UI with preview and undo history");
+    println!("    recover             Reconcile renames interrupted by a crash with the history");
+    println!("    history             Search, export, import, prune and summarize the rename history");
     println!("    analyze             Analyze directories for longest prefix detection");
     println!("    extract-paths       Extract file paths from text input");
     println!("    find-delimited      Find delimited prefixes like [Artist], (Draft)");
@@ -70,6 +86,7 @@ fn print_help() {
     println!("    ftmi rename ./music");
     println!("    ftmi rename --continuous");
     println!("    ftmi rename --undo");
+    println!("    ftmi tui ./music");
     println!();
     println!("    # Analysis");
     println!("    ftmi analyze");
@@ -0,0 +1,318 @@
+use rusqlite::{Connection, Result as SqliteResult, Transaction, TransactionBehavior};
+
+/// Schema version written by this build; the number of the last migration
+pub const SCHEMA_VERSION: u32 = 7;
+
+/// One step of the schema history. Migrations run in order, each in its own
+/// transaction that also stores its `version` in `PRAGMA user_version`.
+struct Migration {
+    version: u32,
+    description: &'static str,
+    apply: fn(&Transaction) -> SqliteResult<()>,
+}
+
+/// Every schema change since the first release, oldest first. Databases
+/// written before versioning was added report version 0 but may already
+/// contain any of these changes, so every step must be safe to run again.
+/// Append new migrations at the end and never change released ones.
+const MIGRATIONS: &[Migration] = &[
+    Migration { version: 1, description: "rename history", apply: create_history },
+    Migration { version: 2, description: "paths stored as raw bytes", apply: paths_as_blobs },
+    Migration { version: 3, description: "rename journal", apply: create_journal },
+    Migration { version: 4, description: "collision strategies", apply: add_collision_columns },
+    Migration { version: 5, description: "undo and redo operations", apply: create_operations },
+    Migration { version: 6, description: "file identities", apply: add_identity_columns },
+    Migration { version: 7, description: "operation details", apply: add_operation_details },
+];
+
+/// The schema version stored in the database (0 for unversioned databases)
+pub fn schema_version(conn: &Connection) -> SqliteResult<u32> {
+    conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+}
+
+/// Bring the database up to `SCHEMA_VERSION`. Returns the descriptions of the
+/// migrations that ran. Databases written by a newer ftmi are refused rather
+/// than modified.
+pub fn migrate(conn: &mut Connection) -> SqliteResult<Vec<&'static str>> {
+    migrate_to(conn, SCHEMA_VERSION)
+}
+
+/// Run the migrations up to and including `target`
+pub fn migrate_to(conn: &mut Connection, target: u32) -> SqliteResult<Vec<&'static str>> {
+    let mut applied = Vec::new();
+    let current = schema_version(conn)?;
+    if current > SCHEMA_VERSION {
+        return Err(newer_schema(current));
+    }
+    
+    for migration in MIGRATIONS.iter().filter(|migration| migration.version > current && migration.version <= target) {
+        // Lock and check again so two processes never run the same step
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let current = schema_version(&tx)?;
+        if current > SCHEMA_VERSION {
+            return Err(newer_schema(current));
+        }
+        if current >= migration.version {
+            continue;
+        }
+        
+        (migration.apply)(&tx)?;
+        tx.pragma_update(None, "user_version", migration.version)?;
+        tx.commit()?;
+        applied.push(migration.description);
+    }
+    
+    Ok(applied)
+}
+
+fn newer_schema(version: u32) -> rusqlite::Error {
+    rusqlite::Error::SqliteFailure(
+        rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_ERROR),
+        Some(format!(
+            "the rename database has schema version {}, but this ftmi only knows up to {}; please upgrade ftmi",
+            version, SCHEMA_VERSION,
+        )),
+    )
+}
+
+fn create_history(tx: &Transaction) -> SqliteResult<()> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS renames (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timestamp TEXT NOT NULL,
+            old_path BLOB NOT NULL,
+            new_path BLOB NOT NULL,
+            directory BLOB NOT NULL,
+            prefix_removed TEXT NOT NULL,
+            operation_id TEXT NOT NULL
+        );
+        CREATE INDEX IF NOT EXISTS idx_operation_id ON renames(operation_id);
+        CREATE INDEX IF NOT EXISTS idx_timestamp ON renames(timestamp);",
+    )
+}
+
+/// Paths used to be stored as lossy TEXT; keep every row comparable as BLOB
+fn paths_as_blobs(tx: &Transaction) -> SqliteResult<()> {
+    tx.execute(
+        "UPDATE renames SET
+            old_path = CAST(old_path AS BLOB),
+            new_path = CAST(new_path AS BLOB),
+            directory = CAST(directory AS BLOB)
+         WHERE typeof(old_path) = 'text' OR typeof(new_path) = 'text' OR typeof(directory) = 'text'",
+        [],
+    )?;
+    Ok(())
+}
+
+/// Write-ahead journal of renames that were started but not yet recorded
+fn create_journal(tx: &Transaction) -> SqliteResult<()> {
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS journal (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            timestamp TEXT NOT NULL,
+            old_path BLOB NOT NULL,
+            new_path BLOB NOT NULL,
+            temp_path BLOB,
+            parked INTEGER NOT NULL DEFAULT 0,
+            directory BLOB NOT NULL,
+            prefix_removed TEXT NOT NULL,
+            operation_id TEXT NOT NULL
+        )",
+        [],
+    )?;
+    Ok(())
+}
+
+fn add_collision_columns(tx: &Transaction) -> SqliteResult<()> {
+    add_column_if_missing(tx, "renames", "collision", "TEXT")?;
+    add_column_if_missing(tx, "journal", "collision", "TEXT")
+}
+
+/// Undo and redo operations and the operation each of them reverts, and the
+/// undo or redo that currently reverts each rename
+fn create_operations(tx: &Transaction) -> SqliteResult<()> {
+    tx.execute(
+        "CREATE TABLE IF NOT EXISTS operations (
+            id TEXT PRIMARY KEY,
+            timestamp TEXT NOT NULL,
+            kind TEXT NOT NULL,
+            reverts TEXT
+        )",
+        [],
+    )?;
+    add_column_if_missing(tx, "renames", "reverted_by", "TEXT")
+}
+
+/// File identities, checked before a rename is undone
+fn add_identity_columns(tx: &Transaction) -> SqliteResult<()> {
+    for table in ["renames", "journal"] {
+        for (column, definition) in [
+            ("file_device", "INTEGER"),
+            ("file_inode", "INTEGER"),
+            ("file_size", "INTEGER"),
+            ("file_modified", "INTEGER"),
+            ("content_hash", "TEXT"),
+        ] {
+            add_column_if_missing(tx, table, column, definition)?;
+        }
+    }
+    Ok(())
+}
+
+/// Who ran each operation, where, with which settings, and its results.
+/// Every operation gets an entry from now on; older renames have none.
+fn add_operation_details(tx: &Transaction) -> SqliteResult<()> {
+    for (column, definition) in [
+        ("command_line", "TEXT"),
+        ("working_directory", "TEXT"),
+        ("user_name", "TEXT"),
+        ("hostname", "TEXT"),
+        ("version", "TEXT"),
+        ("settings", "TEXT"),
+        ("note", "TEXT"),
+        ("tags", "TEXT"),
+        ("duration_ms", "INTEGER"),
+        ("success_count", "INTEGER"),
+        ("failure_count", "INTEGER"),
+    ] {
+        add_column_if_missing(tx, "operations", column, definition)?;
+    }
+    Ok(())
+}
+
+/// Add a column to an existing table unless it is already there
+fn add_column_if_missing(conn: &Connection, table: &str, column: &str, definition: &str) -> SqliteResult<()> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+    let columns = stmt.query_map([], |row| row.get::<_, String>(1))?
+        .collect::<SqliteResult<Vec<String>>>()?;
+    
+    if !columns.iter().any(|name| name == column) {
+        conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition), [])?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{OperationKind, RenameDatabase};
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+    
+    /// Create a database from one of the SQL dumps in `tests/fixtures`
+    fn fixture_database(temp_dir: &TempDir, sql: &str) -> Result<RenameDatabase, Box<dyn std::error::Error>> {
+        let path = temp_dir.path().join("renames.db");
+        Connection::open(&path)?.execute_batch(sql)?;
+        Ok(RenameDatabase::new(path))
+    }
+    
+    fn columns(conn: &Connection, table: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let mut stmt = conn.prepare(&format!("PRAGMA table_info({})", table))?;
+        let columns = stmt.query_map([], |row| row.get::<_, String>(1))?.collect::<SqliteResult<Vec<String>>>()?;
+        Ok(columns)
+    }
+    
+    #[test]
+    fn test_migrations_are_numbered_in_order() {
+        for (index, migration) in MIGRATIONS.iter().enumerate() {
+            assert_eq!(migration.version as usize, index + 1, "{}", migration.description);
+        }
+        assert_eq!(MIGRATIONS.last().map(|migration| migration.version), Some(SCHEMA_VERSION));
+    }
+    
+    #[test]
+    fn test_new_database_gets_current_schema() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let mut conn = Connection::open(temp_dir.path().join("renames.db"))?;
+        assert_eq!(migrate(&mut conn)?.len(), MIGRATIONS.len());
+        assert_eq!(schema_version(&conn)?, SCHEMA_VERSION);
+        
+        // Nothing left to do the second time
+        assert!(migrate(&mut conn)?.is_empty());
+        Ok(())
+    }
+    
+    #[test]
+    fn test_upgrade_first_release() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let db = fixture_database(&temp_dir, include_str!("../tests/fixtures/renames_first_release.sql"))?;
+        db.initialize()?;
+        
+        let conn = Connection::open(temp_dir.path().join("renames.db"))?;
+        assert_eq!(schema_version(&conn)?, SCHEMA_VERSION);
+        let blobs: i64 = conn.query_row("SELECT COUNT(*) FROM renames WHERE typeof(old_path) = 'blob'", [], |row| row.get(0))?;
+        assert_eq!(blobs, 2);
+        assert!(columns(&conn, "renames")?.contains(&"content_hash".to_string()));
+        
+        let records = db.get_operation_renames("op_1700000000")?;
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].old_path, PathBuf::from("/music/[Artist] One.mp3"));
+        assert_eq!(records[0].new_path, PathBuf::from("/music/One.mp3"));
+        assert_eq!(records[0].identity, None);
+        assert_eq!(records[0].prefix_removed, "Artist");
+        assert_eq!(db.operation_kind("op_1700000000")?, (OperationKind::Rename, None));
+        Ok(())
+    }
+    
+    #[test]
+    fn test_upgrade_journal_release() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let db = fixture_database(&temp_dir, include_str!("../tests/fixtures/renames_with_journal.sql"))?;
+        db.initialize()?;
+        
+        let entries = db.journal_entries()?;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].temp_path, Some(PathBuf::from("/music/.ftmi-tmp-0-One.mp3")));
+        assert_eq!(db.get_operation_renames("op_1757889353")?.len(), 1);
+        
+        let conn = Connection::open(temp_dir.path().join("renames.db"))?;
+        assert!(columns(&conn, "journal")?.contains(&"collision".to_string()));
+        assert!(columns(&conn, "journal")?.contains(&"file_inode".to_string()));
+        Ok(())
+    }
+    
+    #[test]
+    fn test_upgrade_undo_release() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let db = fixture_database(&temp_dir, include_str!("../tests/fixtures/renames_with_operations.sql"))?;
+        db.initialize()?;
+        
+        let undo_id = "op_01K55YJ7X8Q3N4V6B2C9D0E1F3";
+        assert_eq!(db.reverted_by("op_01K55YJ7X8Q3N4V6B2C9D0E1F2")?.as_deref(), Some(undo_id));
+        let undo = db.operation(undo_id)?.ok_or("undo operation lost")?;
+        assert_eq!(undo.kind, OperationKind::Undo);
+        assert_eq!(undo.context.command_line, "");
+        assert_eq!(undo.success_count, None);
+        
+        let records = db.get_operation_renames("op_01K55YJ7X8Q3N4V6B2C9D0E1F2")?;
+        assert_eq!(records[0].collision, Some(crate::CollisionStrategy::Number));
+        Ok(())
+    }
+    
+    #[test]
+    fn test_partly_migrated_database_continues() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let mut conn = Connection::open(temp_dir.path().join("renames.db"))?;
+        assert_eq!(migrate_to(&mut conn, 3)?, vec!["rename history", "paths stored as raw bytes", "rename journal"]);
+        assert!(!columns(&conn, "renames")?.contains(&"collision".to_string()));
+        
+        let applied = migrate(&mut conn)?;
+        assert_eq!(applied.first(), Some(&"collision strategies"));
+        assert_eq!(applied.len(), MIGRATIONS.len() - 3);
+        Ok(())
+    }
+    
+    #[test]
+    fn test_newer_schema_is_refused() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let db = RenameDatabase::new(temp_dir.path().join("renames.db"));
+        db.initialize()?;
+        
+        let conn = Connection::open(temp_dir.path().join("renames.db"))?;
+        conn.pragma_update(None, "user_version", SCHEMA_VERSION + 1)?;
+        let error = db.initialize().expect_err("a newer schema must not be touched");
+        assert!(error.to_string().contains("please upgrade ftmi"));
+        assert_eq!(schema_version(&conn)?, SCHEMA_VERSION + 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,91 @@
+use std::borrow::Cow;
+use std::ffi::{OsStr, OsString};
+
+/// Marker appended when a name is not valid UTF-8 and had to be shown lossily
+pub const NON_UTF8_MARKER: &str = "⚠️ (not UTF-8)";
+
+/// Format a file name or path for display.
+/// Names that are not valid UTF-8 are shown lossily followed by `NON_UTF8_MARKER`.
+pub fn display_name(name: &OsStr) -> Cow<'_, str> {
+    match name.to_str() {
+        Some(name) => Cow::Borrowed(name),
+        None => Cow::Owned(format!("{} {}", name.to_string_lossy(), NON_UTF8_MARKER)),
+    }
+}
+
+/// Raw bytes of a name, as stored in the database
+#[cfg(unix)]
+pub fn name_to_bytes(name: &OsStr) -> Cow<'_, [u8]> {
+    use std::os::unix::ffi::OsStrExt;
+    Cow::Borrowed(name.as_bytes())
+}
+
+/// Raw bytes of a name, as stored in the database
+#[cfg(not(unix))]
+pub fn name_to_bytes(name: &OsStr) -> Cow<'_, [u8]> {
+    match name.to_string_lossy() {
+        Cow::Borrowed(name) => Cow::Borrowed(name.as_bytes()),
+        Cow::Owned(name) => Cow::Owned(name.into_bytes()),
+    }
+}
+
+/// Rebuild a name from the bytes produced by `name_to_bytes`
+#[cfg(unix)]
+pub fn name_from_bytes(bytes: Vec<u8>) -> OsString {
+    use std::os::unix::ffi::OsStringExt;
+    OsString::from_vec(bytes)
+}
+
+/// Rebuild a name from the bytes produced by `name_to_bytes`
+#[cfg(not(unix))]
+pub fn name_from_bytes(bytes: Vec<u8>) -> OsString {
+    OsString::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// Serde helpers for paths that may not be valid UTF-8.
+/// Valid paths are written as strings and other paths as arrays of raw bytes.
+pub mod serde_path {
+    use super::{name_from_bytes, name_to_bytes};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::path::{Path, PathBuf};
+    
+    #[derive(Serialize, Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        Text(String),
+        Bytes(Vec<u8>),
+    }
+    
+    pub fn serialize<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+        match path.to_str() {
+            Some(text) => serializer.serialize_str(text),
+            None => name_to_bytes(path.as_os_str()).serialize(serializer),
+        }
+    }
+    
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathBuf, D::Error> {
+        Ok(match Repr::deserialize(deserializer)? {
+            Repr::Text(text) => PathBuf::from(text),
+            Repr::Bytes(bytes) => PathBuf::from(name_from_bytes(bytes)),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_name() {
+        assert_eq!(display_name(OsStr::new("Song.mp3")), "Song.mp3");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_non_utf8_round_trip() {
+        let name = name_from_bytes(b"caf\xe9.mp3".to_vec());
+        assert!(name.to_str().is_none());
+        assert_eq!(name_to_bytes(&name).as_ref(), b"caf\xe9.mp3");
+        assert_eq!(display_name(&name), format!("caf\u{fffd}.mp3 {}", NON_UTF8_MARKER));
+    }
+}
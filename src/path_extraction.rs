@@ -23,7 +23,7 @@ pub fn extract_paths_from_text(text: &str) -> Vec<String> {
 
 pub fn deduplicate_paths(paths: BTreeSet<String>) -> Vec<String> {
     let mut sorted_paths: Vec<String> = paths.into_iter().collect();
-    sorted_paths.sort_by(|a, b| b.len().cmp(&a.len()));
+    sorted_paths.sort_by_key(|path| std::cmp::Reverse(path.len()));
     
     let mut result: Vec<String> = Vec::new();
     
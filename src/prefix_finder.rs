@@ -1,9 +1,19 @@
 use std::fs;
-use std::path::Path;
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
 use std::collections::HashMap;
 use regex::Regex;
+use serde::Serialize;
+use unicode_normalization::UnicodeNormalization as _;
+use unicode_segmentation::UnicodeSegmentation;
+use crate::os_names::{name_from_bytes, name_to_bytes};
+use crate::rename_plan::Affix;
+use crate::suffix_finder::find_common_suffix;
 
-#[derive(Debug, Clone)]
+type DelimitedKey = (String, Option<(String, String)>);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "snake_case")]
 pub enum PrefixMode {
     /// Only search for prefixes within specified delimiters
     DelimiterOnly {
@@ -19,12 +29,61 @@ pub enum PrefixMode {
     },
 }
 
-#[derive(Debug, Clone)]
+/// How a directory tree is grouped when scanning recursively
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TreeGrouping {
+    /// Analyze each subdirectory as its own prefix group
+    PerDirectory,
+    /// Pool every file in the tree into a single analysis
+    Pooled,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Recursion {
+    /// Maximum depth below the starting directory (None means unlimited)
+    pub max_depth: Option<usize>,
+    pub grouping: TreeGrouping,
+}
+
+/// Unicode normalization form applied to file names before comparing them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnicodeNormalization {
+    /// Composed form (common on Linux and Windows)
+    Nfc,
+    /// Decomposed form (used by macOS file systems)
+    Nfd,
+}
+
+impl UnicodeNormalization {
+    /// Parse a form name such as "nfc" or "NFD"
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "nfc" => Some(UnicodeNormalization::Nfc),
+            "nfd" => Some(UnicodeNormalization::Nfd),
+            _ => None,
+        }
+    }
+    
+    pub fn apply(self, text: &str) -> String {
+        match self {
+            UnicodeNormalization::Nfc => text.nfc().collect(),
+            UnicodeNormalization::Nfd => text.nfd().collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct PrefixOptions {
     pub mode: PrefixMode,
     pub min_occurrences: usize,
     /// Regex pattern to filter prefixes (e.g., r"\[.*\]" for bracket-delimited prefixes)
     pub filter_regex: Option<String>,
+    /// Descend into subdirectories (None scans only the given directory)
+    pub recursion: Option<Recursion>,
+    /// Normalize file names so the same text in NFC and NFD counts as one prefix
+    pub normalization: Option<UnicodeNormalization>,
 }
 
 impl Default for PrefixOptions {
@@ -41,6 +100,8 @@ impl Default for PrefixOptions {
             },
             min_occurrences: 2,
             filter_regex: Some(r"\[.*\]".to_string()), // Default to bracket-delimited prefixes
+            recursion: None,
+            normalization: None,
         }
     }
 }
@@ -84,60 +145,111 @@ pub struct CommonPrefix {
     pub prefix: String,
     pub delimiter: Option<(String, String)>,
     pub occurrences: usize,
-    pub files: Vec<String>,
+    pub files: Vec<PathBuf>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct PrefixedPath {
     pub paths: Vec<std::path::PathBuf>,
     pub prefix: String,
+    pub delimiter: Option<(String, String)>,
 }
 
-pub fn find_common_prefix(directory: &Path, options: &PrefixOptions) -> Result<Vec<CommonPrefix>, std::io::Error> {
-    let mut prefix_map: HashMap<String, Vec<String>> = HashMap::new();
-    let mut delimiter_prefix_map: HashMap<(String, Option<(String, String)>), Vec<String>> = HashMap::new();
+impl PrefixedPath {
+    /// The prefix as it appears in file names, including any delimiters
+    pub fn literal_prefix(&self) -> String {
+        match &self.delimiter {
+            Some((open, close)) => format!("{}{}{}", open, self.prefix, close),
+            None => self.prefix.clone(),
+        }
+    }
     
-    // Read all files in the directory
-    for entry in fs::read_dir(directory)? {
-        let entry = entry?;
-        let path = entry.path();
+    /// Remove this prefix from the start of a filename.
+    /// Leading whitespace and underscores left behind are removed, but dashes and
+    /// dots are preserved. Filenames that do not start with the prefix (or would
+    /// become empty) are returned unchanged. If the prefix only matches a
+    /// normalized form of the name, the result is in that form.
+    pub fn remove_from(&self, filename: &str) -> String {
+        let literal = self.literal_prefix();
+        for form in normalized_forms(filename) {
+            if let Some(remaining) = form.strip_prefix(literal.as_str()) {
+                let remaining = remaining.trim_start_matches([' ', '_']);
+                if !remaining.is_empty() {
+                    return remaining.to_string();
+                }
+                break;
+            }
+        }
         
-        if path.is_file() {
-            if let Some(filename) = path.file_name().and_then(|s| s.to_str()) {
-                match &options.mode {
-                    PrefixMode::DelimiterOnly { delimiters } => {
-                        // Only check for prefixes within delimiters
-                        for (open, close) in delimiters {
-                            if let Some(prefix) = extract_prefix_with_delimiter(filename, open, close) {
-                                let key = (prefix.clone(), Some((open.clone(), close.clone())));
-                                delimiter_prefix_map.entry(key).or_insert_with(Vec::new).push(filename.to_string());
-                            }
-                        }
-                    },
-                    PrefixMode::SpecificPrefixes { prefixes } => {
-                        // Only check for specific prefixes
-                        for prefix in prefixes {
-                            if filename.starts_with(prefix) {
-                                prefix_map.entry(prefix.clone()).or_insert_with(Vec::new).push(filename.to_string());
-                            }
-                        }
-                    },
-                    PrefixMode::DetectAll { delimiters } => {
-                        // Check for prefixes within delimiters
-                        for (open, close) in delimiters {
-                            if let Some(prefix) = extract_prefix_with_delimiter(filename, open, close) {
-                                let key = (prefix.clone(), Some((open.clone(), close.clone())));
-                                delimiter_prefix_map.entry(key).or_insert_with(Vec::new).push(filename.to_string());
-                            }
-                        }
-                        
-                        // Also check for common prefixes without delimiters
-                        let prefix_candidates = generate_prefix_candidates(filename);
-                        for prefix in prefix_candidates {
-                            prefix_map.entry(prefix).or_insert_with(Vec::new).push(filename.to_string());
-                        }
+        filename.to_string()
+    }
+    
+    /// Remove this prefix from a file name that may not be valid UTF-8.
+    /// Valid names behave like `remove_from`; other names are matched byte for byte.
+    pub fn remove_from_name(&self, filename: &OsStr) -> OsString {
+        if let Some(filename) = filename.to_str() {
+            return OsString::from(self.remove_from(filename));
+        }
+        
+        let literal = self.literal_prefix();
+        if let Some(remaining) = name_to_bytes(filename).strip_prefix(literal.as_bytes()) {
+            let start = remaining.iter()
+                .position(|b| *b != b' ' && *b != b'_')
+                .unwrap_or(remaining.len());
+            if start < remaining.len() {
+                return name_from_bytes(remaining[start..].to_vec());
+            }
+        }
+        
+        filename.to_os_string()
+    }
+}
+
+/// Find common prefixes among the files of a directory.
+/// With pooled recursion, files from the whole tree are analyzed together and
+/// `CommonPrefix::files` holds paths relative to `directory`.
+/// Names that are not valid UTF-8 are compared lossily, but prefixes containing
+/// replaced bytes are never reported since they cannot be removed reliably.
+pub fn find_common_prefix(directory: &Path, options: &PrefixOptions) -> Result<Vec<CommonPrefix>, std::io::Error> {
+    let mut prefix_map: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    let mut delimiter_prefix_map: HashMap<DelimitedKey, Vec<PathBuf>> = HashMap::new();
+    
+    let entries = scan_files(directory, options)?;
+    
+    for (relative, filename) in &entries {
+        let filename = filename.as_str();
+        match &options.mode {
+            PrefixMode::DelimiterOnly { delimiters } => {
+                // Only check for prefixes within delimiters
+                for (open, close) in delimiters {
+                    if let Some(prefix) = extract_prefix_with_delimiter(filename, open, close) {
+                        let key = (prefix.clone(), Some((open.clone(), close.clone())));
+                        delimiter_prefix_map.entry(key).or_default().push(relative.clone());
+                    }
+                }
+            },
+            PrefixMode::SpecificPrefixes { prefixes } => {
+                // Only check for specific prefixes
+                for prefix in prefixes {
+                    if filename.starts_with(prefix) {
+                        prefix_map.entry(prefix.clone()).or_default().push(relative.clone());
+                    }
+                }
+            },
+            PrefixMode::DetectAll { delimiters } => {
+                // Check for prefixes within delimiters
+                for (open, close) in delimiters {
+                    if let Some(prefix) = extract_prefix_with_delimiter(filename, open, close) {
+                        let key = (prefix.clone(), Some((open.clone(), close.clone())));
+                        delimiter_prefix_map.entry(key).or_default().push(relative.clone());
                     }
                 }
+                
+                // Also check for common prefixes without delimiters
+                let prefix_candidates = generate_prefix_candidates(filename);
+                for prefix in prefix_candidates {
+                    prefix_map.entry(prefix).or_default().push(relative.clone());
+                }
             }
         }
     }
@@ -146,7 +258,7 @@ pub fn find_common_prefix(directory: &Path, options: &PrefixOptions) -> Result<V
     
     // Process delimiter-based prefixes
     for ((prefix, delimiter), files) in delimiter_prefix_map {
-        if files.len() >= options.min_occurrences {
+        if files.len() >= options.min_occurrences && !prefix.contains(char::REPLACEMENT_CHARACTER) {
             results.push(CommonPrefix {
                 prefix,
                 delimiter,
@@ -156,10 +268,13 @@ pub fn find_common_prefix(directory: &Path, options: &PrefixOptions) -> Result<V
         }
     }
     
+    // Keep ties in a stable order regardless of hash map iteration
+    results.sort_by(|a, b| (&a.prefix, &a.delimiter).cmp(&(&b.prefix, &b.delimiter)));
+    
     // Process non-delimiter prefixes
     let mut non_delimiter_results: Vec<CommonPrefix> = Vec::new();
     for (prefix, mut files) in prefix_map {
-        if files.len() >= options.min_occurrences {
+        if files.len() >= options.min_occurrences && !prefix.contains(char::REPLACEMENT_CHARACTER) {
             // Deduplicate files
             files.sort();
             files.dedup();
@@ -194,7 +309,9 @@ pub fn find_common_prefix(directory: &Path, options: &PrefixOptions) -> Result<V
     // Remove redundant prefixes (e.g., if we have "IMG_2024" don't also show "IMG", "IMG_", etc.)
     non_delimiter_results.sort_by(|a, b| {
         // Sort by prefix length (longest first) then by occurrences
-        b.prefix.len().cmp(&a.prefix.len()).then(b.occurrences.cmp(&a.occurrences))
+        b.prefix.len().cmp(&a.prefix.len())
+            .then(b.occurrences.cmp(&a.occurrences))
+            .then_with(|| a.prefix.cmp(&b.prefix))
     });
     
     let mut filtered_results = Vec::new();
@@ -213,11 +330,104 @@ pub fn find_common_prefix(directory: &Path, options: &PrefixOptions) -> Result<V
     results.extend(filtered_results);
     
     // Sort by number of occurrences (descending)
-    results.sort_by(|a, b| b.occurrences.cmp(&a.occurrences));
+    results.sort_by_key(|prefix| std::cmp::Reverse(prefix.occurrences));
     
     Ok(results)
 }
 
+/// List the files to analyze as `(relative path, file name)` pairs.
+/// Only pooled recursion descends into subdirectories. The file name is the
+/// (lossy) text used for matching and is normalized when normalization is
+/// enabled, while the relative path stays exactly as on disk.
+pub(crate) fn scan_files(directory: &Path, options: &PrefixOptions) -> Result<Vec<(PathBuf, String)>, std::io::Error> {
+    let max_depth = match options.recursion {
+        Some(Recursion { grouping: TreeGrouping::Pooled, max_depth }) => max_depth,
+        _ => Some(0),
+    };
+    
+    let mut entries = Vec::new();
+    collect_files(directory, Path::new(""), 0, max_depth, &mut entries)?;
+    
+    if let Some(normalization) = options.normalization {
+        for (_, filename) in &mut entries {
+            *filename = normalization.apply(filename);
+        }
+    }
+    
+    Ok(entries)
+}
+
+/// A file name followed by its NFC and NFD forms, used to match prefixes that
+/// were detected under a different Unicode normalization than the name on disk.
+pub(crate) fn normalized_forms(filename: &str) -> [String; 3] {
+    [
+        filename.to_string(),
+        UnicodeNormalization::Nfc.apply(filename),
+        UnicodeNormalization::Nfd.apply(filename),
+    ]
+}
+
+/// Collect `(relative path, file name)` pairs for files under `directory`,
+/// descending at most `max_depth` levels (None means unlimited).
+/// Symlinked directories are not followed to avoid cycles.
+fn collect_files(
+    directory: &Path,
+    relative: &Path,
+    depth: usize,
+    max_depth: Option<usize>,
+    entries: &mut Vec<(PathBuf, String)>,
+) -> Result<(), std::io::Error> {
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        let path = entry.path();
+        
+        if path.is_file() {
+            let filename = entry.file_name();
+            entries.push((relative.join(&filename), filename.to_string_lossy().into_owned()));
+        } else if entry.file_type()?.is_dir() && max_depth.is_none_or(|max| depth < max) {
+            collect_files(&path, &relative.join(entry.file_name()), depth + 1, max_depth, entries)?;
+        }
+    }
+    
+    Ok(())
+}
+
+/// List the directories that should each be analyzed as a separate group.
+/// Returns just `directory` unless per-directory recursion is enabled, in which
+/// case every subdirectory up to the configured depth is included (sorted).
+pub fn directories_to_analyze(directory: &Path, options: &PrefixOptions) -> Result<Vec<PathBuf>, std::io::Error> {
+    let mut directories = vec![directory.to_path_buf()];
+    
+    if let Some(Recursion { grouping: TreeGrouping::PerDirectory, max_depth }) = options.recursion {
+        collect_subdirectories(directory, 0, max_depth, &mut directories)?;
+        directories[1..].sort();
+    }
+    
+    Ok(directories)
+}
+
+fn collect_subdirectories(
+    directory: &Path,
+    depth: usize,
+    max_depth: Option<usize>,
+    directories: &mut Vec<PathBuf>,
+) -> Result<(), std::io::Error> {
+    if max_depth.is_some_and(|max| depth >= max) {
+        return Ok(());
+    }
+    
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            let path = entry.path();
+            directories.push(path.clone());
+            collect_subdirectories(&path, depth + 1, max_depth, directories)?;
+        }
+    }
+    
+    Ok(())
+}
+
 fn extract_prefix_with_delimiter(filename: &str, open: &str, close: &str) -> Option<String> {
     if let Some(open_pos) = filename.find(open) {
         if let Some(close_pos) = filename[open_pos + open.len()..].find(close) {
@@ -260,10 +470,12 @@ fn generate_prefix_candidates(filename: &str) -> Vec<String> {
         }
     }
     
-    // Also try character-based prefixes (first n characters)
+    // Also try character-based prefixes (first n grapheme clusters, so multi-byte
+    // characters and combining marks are never split)
     // Skip single character prefixes to avoid noise
-    for i in 2..name.len().min(20) {
-        let candidate = &name[..i];
+    let boundaries: Vec<usize> = name.grapheme_indices(true).map(|(i, _)| i).collect();
+    for &end in boundaries.iter().take(20).skip(2) {
+        let candidate = &name[..end];
         // Skip if it looks like a partial delimiter match
         if candidate.ends_with('[') || candidate.ends_with('(') || candidate.ends_with('{') {
             continue;
@@ -320,8 +532,8 @@ pub fn extract_prefix_from_filename(filename: &str, options: &PrefixOptions) ->
 
 /// Remove prefix from a filename
 pub fn remove_prefix(filename: &str, prefix: &str) -> String {
-    if filename.starts_with(prefix) {
-        filename[prefix.len()..].trim_start().to_string()
+    if let Some(remaining) = filename.strip_prefix(prefix) {
+        remaining.trim_start().to_string()
     } else {
         filename.to_string()
     }
@@ -341,71 +553,84 @@ pub fn remove_prefix_with_delimiter(filename: &str, prefix: &str, open: &str, cl
 /// Uses configurable regex pattern to filter prefixes 
 /// Returns multiple results if there are ties in occurrence count
 pub fn find_longest_prefix(directory: &Path, options: &PrefixOptions) -> Result<Vec<PrefixedPath>, std::io::Error> {
-    let all_prefixes = find_common_prefix(directory, options)?;
-    
-    if all_prefixes.is_empty() {
-        return Ok(Vec::new());
-    }
-    
-    // Filter prefixes using regex pattern if provided
-    let filtered_prefixes: Vec<&CommonPrefix> = if let Some(regex_pattern) = &options.filter_regex {
-        match Regex::new(regex_pattern) {
-            Ok(regex) => {
-                all_prefixes.iter()
-                    .filter(|prefix| {
-                        // Create the full prefix pattern based on delimiter
-                        let full_prefix = if let Some((open, close)) = &prefix.delimiter {
-                            format!("{}{}{}", open, prefix.prefix, close)
-                        } else {
-                            prefix.prefix.clone()
-                        };
-                        regex.is_match(&full_prefix)
-                    })
-                    .collect()
-            }
-            Err(e) => {
-                eprintln!("Warning: Invalid regex pattern '{}': {}", regex_pattern, e);
-                all_prefixes.iter().collect()
-            }
-        }
-    } else {
-        all_prefixes.iter().collect()
+    let results = find_longest_affix(directory, options, Affix::Prefix)?
+        .into_iter()
+        .map(|group| PrefixedPath {
+            paths: group.files.iter().map(|filename| directory.join(filename)).collect(),
+            prefix: group.affix,
+            delimiter: group.delimiter,
+        })
+        .collect();
+    
+    Ok(results)
+}
+
+/// A common prefix or suffix as compared by `find_longest_affix`
+pub(crate) struct AffixGroup {
+    pub(crate) affix: String,
+    pub(crate) delimiter: Option<(String, String)>,
+    pub(crate) occurrences: usize,
+    pub(crate) files: Vec<PathBuf>,
+}
+
+/// The most common prefixes or suffixes (`kind`) of a directory.
+/// The regex filter is applied to the full affix including its delimiters;
+/// when nothing matches, every affix is considered. Ties in occurrence count
+/// return multiple groups.
+pub(crate) fn find_longest_affix(directory: &Path, options: &PrefixOptions, kind: Affix) -> Result<Vec<AffixGroup>, std::io::Error> {
+    let all_groups: Vec<AffixGroup> = match kind {
+        Affix::Prefix => find_common_prefix(directory, options)?
+            .into_iter()
+            .map(|prefix| AffixGroup {
+                affix: prefix.prefix,
+                delimiter: prefix.delimiter,
+                occurrences: prefix.occurrences,
+                files: prefix.files,
+            })
+            .collect(),
+        Affix::Suffix => find_common_suffix(directory, options)?
+            .into_iter()
+            .map(|suffix| AffixGroup {
+                affix: suffix.suffix,
+                delimiter: suffix.delimiter,
+                occurrences: suffix.occurrences,
+                files: suffix.files,
+            })
+            .collect(),
     };
     
-    let candidates = if !filtered_prefixes.is_empty() {
-        filtered_prefixes
-    } else {
-        // Fall back to any prefix if no filtered prefixes found
-        all_prefixes.iter().collect()
+    // Filter using the regex pattern if provided
+    let regex = match &options.filter_regex {
+        Some(pattern) => match Regex::new(pattern) {
+            Ok(regex) => Some(regex),
+            Err(e) => {
+                eprintln!("Warning: Invalid regex pattern '{}': {}", pattern, e);
+                None
+            }
+        },
+        None => None,
     };
+    let matches = |group: &AffixGroup| regex.as_ref().is_none_or(|regex| {
+        // Create the full affix based on the delimiter
+        let full_affix = match &group.delimiter {
+            Some((open, close)) => format!("{}{}{}", open, group.affix, close),
+            None => group.affix.clone(),
+        };
+        regex.is_match(&full_affix)
+    });
+    let (mut candidates, others): (Vec<AffixGroup>, Vec<AffixGroup>) = all_groups.into_iter().partition(matches);
+    if candidates.is_empty() {
+        // Fall back to any affix if no filtered affixes found
+        candidates = others;
+    }
     
-    // Find the maximum occurrence count
+    // Collect all affixes with the maximum occurrence count
     let max_occurrences = candidates.iter()
-        .map(|prefix| prefix.occurrences)
+        .map(|group| group.occurrences)
         .max()
         .unwrap_or(0);
     
-    // Collect all prefixes with the maximum occurrence count
-    let best_prefixes: Vec<&CommonPrefix> = candidates.iter()
-        .filter(|prefix| prefix.occurrences == max_occurrences)
-        .cloned()
-        .collect();
-    
-    // Convert to PrefixedPath results
-    let results: Vec<PrefixedPath> = best_prefixes.iter()
-        .map(|prefix| {
-            let paths: Vec<std::path::PathBuf> = prefix.files.iter()
-                .map(|filename| directory.join(filename))
-                .collect();
-            
-            PrefixedPath {
-                paths,
-                prefix: prefix.prefix.clone(),
-            }
-        })
-        .collect();
-    
-    Ok(results)
+    Ok(candidates.into_iter().filter(|group| group.occurrences == max_occurrences).collect())
 }
 
 #[cfg(test)]
@@ -440,6 +665,82 @@ mod tests {
         assert!(candidates.contains(&"prefix".to_string()));
     }
 
+    #[test]
+    fn test_generate_prefix_candidates_unicode() {
+        // Byte-indexed slicing used to panic inside multi-byte characters
+        let candidates = generate_prefix_candidates("東京事変 - 群青日和.mp3");
+        assert!(candidates.contains(&"東京".to_string()));
+        assert!(candidates.contains(&"東京事変".to_string()));
+        
+        let candidates = generate_prefix_candidates("🎵🎶 Playlist.m4a");
+        assert!(candidates.contains(&"🎵🎶".to_string()));
+        
+        // Combining marks stay attached to their base character
+        let candidates = generate_prefix_candidates("Be\u{301}la Fleck.flac");
+        assert!(candidates.contains(&"Be\u{301}".to_string()));
+        assert!(!candidates.contains(&"Be".to_string()));
+    }
+
+    #[test]
+    fn test_find_common_prefix_normalization() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let dir_path = temp_dir.path();
+        
+        // The same artist written in NFC and NFD
+        File::create(dir_path.join("[Beyonc\u{e9}] Halo.mp3"))?;
+        File::create(dir_path.join("[Beyonce\u{301}] Crazy in Love.mp3"))?;
+        
+        // Without normalization the two spellings are different artists
+        let results = find_common_prefix(dir_path, &PrefixOptions::default())?;
+        assert!(!results.iter().any(|cp| cp.delimiter.is_some() && cp.occurrences == 2));
+        
+        let options = PrefixOptions {
+            normalization: Some(UnicodeNormalization::Nfc),
+            ..PrefixOptions::default()
+        };
+        let results = find_longest_prefix(dir_path, &options)?;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].prefix, "Beyonc\u{e9}");
+        assert_eq!(results[0].paths.len(), 2);
+        
+        // Both spellings have the NFC prefix removed
+        for path in &results[0].paths {
+            let filename = path.file_name().and_then(|s| s.to_str()).unwrap();
+            assert!(!results[0].remove_from(filename).contains("Beyonc"));
+        }
+        
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_find_common_prefix_non_utf8() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let dir_path = temp_dir.path();
+        
+        // Latin-1 encoded names are not valid UTF-8
+        let latin1 = name_from_bytes(b"[Artist] Caf\xe9 del Mar.mp3".to_vec());
+        File::create(dir_path.join(&latin1))?;
+        File::create(dir_path.join("[Artist] Song.mp3"))?;
+        File::create(dir_path.join(name_from_bytes(b"\xe9t\xe9 1.mp3".to_vec())))?;
+        File::create(dir_path.join(name_from_bytes(b"\xe9t\xe9 2.mp3".to_vec())))?;
+        
+        let results = find_longest_prefix(dir_path, &PrefixOptions::default())?;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].literal_prefix(), "[Artist]");
+        assert!(results[0].paths.contains(&dir_path.join(&latin1)));
+        assert_eq!(
+            results[0].remove_from_name(&latin1),
+            name_from_bytes(b"Caf\xe9 del Mar.mp3".to_vec())
+        );
+        
+        // Prefixes made of undecodable bytes are not reported
+        let results = find_common_prefix(dir_path, &PrefixOptions::no_filter())?;
+        assert!(results.iter().all(|cp| !cp.prefix.contains(char::REPLACEMENT_CHARACTER)));
+        
+        Ok(())
+    }
+
     #[test]
     fn test_find_common_prefix_delimiter_only() -> Result<(), Box<dyn std::error::Error>> {
         let temp_dir = TempDir::new()?;
@@ -459,6 +760,8 @@ mod tests {
             },
             min_occurrences: 2,
             filter_regex: Some(r"\[.*\]".to_string()),
+            recursion: None,
+            normalization: None,
         };
         let results = find_common_prefix(dir_path, &options)?;
         
@@ -489,6 +792,8 @@ mod tests {
             },
             min_occurrences: 1,
             filter_regex: None,
+            recursion: None,
+            normalization: None,
         };
         let results = find_common_prefix(dir_path, &options)?;
         
@@ -525,6 +830,73 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_find_common_prefix_pooled_recursion() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let dir_path = temp_dir.path();
+        
+        // Files with the same artist spread over two album folders
+        std::fs::create_dir_all(dir_path.join("album1"))?;
+        std::fs::create_dir_all(dir_path.join("album2/disc1"))?;
+        File::create(dir_path.join("album1/[Queen] Track1.mp3"))?;
+        File::create(dir_path.join("album2/disc1/[Queen] Track2.mp3"))?;
+        File::create(dir_path.join("[Queen] Track3.mp3"))?;
+        
+        // Without recursion only the top-level file is seen
+        let options = PrefixOptions::default();
+        let results = find_common_prefix(dir_path, &options)?;
+        assert!(results.iter().all(|cp| cp.prefix != "Queen"));
+        
+        let options = PrefixOptions {
+            recursion: Some(Recursion { max_depth: None, grouping: TreeGrouping::Pooled }),
+            ..PrefixOptions::default()
+        };
+        let results = find_longest_prefix(dir_path, &options)?;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].prefix, "Queen");
+        assert_eq!(results[0].paths.len(), 3);
+        assert!(results[0].paths.contains(&dir_path.join("album2/disc1/[Queen] Track2.mp3")));
+        
+        // Depth 1 stops before album2/disc1
+        let options = PrefixOptions {
+            recursion: Some(Recursion { max_depth: Some(1), grouping: TreeGrouping::Pooled }),
+            ..PrefixOptions::default()
+        };
+        let results = find_common_prefix(dir_path, &options)?;
+        let queen = results.iter().find(|cp| cp.prefix == "Queen").unwrap();
+        assert_eq!(queen.occurrences, 2);
+        
+        Ok(())
+    }
+
+    #[test]
+    fn test_directories_to_analyze() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let dir_path = temp_dir.path();
+        std::fs::create_dir_all(dir_path.join("b/nested"))?;
+        std::fs::create_dir_all(dir_path.join("a"))?;
+        
+        let options = PrefixOptions::default();
+        assert_eq!(directories_to_analyze(dir_path, &options)?, vec![dir_path.to_path_buf()]);
+        
+        let options = PrefixOptions {
+            recursion: Some(Recursion { max_depth: None, grouping: TreeGrouping::PerDirectory }),
+            ..PrefixOptions::default()
+        };
+        assert_eq!(
+            directories_to_analyze(dir_path, &options)?,
+            vec![dir_path.to_path_buf(), dir_path.join("a"), dir_path.join("b"), dir_path.join("b/nested")]
+        );
+        
+        let options = PrefixOptions {
+            recursion: Some(Recursion { max_depth: Some(1), grouping: TreeGrouping::PerDirectory }),
+            ..PrefixOptions::default()
+        };
+        assert_eq!(directories_to_analyze(dir_path, &options)?.len(), 3);
+        
+        Ok(())
+    }
+
     #[test]
     fn test_extract_prefix_from_filename() {
         let options = PrefixOptions {
@@ -533,6 +905,8 @@ mod tests {
             },
             min_occurrences: 1,
             filter_regex: Some(r"\[.*\]".to_string()),
+            recursion: None,
+            normalization: None,
         };
         
         let result = extract_prefix_from_filename("[Artist] Song.mp3", &options);
@@ -550,6 +924,8 @@ mod tests {
             },
             min_occurrences: 1,
             filter_regex: None,
+            recursion: None,
+            normalization: None,
         };
         
         let result = extract_prefix_from_filename("IMG_001.jpg", &options);
@@ -569,6 +945,51 @@ mod tests {
         assert_eq!(remove_prefix("no_match.txt", "IMG_"), "no_match.txt");
     }
 
+    #[test]
+    fn test_prefixed_path_remove_from() {
+        let prefixed = |prefix: &str, delimiter: Option<(&str, &str)>| PrefixedPath {
+            paths: Vec::new(),
+            prefix: prefix.to_string(),
+            delimiter: delimiter.map(|(open, close)| (open.to_string(), close.to_string())),
+        };
+        
+        let brackets = prefixed("Artist", Some(("[", "]")));
+        assert_eq!(brackets.literal_prefix(), "[Artist]");
+        assert_eq!(brackets.remove_from("[Artist] Song.mp3"), "Song.mp3");
+        assert_eq!(brackets.remove_from("[Artist]_Song.mp3"), "Song.mp3");
+        assert_eq!(brackets.remove_from("[Artist] - Song.mp3"), "- Song.mp3");
+        assert_eq!(brackets.remove_from("No Prefix Song.mp3"), "No Prefix Song.mp3");
+        assert_eq!(brackets.remove_from("Song [Artist].mp3"), "Song [Artist].mp3");
+        assert_eq!(
+            prefixed("The Beatles", Some(("[", "]"))).remove_from("[The Beatles] Hey Jude.mp3"),
+            "Hey Jude.mp3"
+        );
+        
+        assert_eq!(prefixed("Draft", Some(("(", ")"))).remove_from("(Draft) Report.pdf"), "Report.pdf");
+        assert_eq!(prefixed("WIP", Some(("{", "}"))).remove_from("{WIP}_notes.txt"), "notes.txt");
+        assert_eq!(prefixed("Live", Some(("\"", "\""))).remove_from("\"Live\" Encore.mp3"), "Encore.mp3");
+        assert_eq!(prefixed("IMG", None).remove_from("IMG_001.jpg"), "001.jpg");
+        
+        // Never strip a name down to nothing
+        assert_eq!(brackets.remove_from("[Artist]"), "[Artist]");
+    }
+
+    #[test]
+    fn test_find_longest_prefix_keeps_delimiter() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let dir_path = temp_dir.path();
+        
+        File::create(dir_path.join("(Draft) report.pdf"))?;
+        File::create(dir_path.join("(Draft) summary.pdf"))?;
+        
+        let results = find_longest_prefix(dir_path, &PrefixOptions::paren_only())?;
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].delimiter, Some(("(".to_string(), ")".to_string())));
+        assert_eq!(results[0].literal_prefix(), "(Draft)");
+        
+        Ok(())
+    }
+
     #[test]
     fn test_remove_prefix_with_delimiter() {
         assert_eq!(
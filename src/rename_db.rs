@@ -1,18 +1,363 @@
-use rusqlite::{Connection, Result as SqliteResult, params};
-use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OptionalExtension, Result as SqliteResult, params};
+use rusqlite::types::ValueRef;
+use chrono::{DateTime, NaiveDate, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::OsStr;
 use std::path::{Path, PathBuf};
 use std::fs;
+use std::time::{Duration, Instant};
+use crate::file_identity::{FileIdentity, IdentityMismatch};
+use crate::history_export::{HistoryExport, ImportSummary};
+use crate::migrations;
+use crate::os_names::{display_name, name_from_bytes, name_to_bytes, serde_path};
+use crate::rename_edit::is_valid_name;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct RenameRecord {
     pub id: i64,
     pub timestamp: DateTime<Utc>,
+    #[serde(with = "serde_path")]
     pub old_path: PathBuf,
+    #[serde(with = "serde_path")]
     pub new_path: PathBuf,
+    #[serde(with = "serde_path")]
     pub directory: PathBuf,
     pub prefix_removed: String,
     pub operation_id: String, // Groups related renames together
+    /// How a taken target name was resolved, if it was
+    pub collision: Option<CollisionStrategy>,
+    /// The undo or redo operation that currently reverts this rename
+    pub reverted_by: Option<String>,
+    /// What the file looked like when it was renamed; `None` for records
+    /// written by older versions
+    pub identity: Option<FileIdentity>,
+}
+
+/// A rename that was started but is not yet recorded in the history.
+/// Entries are written before a file is renamed and removed in the same
+/// transaction that records the finished rename, so anything left in the
+/// journal was interrupted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JournalEntry {
+    pub id: i64,
+    pub timestamp: DateTime<Utc>,
+    pub old_path: PathBuf,
+    pub new_path: PathBuf,
+    /// Temporary name the file is parked under during a batch rename
+    pub temp_path: Option<PathBuf>,
+    /// Whether the file was moved to `temp_path`
+    pub parked: bool,
+    pub directory: PathBuf,
+    pub prefix_removed: String,
+    pub operation_id: String,
+}
+
+/// How `RenameDatabase::recover` reconciled an unfinished journal entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recovery {
+    /// The file had been renamed; the rename is now in the history
+    Recorded,
+    /// The file was moved back from its temporary name
+    Restored,
+    /// The file was never renamed
+    NotStarted,
+    /// Neither the old nor the new name exists any more
+    Missing,
+    /// The file is still parked under its temporary name because both the old
+    /// and the new name are taken; the entry is kept
+    Stranded,
+}
+
+/// What happened to a single file during a rename or undo
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RenameOutcome {
+    Renamed,
+    /// The new name equals the old name, nothing to do
+    Unchanged,
+    /// The file to rename no longer exists
+    SourceMissing,
+    /// Another file already has the target name
+    TargetExists,
+    /// Another rename of the same batch has the same target
+    DuplicateTarget,
+    /// The target was taken and the rename was skipped (`CollisionStrategy::Skip`)
+    Skipped,
+    /// The file at the current name is not the one that was renamed, so it
+    /// was not renamed back (see `UndoOptions::force`)
+    IdentityMismatch(IdentityMismatch),
+    /// The rename itself failed
+    Failed(String),
+}
+
+/// How a rename whose target name is already taken is resolved
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CollisionStrategy {
+    /// Leave the file alone
+    Skip,
+    /// Add a number: `Intro (2).mp3`
+    Number,
+    /// Keep the removed text as a suffix: `Intro - A.mp3`
+    Suffix,
+    /// Move the file into a subfolder named after the removed text: `A/Intro.mp3`
+    Subfolder,
+    /// Rename the existing file to `Intro.mp3.bak` and take its name
+    Backup,
+}
+
+impl CollisionStrategy {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "skip" => Some(CollisionStrategy::Skip),
+            "number" => Some(CollisionStrategy::Number),
+            "suffix" => Some(CollisionStrategy::Suffix),
+            "subfolder" => Some(CollisionStrategy::Subfolder),
+            "backup" => Some(CollisionStrategy::Backup),
+            _ => None,
+        }
+    }
+    
+    pub fn name(self) -> &'static str {
+        match self {
+            CollisionStrategy::Skip => "skip",
+            CollisionStrategy::Number => "number",
+            CollisionStrategy::Suffix => "suffix",
+            CollisionStrategy::Subfolder => "subfolder",
+            CollisionStrategy::Backup => "backup",
+        }
+    }
+}
+
+/// What an operation did. Undo and redo are recorded as operations of their
+/// own that link back to the operation they revert.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OperationKind {
+    Rename,
+    /// Restored the files of an earlier operation
+    Undo,
+    /// Reapplied an operation by reverting its undo
+    Redo,
+}
+
+impl OperationKind {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "rename" => Some(OperationKind::Rename),
+            "undo" => Some(OperationKind::Undo),
+            "redo" => Some(OperationKind::Redo),
+            _ => None,
+        }
+    }
+    
+    pub fn name(self) -> &'static str {
+        match self {
+            OperationKind::Rename => "rename",
+            OperationKind::Undo => "undo",
+            OperationKind::Redo => "redo",
+        }
+    }
+}
+
+/// Which renames of an operation an undo or redo reverts, and how
+#[derive(Debug, Clone, Copy, Default)]
+pub struct UndoOptions<'a> {
+    /// Only the renames with these record IDs; all of them when `None`
+    pub only: Option<&'a [i64]>,
+    /// Restore files that later operations renamed again (see
+    /// `RenameDatabase::later_renames`) from where they are now, and mark
+    /// those later renames as undone as well
+    pub follow: bool,
+    /// Rename files back even when they no longer match the identity recorded
+    /// with the rename
+    pub force: bool,
+    /// Recorded with the undo or redo; `OperationContext::current()` when `None`
+    pub context: Option<&'a OperationContext>,
+}
+
+/// Who ran an operation, where, and with which settings
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OperationContext {
+    /// The command line, arguments separated by spaces
+    pub command_line: String,
+    pub working_directory: Option<String>,
+    pub user: Option<String>,
+    pub hostname: Option<String>,
+    /// Version of ftmi that ran the operation
+    pub version: String,
+    /// The settings that decided the new names (regex, `PrefixOptions`, ...) as JSON
+    pub settings: Option<String>,
+    /// Free-form note (`--note`)
+    pub note: Option<String>,
+    /// Tags (`--tag`)
+    pub tags: Vec<String>,
+}
+
+impl OperationContext {
+    /// The context of this process: its command line, working directory,
+    /// user, host and ftmi version. Settings, note and tags are left empty.
+    pub fn current() -> Self {
+        let non_empty = |value: String| Some(value.trim().to_string()).filter(|value| !value.is_empty());
+        let user = ["USER", "USERNAME", "LOGNAME"].iter()
+            .find_map(|name| std::env::var(name).ok().and_then(non_empty));
+        let hostname = ["HOSTNAME", "COMPUTERNAME"].iter()
+            .find_map(|name| std::env::var(name).ok().and_then(non_empty))
+            .or_else(|| fs::read_to_string("/etc/hostname").ok().and_then(non_empty));
+        
+        OperationContext {
+            command_line: std::env::args().collect::<Vec<String>>().join(" "),
+            working_directory: std::env::current_dir().ok().map(|dir| display_name(dir.as_os_str()).into_owned()),
+            user,
+            hostname,
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            settings: None,
+            note: None,
+            tags: Vec::new(),
+        }
+    }
+}
+
+/// An operation as recorded in the `operations` table
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OperationRecord {
+    pub id: String,
+    /// When the operation started
+    pub timestamp: DateTime<Utc>,
+    pub kind: OperationKind,
+    /// The operation an undo or redo reverts
+    pub reverts: Option<String>,
+    pub context: OperationContext,
+    /// How long the renames took; `None` until the operation finished
+    pub duration_ms: Option<i64>,
+    pub success_count: Option<i64>,
+    pub failure_count: Option<i64>,
+}
+
+/// Which operations `RenameDatabase::query_operations` returns. An operation
+/// matches when one of its renames passes every rename filter and it carries
+/// every tag.
+#[derive(Debug, Clone, Default)]
+pub struct HistoryQuery {
+    /// Renames made at or after this time
+    pub since: Option<DateTime<Utc>>,
+    /// Renames made before this time
+    pub until: Option<DateTime<Utc>>,
+    /// Renames in this directory or below it
+    pub directory: Option<PathBuf>,
+    /// Renames whose removed prefix contains this text
+    pub prefix: Option<String>,
+    /// Renames whose old or new file name matches this glob
+    pub name: Option<glob::Pattern>,
+    pub tags: Vec<String>,
+    /// Matching operations to leave out, most recent first
+    pub offset: usize,
+    /// At most this many operations; all of them when `None`
+    pub limit: Option<usize>,
+}
+
+impl HistoryQuery {
+    /// Whether `record` passes the rename filters
+    pub fn matches_rename(&self, record: &RenameRecord) -> bool {
+        let name_matches = |pattern: &glob::Pattern, path: &Path| {
+            path.file_name().is_some_and(|name| pattern.matches(&name.to_string_lossy()))
+        };
+        
+        self.since.is_none_or(|since| record.timestamp >= since)
+            && self.until.is_none_or(|until| record.timestamp < until)
+            && self.directory.as_ref().is_none_or(|directory| record.directory.starts_with(directory))
+            && self.prefix.as_ref().is_none_or(|prefix| record.prefix_removed.contains(prefix.as_str()))
+            && self.name.as_ref().is_none_or(|pattern| name_matches(pattern, &record.old_path) || name_matches(pattern, &record.new_path))
+    }
+}
+
+/// Which operations `RenameDatabase::prune` removes: those matching every
+/// criterion given (all operations when none is)
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PruneOptions {
+    /// Operations started before this time
+    pub older_than: Option<DateTime<Utc>>,
+    /// All but this many most recent operations
+    pub keep_last: Option<usize>,
+    /// Also remove operations with renames still in effect, which could
+    /// otherwise be undone (or, for an undo, redone)
+    pub force: bool,
+    /// Only report what would be removed
+    pub dry_run: bool,
+}
+
+/// What `RenameDatabase::prune` removed, or would remove
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PruneSummary {
+    /// Oldest first
+    pub operations: Vec<String>,
+    pub renames: usize,
+    /// Matching operations left alone because they can still be undone
+    pub kept_undoable: Vec<String>,
+}
+
+/// Totals over a whole history, see `RenameDatabase::stats`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HistoryStats {
+    pub rename_operations: usize,
+    pub undo_operations: usize,
+    pub redo_operations: usize,
+    /// Files renamed by rename operations, not counting undo and redo
+    pub files_renamed: usize,
+    /// Rename operations that were undone, even if they were redone since
+    pub undone_operations: usize,
+    /// Rename operations started on each day (UTC), oldest first
+    pub operations_per_day: Vec<(NaiveDate, usize)>,
+    /// Removed prefixes with the number of files, most files first
+    pub prefixes: Vec<(String, usize)>,
+    pub first: Option<DateTime<Utc>>,
+    pub last: Option<DateTime<Utc>>,
+}
+
+impl HistoryStats {
+    /// Share of rename operations that were undone, from 0 to 1
+    pub fn undo_rate(&self) -> f64 {
+        if self.rename_operations == 0 {
+            0.0
+        } else {
+            self.undone_operations as f64 / self.rename_operations as f64
+        }
+    }
+}
+
+/// An operation with the counts `prune` and `stats` need
+struct OperationSummary {
+    id: String,
+    started: DateTime<Utc>,
+    kind: OperationKind,
+    renames: usize,
+    /// Renames reverted by an undo or redo
+    reverted: usize,
+    /// Whether the operation renamed at least one file
+    succeeded: bool,
+}
+
+/// Directory holding a history database, in the home directory or at the
+/// root of a project
+pub const DATABASE_DIR: &str = ".ftmi";
+
+/// File name of the history database
+pub const DATABASE_FILE: &str = "renames.db";
+
+/// Environment variable naming the history database to use
+pub const DATABASE_ENV: &str = "FTMI_DB";
+
+/// Why a history database was chosen (see `RenameDatabase::locate`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DatabaseSource {
+    /// Given with `--db PATH`
+    Flag,
+    /// Named by the `FTMI_DB` environment variable
+    Environment,
+    /// A `.ftmi` directory in the target directory or one of its parents
+    Project,
+    /// The user's own history (`RenameDatabase::default_path`)
+    User,
 }
 
 #[derive(Debug, Clone)]
@@ -26,53 +371,88 @@ impl RenameDatabase {
         Self { db_path }
     }
     
-    /// Get default database path (in user's home/.ftmi/renames.db)
+    /// Path of the database file
+    pub fn path(&self) -> &Path {
+        &self.db_path
+    }
+    
+    /// Get the path of the user's own history. Older versions always used
+    /// `~/.ftmi/renames.db`, which is kept when it exists. Otherwise the
+    /// history goes to the XDG data directory (`$XDG_DATA_HOME/ftmi`, on Linux
+    /// and other Unix systems `~/.local/share/ftmi` by default) or to
+    /// `~/.ftmi`. The directory is created if needed.
     pub fn default_path() -> SqliteResult<PathBuf> {
-        let home = std::env::var("HOME")
-            .or_else(|_| std::env::var("USERPROFILE"))
-            .map_err(|_| rusqlite::Error::InvalidPath("Could not find home directory".into()))?;
+        let home = home_dir()
+            .ok_or_else(|| rusqlite::Error::InvalidPath("Could not find home directory".into()))?;
         
-        let ftmi_dir = PathBuf::from(home).join(".ftmi");
-        if !ftmi_dir.exists() {
-            fs::create_dir_all(&ftmi_dir)
-                .map_err(|e| rusqlite::Error::InvalidPath(format!("Could not create .ftmi directory: {}", e).into()))?;
-        }
+        let legacy = home.join(DATABASE_DIR).join(DATABASE_FILE);
+        let data_home = std::env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute());
+        let path = if legacy.exists() {
+            legacy
+        } else if let Some(data_home) = data_home {
+            data_home.join("ftmi").join(DATABASE_FILE)
+        } else if cfg!(all(unix, not(target_os = "macos"))) {
+            home.join(".local").join("share").join("ftmi").join(DATABASE_FILE)
+        } else {
+            legacy
+        };
         
-        Ok(ftmi_dir.join("renames.db"))
+        create_parent_dir(&path)?;
+        Ok(path)
     }
     
-    /// Initialize the database with required tables
-    pub fn initialize(&self) -> SqliteResult<()> {
-        let conn = Connection::open(&self.db_path)?;
-        
-        conn.execute(
-            "CREATE TABLE IF NOT EXISTS renames (
-                id INTEGER PRIMARY KEY AUTOINCREMENT,
-                timestamp TEXT NOT NULL,
-                old_path TEXT NOT NULL,
-                new_path TEXT NOT NULL,
-                directory TEXT NOT NULL,
-                prefix_removed TEXT NOT NULL,
-                operation_id TEXT NOT NULL
-            )",
-            [],
-        )?;
-        
-        // Create index for faster operation_id lookups
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_operation_id ON renames(operation_id)",
-            [],
-        )?;
+    /// Choose the history database for work on `start` (a file or directory):
+    /// `explicit` (`--db`) if given, else the `FTMI_DB` environment variable,
+    /// else the project history in the closest `.ftmi` directory at or above
+    /// `start`, else the user's history. The directory of the database is
+    /// created if needed.
+    pub fn locate(explicit: Option<&Path>, start: &Path) -> SqliteResult<(PathBuf, DatabaseSource)> {
+        let from_env = std::env::var_os(DATABASE_ENV).filter(|value| !value.is_empty());
+        let (path, source) = if let Some(path) = explicit {
+            (path.to_path_buf(), DatabaseSource::Flag)
+        } else if let Some(path) = from_env {
+            (PathBuf::from(path), DatabaseSource::Environment)
+        } else if let Some(path) = Self::project_path(start) {
+            (path, DatabaseSource::Project)
+        } else {
+            (Self::default_path()?, DatabaseSource::User)
+        };
         
-        // Create index for faster timestamp lookups
-        conn.execute(
-            "CREATE INDEX IF NOT EXISTS idx_timestamp ON renames(timestamp)",
-            [],
-        )?;
+        create_parent_dir(&path)?;
+        Ok((path, source))
+    }
+    
+    /// The project history for `start`: `.ftmi/renames.db` in the closest
+    /// directory at or above `start` that has a `.ftmi` directory. The one in
+    /// the home directory belongs to the user's history and is skipped.
+    pub fn project_path(start: &Path) -> Option<PathBuf> {
+        let start = fs::canonicalize(start).ok()?;
+        let home = home_dir().and_then(|home| fs::canonicalize(home).ok());
         
+        start.ancestors()
+            .filter(|dir| Some(*dir) != home.as_deref())
+            .map(|dir| dir.join(DATABASE_DIR))
+            .find(|dir| dir.is_dir())
+            .map(|dir| dir.join(DATABASE_FILE))
+    }
+    
+    /// Create the database or bring an existing one up to the current schema
+    /// (see `migrations`). Fails without touching the file when it was written
+    /// by a newer ftmi.
+    pub fn initialize(&self) -> SqliteResult<()> {
+        let mut conn = Connection::open(&self.db_path)?;
+        migrations::migrate(&mut conn)?;
         Ok(())
     }
     
+    /// The schema version of the database file
+    pub fn schema_version(&self) -> SqliteResult<u32> {
+        let conn = Connection::open(&self.db_path)?;
+        migrations::schema_version(&conn)
+    }
+    
     /// Record a rename operation
     pub fn record_rename(
         &self,
@@ -84,17 +464,25 @@ impl RenameDatabase {
     ) -> SqliteResult<i64> {
         let conn = Connection::open(&self.db_path)?;
         let timestamp = Utc::now();
+        let identity = FileIdentity::of(new_path).or_else(|| FileIdentity::of(old_path));
+        let (device, inode, size, modified, content_hash) = identity_to_sql(identity.as_ref());
         
         conn.execute(
-            "INSERT INTO renames (timestamp, old_path, new_path, directory, prefix_removed, operation_id)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            "INSERT INTO renames (timestamp, old_path, new_path, directory, prefix_removed, operation_id,
+                                  file_device, file_inode, file_size, file_modified, content_hash)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
             params![
                 timestamp.to_rfc3339(),
-                old_path.to_string_lossy(),
-                new_path.to_string_lossy(),
-                directory.to_string_lossy(),
+                path_to_sql(&normal_path(old_path)),
+                path_to_sql(&normal_path(new_path)),
+                path_to_sql(&normal_path(directory)),
                 prefix_removed,
                 operation_id,
+                device,
+                inode,
+                size,
+                modified,
+                content_hash,
             ],
         )?;
         
@@ -113,7 +501,7 @@ impl RenameDatabase {
         )?;
         
         let operation_ids = stmt.query_map(params![limit], |row| {
-            Ok(row.get::<_, String>(0)?)
+            row.get::<_, String>(0)
         })?;
         
         let mut result = Vec::new();
@@ -124,32 +512,92 @@ impl RenameDatabase {
         Ok(result)
     }
     
+    /// The operations matching `query`, most recent first
+    pub fn query_operations(&self, query: &HistoryQuery) -> SqliteResult<Vec<String>> {
+        let conn = Connection::open(&self.db_path)?;
+        let mut stmt = conn.prepare(&format!("SELECT {} FROM renames ORDER BY id ASC", RECORD_COLUMNS))?;
+        
+        // Operations in the order of their first rename, and those with a matching rename
+        let mut operations: Vec<String> = Vec::new();
+        let mut seen: HashSet<String> = HashSet::new();
+        let mut matched: HashSet<String> = HashSet::new();
+        for record in stmt.query_map([], record_from_row)? {
+            let record = record?;
+            if !matched.contains(&record.operation_id) && query.matches_rename(&record) {
+                matched.insert(record.operation_id.clone());
+            }
+            if seen.insert(record.operation_id.clone()) {
+                operations.push(record.operation_id);
+            }
+        }
+        
+        let mut result = Vec::new();
+        let mut skipped = 0;
+        for operation_id in operations.into_iter().rev().filter(|id| matched.contains(id)) {
+            if query.limit.is_some_and(|limit| result.len() >= limit) {
+                break;
+            }
+            if !query.tags.is_empty() {
+                let tags = self.operation(&operation_id)?.map(|operation| operation.context.tags).unwrap_or_default();
+                if !query.tags.iter().all(|tag| tags.contains(tag)) {
+                    continue;
+                }
+            }
+            if skipped < query.offset {
+                skipped += 1;
+                continue;
+            }
+            result.push(operation_id);
+        }
+        
+        Ok(result)
+    }
+    
+    /// Every rename that gave the file at `path` its name or moved it on,
+    /// oldest first: the renames leading to `path` (each one's new path is the
+    /// old path of the next) followed by those made from `path` afterwards.
+    /// Undo and redo are included, as they rename files too.
+    pub fn path_lineage(&self, path: &Path) -> SqliteResult<Vec<RenameRecord>> {
+        let conn = Connection::open(&self.db_path)?;
+        let mut earlier = conn.prepare(&format!(
+            "SELECT {} FROM renames WHERE new_path = ?1 AND id < ?2 ORDER BY id DESC LIMIT 1",
+            RECORD_COLUMNS
+        ))?;
+        let mut later = conn.prepare(&format!(
+            "SELECT {} FROM renames WHERE old_path = ?1 AND id > ?2 ORDER BY id ASC LIMIT 1",
+            RECORD_COLUMNS
+        ))?;
+        
+        // Record IDs only decrease going back and increase going forward, so both walks end
+        let path = normal_path(path);
+        let mut lineage: Vec<RenameRecord> = Vec::new();
+        let (mut current, mut id) = (path.clone(), i64::MAX);
+        while let Some(record) = earlier.query_map(params![path_to_sql(&current), id], record_from_row)?.next().transpose()? {
+            current = record.old_path.clone();
+            id = record.id;
+            lineage.push(record);
+        }
+        lineage.reverse();
+        
+        let (mut current, mut id) = (path, lineage.last().map_or(0, |record| record.id));
+        while let Some(record) = later.query_map(params![path_to_sql(&current), id], record_from_row)?.next().transpose()? {
+            current = record.new_path.clone();
+            id = record.id;
+            lineage.push(record);
+        }
+        
+        Ok(lineage)
+    }
+    
     /// Get all renames for a specific operation
     pub fn get_operation_renames(&self, operation_id: &str) -> SqliteResult<Vec<RenameRecord>> {
         let conn = Connection::open(&self.db_path)?;
-        let mut stmt = conn.prepare(
-            "SELECT id, timestamp, old_path, new_path, directory, prefix_removed, operation_id
-             FROM renames 
-             WHERE operation_id = ?1 
-             ORDER BY timestamp ASC"
-        )?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM renames WHERE operation_id = ?1 ORDER BY timestamp ASC",
+            RECORD_COLUMNS
+        ))?;
         
-        let rename_iter = stmt.query_map(params![operation_id], |row| {
-            let timestamp_str: String = row.get(1)?;
-            let timestamp = DateTime::parse_from_rfc3339(&timestamp_str)
-                .map_err(|_e| rusqlite::Error::InvalidColumnType(1, "timestamp".to_string(), rusqlite::types::Type::Text))?
-                .with_timezone(&Utc);
-            
-            Ok(RenameRecord {
-                id: row.get(0)?,
-                timestamp,
-                old_path: PathBuf::from(row.get::<_, String>(2)?),
-                new_path: PathBuf::from(row.get::<_, String>(3)?),
-                directory: PathBuf::from(row.get::<_, String>(4)?),
-                prefix_removed: row.get(5)?,
-                operation_id: row.get(6)?,
-            })
-        })?;
+        let rename_iter = stmt.query_map(params![operation_id], record_from_row)?;
         
         let mut result = Vec::new();
         for record in rename_iter {
@@ -159,151 +607,2293 @@ impl RenameDatabase {
         Ok(result)
     }
     
+    /// The renames that moved the file of `record` further after it, in order:
+    /// the rename in effect whose old path is the new path of the previous one.
+    pub fn later_renames(&self, record: &RenameRecord) -> SqliteResult<Vec<RenameRecord>> {
+        let conn = Connection::open(&self.db_path)?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM renames
+             WHERE old_path = ?1 AND id > ?2 AND reverted_by IS NULL
+             ORDER BY id ASC LIMIT 1",
+            RECORD_COLUMNS
+        ))?;
+        
+        let mut chain: Vec<RenameRecord> = Vec::new();
+        loop {
+            let previous = chain.last().unwrap_or(record);
+            let next = stmt.query_map(params![path_to_sql(&previous.new_path), previous.id], record_from_row)?
+                .next()
+                .transpose()?;
+            match next {
+                // A file renamed back and forth would loop forever
+                Some(next) if !chain.iter().any(|link| link.id == next.id) => chain.push(next),
+                _ => return Ok(chain),
+            }
+        }
+    }
+    
+    /// Later operations that renamed files of `operation_id` again (directly or
+    /// through other later operations), most recent first, which is the order
+    /// they have to be undone in before `operation_id` can be
+    pub fn dependent_operations(&self, operation_id: &str) -> SqliteResult<Vec<String>> {
+        let mut dependents: Vec<(i64, String)> = Vec::new();
+        for record in self.get_operation_renames(operation_id)? {
+            if record.reverted_by.is_some() {
+                continue;
+            }
+            for later in self.later_renames(&record)? {
+                if later.operation_id == operation_id {
+                    continue;
+                }
+                match dependents.iter_mut().find(|(_, id)| *id == later.operation_id) {
+                    Some(dependent) => dependent.0 = dependent.0.max(later.id),
+                    None => dependents.push((later.id, later.operation_id)),
+                }
+            }
+        }
+        
+        dependents.sort_by_key(|(id, _)| std::cmp::Reverse(*id));
+        Ok(dependents.into_iter().map(|(_, id)| id).collect())
+    }
+    
     /// Undo a specific operation (reverse all renames in that operation)
     pub fn undo_operation(&self, operation_id: &str) -> Result<(usize, usize), Box<dyn std::error::Error>> {
-        let records = self.get_operation_renames(operation_id)?;
+        self.undo_operation_with(operation_id, print_undo)
+    }
+    
+    /// Undo an operation, reporting the outcome of each file to `report`
+    /// (current path, original path) instead of printing it.
+    /// The files are restored as one batch, so undoing swaps and chains works.
+    /// The restores are recorded as an undo operation linked to `operation_id`,
+    /// which makes the undo itself visible in the history and redoable.
+    /// Renames that were already undone are left alone.
+    pub fn undo_operation_with<F: FnMut(&Path, &Path, &RenameOutcome)>(
+        &self,
+        operation_id: &str,
+        report: F,
+    ) -> Result<(usize, usize), Box<dyn std::error::Error>> {
+        self.revert_operation(operation_id, OperationKind::Undo, &UndoOptions::default(), report)
+    }
+    
+    /// Undo the renames of `operation_id` selected by `options`, printing the
+    /// outcome of each file
+    pub fn undo_with_options(&self, operation_id: &str, options: &UndoOptions) -> Result<(usize, usize), Box<dyn std::error::Error>> {
+        self.revert_operation(operation_id, OperationKind::Undo, options, print_undo)
+    }
+    
+    /// Redo an operation by reverting `undo_id`, the undo operation that
+    /// restored it. The files go through the same conflict checks as any rename.
+    pub fn redo_operation(&self, undo_id: &str) -> Result<(usize, usize), Box<dyn std::error::Error>> {
+        self.redo_with_options(undo_id, &UndoOptions::default())
+    }
+    
+    /// Like `redo_operation`, with the identity checks controlled by `options`
+    pub fn redo_with_options(&self, undo_id: &str, options: &UndoOptions) -> Result<(usize, usize), Box<dyn std::error::Error>> {
+        self.revert_operation(undo_id, OperationKind::Redo, options, |current, renamed, outcome| {
+            let current = display_name(current.as_os_str());
+            let renamed = display_name(renamed.as_os_str());
+            match outcome {
+                RenameOutcome::Renamed => println!("✓ Redid: {} → {}", current, renamed),
+                RenameOutcome::Failed(e) => eprintln!("❌ Failed to redo: {} → {}: {}", current, renamed, e),
+                RenameOutcome::IdentityMismatch(mismatch) => {
+                    eprintln!("❌ Not redoing {}: {} (use --force to redo anyway)", current, mismatch.describe());
+                }
+                _ => eprintln!("⚠️  Cannot redo: {} (file state changed)", current),
+            }
+        })
+    }
+    
+    /// Like `redo_operation`, but reports the outcome of each file to `report`
+    /// (current path, new path) instead of printing it
+    pub fn redo_operation_with<F: FnMut(&Path, &Path, &RenameOutcome)>(
+        &self,
+        undo_id: &str,
+        report: F,
+    ) -> Result<(usize, usize), Box<dyn std::error::Error>> {
+        self.revert_operation(undo_id, OperationKind::Redo, &UndoOptions::default(), report)
+    }
+    
+    /// Reverse the renames of `operation_id` that are in effect (all of them,
+    /// or those in `options.only`) as one tracked batch, recorded as a new
+    /// operation of `kind` that reverts it. With `options.follow`, files
+    /// renamed again later are restored from their current name. Unless
+    /// `options.force` is set, files that do not match the identity recorded
+    /// with their last rename are left alone. The reversed records are marked
+    /// with the new operation; a redo clears the marks its undo left, and an
+    /// undo of a redo marks those renames again. When no file could be
+    /// renamed back, the operation is kept with its failures but marks nothing.
+    fn revert_operation<F: FnMut(&Path, &Path, &RenameOutcome)>(
+        &self,
+        operation_id: &str,
+        kind: OperationKind,
+        options: &UndoOptions,
+        mut report: F,
+    ) -> Result<(usize, usize), Box<dyn std::error::Error>> {
+        let records: Vec<RenameRecord> = self.get_operation_renames(operation_id)?
+            .into_iter()
+            .filter(|record| record.reverted_by.is_none())
+            .filter(|record| options.only.is_none_or(|ids| ids.contains(&record.id)))
+            .collect();
+        if records.is_empty() {
+            return Ok((0, 0));
+        }
+        
+        let mut restores: Vec<(PathBuf, PathBuf)> = Vec::new();
+        let mut followed: Vec<Vec<i64>> = Vec::new();
+        let mut mismatches: Vec<Option<IdentityMismatch>> = Vec::new();
+        for record in &records {
+            let mut current = record.new_path.clone();
+            let mut identity = record.identity.as_ref();
+            let mut chain_ids = Vec::new();
+            let chain = if options.follow && current.symlink_metadata().is_err() {
+                self.later_renames(record)?
+            } else {
+                Vec::new()
+            };
+            if let Some(last) = chain.last().filter(|last| last.new_path.symlink_metadata().is_ok()) {
+                current = last.new_path.clone();
+                identity = last.identity.as_ref();
+                chain_ids = chain.iter().map(|link| link.id).collect();
+            }
+            
+            let mismatch = if options.force { None } else { identity.and_then(|identity| identity.verify(&current)) };
+            mismatches.push(mismatch);
+            restores.push((current, record.old_path.clone()));
+            followed.push(chain_ids);
+        }
+        
+        let mut prefixes: Vec<&str> = Vec::new();
+        for record in &records {
+            if !prefixes.contains(&record.prefix_removed.as_str()) {
+                prefixes.push(&record.prefix_removed);
+            }
+        }
+        
+        // Link the new operation before any file is touched
+        let revert_id = generate_operation_id();
+        let started = Instant::now();
+        let context = options.context.cloned().unwrap_or_else(OperationContext::current);
+        self.record_operation(&revert_id, kind, Some(operation_id), &context)?;
+        let verified: Vec<(PathBuf, PathBuf)> = restores.iter().zip(&mismatches)
+            .filter(|(_, mismatch)| mismatch.is_none())
+            .map(|(restore, _)| restore.clone())
+            .collect();
+        let mut renamed = tracked_rename_batch(self, &verified, &[], &prefixes.join(", "), &revert_id).into_iter();
+        let outcomes: Vec<RenameOutcome> = mismatches.iter()
+            .map(|mismatch| match mismatch {
+                Some(mismatch) => RenameOutcome::IdentityMismatch(*mismatch),
+                None => renamed.next().unwrap_or_else(|| RenameOutcome::Failed("not renamed".to_string())),
+            })
+            .collect();
+        
+        // Remove subfolders created for colliding files once they are empty again
+        for (record, outcome) in records.iter().zip(&outcomes) {
+            if record.collision == Some(CollisionStrategy::Subfolder) && *outcome == RenameOutcome::Renamed {
+                if let Some(parent) = record.new_path.parent() {
+                    let _ = fs::remove_dir(parent);
+                }
+            }
+        }
         
         let mut success_count = 0;
         let mut error_count = 0;
+        for ((current, original), outcome) in restores.iter().zip(&outcomes).rev() {
+            match outcome {
+                RenameOutcome::Renamed => success_count += 1,
+                RenameOutcome::Unchanged => {}
+                _ => error_count += 1,
+            }
+            report(current, original, outcome);
+        }
+        
+        self.finish_operation(&revert_id, success_count, error_count, started.elapsed())?;
+        if success_count == 0 {
+            return Ok((success_count, error_count));
+        }
+        let undoing_redo = kind == OperationKind::Undo && self.operation_kind(operation_id)?.0 == OperationKind::Redo;
         
-        // Reverse the renames (go backwards through the list)
-        for record in records.iter().rev() {
-            // Check if the "new" path still exists and the "old" path doesn't exist
-            if record.new_path.exists() && !record.old_path.exists() {
-                match fs::rename(&record.new_path, &record.old_path) {
-                    Ok(_) => {
-                        success_count += 1;
-                        println!("✓ Undid: {} → {}", 
-                                record.new_path.display(), 
-                                record.old_path.display());
+        let mut conn = Connection::open(&self.db_path)?;
+        let tx = conn.transaction()?;
+        for ((record, outcome), chain_ids) in records.iter().zip(&outcomes).zip(&followed) {
+            if *outcome != RenameOutcome::Renamed {
+                continue;
+            }
+            for id in std::iter::once(&record.id).chain(chain_ids) {
+                tx.execute("UPDATE renames SET reverted_by = ?1 WHERE id = ?2", params![revert_id, id])?;
+            }
+            if kind == OperationKind::Redo {
+                // The renames this undo record reversed are in effect again:
+                // one rename, or a chain of them from its new to its old path.
+                // An undo of a redo reversed the redo and the renames it redid.
+                loop {
+                    let mut path = record.new_path.clone();
+                    let mut cleared = false;
+                    while let Some((id, new_path)) = tx.query_row(
+                        "SELECT id, new_path FROM renames WHERE reverted_by = ?1 AND old_path = ?2 LIMIT 1",
+                        params![operation_id, path_to_sql(&path)],
+                        |row| Ok((row.get::<_, i64>(0)?, path_from_sql(row.get_ref(1)?, 1)?)),
+                    ).optional()? {
+                        tx.execute("UPDATE renames SET reverted_by = NULL WHERE id = ?1", params![id])?;
+                        cleared = true;
+                        if new_path == record.old_path {
+                            break;
+                        }
+                        path = new_path;
                     }
-                    Err(e) => {
-                        error_count += 1;
-                        eprintln!("❌ Failed to undo: {} → {}: {}", 
-                                 record.new_path.display(), 
-                                 record.old_path.display(), 
-                                 e);
+                    if !cleared {
+                        break;
                     }
                 }
-            } else {
-                error_count += 1;
-                eprintln!("⚠️  Cannot undo: {} (file state changed)", record.new_path.display());
+            } else if undoing_redo {
+                // The renames the redo put back in effect are reverted again:
+                // the chain of renames in effect before the redo record that
+                // leads from its old to its new path
+                let mut path = record.old_path.clone();
+                let mut previous_id = 0;
+                while let Some((id, new_path)) = tx.query_row(
+                    "SELECT id, new_path FROM renames
+                     WHERE reverted_by IS NULL AND old_path = ?1 AND id > ?2 AND id < ?3
+                     ORDER BY id DESC LIMIT 1",
+                    params![path_to_sql(&path), previous_id, record.id],
+                    |row| Ok((row.get::<_, i64>(0)?, path_from_sql(row.get_ref(1)?, 1)?)),
+                ).optional()? {
+                    tx.execute("UPDATE renames SET reverted_by = ?1 WHERE id = ?2", params![revert_id, id])?;
+                    if new_path == record.new_path {
+                        break;
+                    }
+                    path = new_path;
+                    previous_id = id;
+                }
             }
         }
+        tx.commit()?;
         
         Ok((success_count, error_count))
     }
     
-    /// Delete old records (older than specified days)
-    pub fn cleanup_old_records(&self, days: u32) -> SqliteResult<usize> {
+    /// Record a rename operation and its context before its first file is
+    /// renamed. `finish_operation` adds the results once it is done.
+    pub fn begin_operation(&self, operation_id: &str, context: &OperationContext) -> SqliteResult<()> {
+        self.record_operation(operation_id, OperationKind::Rename, None, context)
+    }
+    
+    /// Record the number of renamed and failed files of an operation and how
+    /// long it took. An operation that renamed nothing stays in the history
+    /// with its failures, but is left out of `stats`.
+    pub fn finish_operation(&self, operation_id: &str, success_count: usize, failure_count: usize, duration: Duration) -> SqliteResult<()> {
         let conn = Connection::open(&self.db_path)?;
-        let cutoff = Utc::now() - chrono::Duration::days(days as i64);
-        
-        let deleted = conn.execute(
-            "DELETE FROM renames WHERE timestamp < ?1",
-            params![cutoff.to_rfc3339()],
+        conn.execute(
+            "UPDATE operations SET success_count = ?1, failure_count = ?2, duration_ms = ?3 WHERE id = ?4",
+            params![success_count as i64, failure_count as i64, duration.as_millis() as i64, operation_id],
         )?;
-        
-        Ok(deleted)
+        Ok(())
     }
-}
-
-/// Generate a unique operation ID for grouping related renames
-pub fn generate_operation_id() -> String {
-    use std::time::{SystemTime, UNIX_EPOCH};
-    let timestamp = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_secs();
-    
-    format!("op_{}", timestamp)
-}
-
-/// Perform a rename operation with database tracking
-pub fn tracked_rename(
-    db: &RenameDatabase,
-    old_path: &Path,
-    new_path: &Path,
-    prefix_removed: &str,
-    operation_id: &str,
-) -> Result<(), Box<dyn std::error::Error>> {
-    // Get the directory (parent of the old path)
-    let directory = old_path.parent()
-        .ok_or("Could not determine parent directory")?;
     
-    // Perform the actual rename
-    fs::rename(old_path, new_path)?;
+    /// Replace the note of an operation and add tags to it. Operations
+    /// recorded by older versions get an entry of their own.
+    pub fn annotate_operation(&self, operation_id: &str, note: Option<&str>, tags: &[String]) -> SqliteResult<()> {
+        let mut record = match self.operation(operation_id)? {
+            Some(record) => record,
+            None => {
+                let conn = Connection::open(&self.db_path)?;
+                let started: Option<String> = conn.query_row(
+                    "SELECT MIN(timestamp) FROM renames WHERE operation_id = ?1",
+                    params![operation_id],
+                    |row| row.get(0),
+                )?;
+                let Some(started) = started else {
+                    return Err(rusqlite::Error::QueryReturnedNoRows);
+                };
+                conn.execute(
+                    "INSERT INTO operations (id, timestamp, kind) VALUES (?1, ?2, ?3)",
+                    params![operation_id, started, OperationKind::Rename.name()],
+                )?;
+                self.operation(operation_id)?.ok_or(rusqlite::Error::QueryReturnedNoRows)?
+            }
+        };
+        
+        if let Some(note) = note {
+            record.context.note = Some(note.to_string());
+        }
+        for tag in tags {
+            if !record.context.tags.contains(tag) {
+                record.context.tags.push(tag.clone());
+            }
+        }
+        
+        let conn = Connection::open(&self.db_path)?;
+        conn.execute(
+            "UPDATE operations SET note = ?1, tags = ?2 WHERE id = ?3",
+            params![record.context.note, tags_to_sql(&record.context.tags), operation_id],
+        )?;
+        Ok(())
+    }
     
-    // Record in database
-    db.record_rename(old_path, new_path, directory, prefix_removed, operation_id)?;
+    /// The recorded details of an operation; `None` for operations recorded
+    /// before operations had details
+    pub fn operation(&self, operation_id: &str) -> SqliteResult<Option<OperationRecord>> {
+        let conn = Connection::open(&self.db_path)?;
+        conn.query_row(
+            "SELECT id, timestamp, kind, reverts, command_line, working_directory, user_name, hostname,
+                    version, settings, note, tags, duration_ms, success_count, failure_count
+             FROM operations WHERE id = ?1",
+            params![operation_id],
+            |row| {
+                let timestamp_str: String = row.get(1)?;
+                let timestamp = DateTime::parse_from_rfc3339(&timestamp_str)
+                    .map_err(|_e| rusqlite::Error::InvalidColumnType(1, "timestamp".to_string(), rusqlite::types::Type::Text))?
+                    .with_timezone(&Utc);
+                let kind: String = row.get(2)?;
+                
+                Ok(OperationRecord {
+                    id: row.get(0)?,
+                    timestamp,
+                    kind: OperationKind::parse(&kind).unwrap_or(OperationKind::Rename),
+                    reverts: row.get(3)?,
+                    context: OperationContext {
+                        command_line: row.get::<_, Option<String>>(4)?.unwrap_or_default(),
+                        working_directory: row.get(5)?,
+                        user: row.get(6)?,
+                        hostname: row.get(7)?,
+                        version: row.get::<_, Option<String>>(8)?.unwrap_or_default(),
+                        settings: row.get(9)?,
+                        note: row.get(10)?,
+                        tags: tags_from_sql(row.get(11)?),
+                    },
+                    duration_ms: row.get(12)?,
+                    success_count: row.get(13)?,
+                    failure_count: row.get(14)?,
+                })
+            },
+        ).optional()
+    }
     
-    Ok(())
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use tempfile::TempDir;
-    use std::fs::File;
+    /// Record `operation_id` with its context; undo and redo link to the
+    /// operation they revert
+    fn record_operation(&self, operation_id: &str, kind: OperationKind, reverts: Option<&str>, context: &OperationContext) -> SqliteResult<()> {
+        let conn = Connection::open(&self.db_path)?;
+        conn.execute(
+            "INSERT INTO operations (id, timestamp, kind, reverts, command_line, working_directory, user_name, hostname,
+                                     version, settings, note, tags)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![
+                operation_id,
+                Utc::now().to_rfc3339(),
+                kind.name(),
+                reverts,
+                context.command_line,
+                context.working_directory,
+                context.user,
+                context.hostname,
+                context.version,
+                context.settings,
+                context.note,
+                tags_to_sql(&context.tags),
+            ],
+        )?;
+        Ok(())
+    }
     
-    #[test]
-    fn test_database_operations() -> Result<(), Box<dyn std::error::Error>> {
-        let temp_dir = TempDir::new()?;
-        let db_path = temp_dir.path().join("test.db");
-        let db = RenameDatabase::new(db_path);
+    /// What `operation_id` did and, for undo and redo, the operation it reverts
+    pub fn operation_kind(&self, operation_id: &str) -> SqliteResult<(OperationKind, Option<String>)> {
+        let conn = Connection::open(&self.db_path)?;
+        let row = conn.query_row(
+            "SELECT kind, reverts FROM operations WHERE id = ?1",
+            params![operation_id],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, Option<String>>(1)?)),
+        );
         
-        // Initialize database
-        db.initialize()?;
+        match row {
+            Ok((kind, reverts)) => Ok((OperationKind::parse(&kind).unwrap_or(OperationKind::Rename), reverts)),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok((OperationKind::Rename, None)),
+            Err(e) => Err(e),
+        }
+    }
+    
+    /// The undo or redo that currently reverts `operation_id`, i.e. the most
+    /// recent one when every rename of the operation is reverted. `None` if
+    /// the operation is (at least partly) in effect.
+    pub fn reverted_by(&self, operation_id: &str) -> SqliteResult<Option<String>> {
+        let conn = Connection::open(&self.db_path)?;
+        let (total, reverted, latest) = conn.query_row(
+            "SELECT COUNT(*), COUNT(reverted_by), MAX(reverted_by) FROM renames WHERE operation_id = ?1",
+            params![operation_id],
+            |row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?, row.get::<_, Option<String>>(2)?)),
+        )?;
+        
+        Ok(if total > 0 && reverted == total { latest } else { None })
+    }
+    
+    /// Every undo or redo currently reverting some renames of `operation_id`,
+    /// most recent first
+    pub fn reverting_operations(&self, operation_id: &str) -> SqliteResult<Vec<String>> {
+        let conn = Connection::open(&self.db_path)?;
+        let mut stmt = conn.prepare(
+            "SELECT DISTINCT reverted_by FROM renames
+             WHERE operation_id = ?1 AND reverted_by IS NOT NULL
+             ORDER BY reverted_by DESC"
+        )?;
+        let ids = stmt.query_map(params![operation_id], |row| row.get::<_, String>(0))?;
+        ids.collect()
+    }
+    
+    /// Roll back an operation that failed part way: restore every file it
+    /// renamed, including renames that could not be recorded and are still in
+    /// the journal, and delete the records of the restored files, so the
+    /// history only keeps renames that are still in effect. The operation
+    /// itself is forgotten once nothing of it is left.
+    /// Returns the number of restored files and of files that could not be restored.
+    pub fn rollback_operation(&self, operation_id: &str) -> Result<(usize, usize), Box<dyn std::error::Error>> {
+        let records = self.get_operation_renames(operation_id)?;
+        let exists = |path: &Path| path.symlink_metadata().is_ok();
+        let unrecorded: Vec<JournalEntry> = self.journal_entries()?
+            .into_iter()
+            .filter(|entry| entry.operation_id == operation_id)
+            .filter(|entry| entry.temp_path.as_deref().is_none_or(|temp_path| !exists(temp_path)))
+            .filter(|entry| exists(&entry.new_path) && !exists(&entry.old_path))
+            .collect();
+        let restores: Vec<(PathBuf, PathBuf)> = records.iter()
+            .map(|record| (record.new_path.clone(), record.old_path.clone()))
+            .chain(unrecorded.iter().map(|entry| (entry.new_path.clone(), entry.old_path.clone())))
+            .collect();
+        let outcomes = rename_batch(&restores);
+        let (record_outcomes, journal_outcomes) = outcomes.split_at(records.len());
+        
+        let mut conn = Connection::open(&self.db_path)?;
+        let tx = conn.transaction()?;
+        let mut restored = 0;
+        for (record, outcome) in records.iter().zip(record_outcomes) {
+            if *outcome == RenameOutcome::Renamed {
+                tx.execute("DELETE FROM renames WHERE id = ?1", params![record.id])?;
+                restored += 1;
+            }
+        }
+        for (entry, outcome) in unrecorded.iter().zip(journal_outcomes) {
+            if *outcome == RenameOutcome::Renamed {
+                tx.execute("DELETE FROM journal WHERE id = ?1", params![entry.id])?;
+                restored += 1;
+            }
+        }
+        
+        let remaining = restores.len() - restored;
+        if remaining == 0 {
+            tx.execute("DELETE FROM operations WHERE id = ?1", params![operation_id])?;
+        } else {
+            tx.execute(
+                "UPDATE operations SET success_count = ?1 WHERE id = ?2",
+                params![remaining as i64, operation_id],
+            )?;
+        }
+        tx.commit()?;
+        
+        Ok((restored, remaining))
+    }
+    
+    /// Journal a rename before it is done. Returns the journal entry ID.
+    fn begin_rename(
+        &self,
+        old_path: &Path,
+        new_path: &Path,
+        temp_path: Option<&Path>,
+        collision: Option<CollisionStrategy>,
+        prefix_removed: &str,
+        operation_id: &str,
+    ) -> SqliteResult<i64> {
+        let conn = Connection::open(&self.db_path)?;
+        let directory = old_path.parent().unwrap_or(Path::new(""));
+        // Renaming keeps the inode, size, content and modification time
+        let identity = FileIdentity::of(old_path);
+        let (device, inode, size, modified, content_hash) = identity_to_sql(identity.as_ref());
+        
+        conn.execute(
+            "INSERT INTO journal (timestamp, old_path, new_path, temp_path, directory, prefix_removed, operation_id, collision,
+                                  file_device, file_inode, file_size, file_modified, content_hash)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+            params![
+                Utc::now().to_rfc3339(),
+                path_to_sql(&normal_path(old_path)),
+                path_to_sql(&normal_path(new_path)),
+                temp_path.map(|temp_path| path_to_sql(&normal_path(temp_path))),
+                path_to_sql(&normal_path(directory)),
+                prefix_removed,
+                operation_id,
+                collision.map(CollisionStrategy::name),
+                device,
+                inode,
+                size,
+                modified,
+                content_hash,
+            ],
+        )?;
+        
+        Ok(conn.last_insert_rowid())
+    }
+    
+    /// Note that a journaled file was moved to its temporary name
+    fn mark_parked(&self, journal_id: i64) -> SqliteResult<()> {
+        let conn = Connection::open(&self.db_path)?;
+        conn.execute("UPDATE journal SET parked = 1 WHERE id = ?1", params![journal_id])?;
+        Ok(())
+    }
+    
+    /// Record a journaled rename as finished: it moves from the journal to the
+    /// history in a single transaction. Returns the ID of the new record.
+    fn complete_rename(&self, journal_id: i64) -> SqliteResult<i64> {
+        let mut conn = Connection::open(&self.db_path)?;
+        let tx = conn.transaction()?;
+        
+        tx.execute(
+            "INSERT INTO renames (timestamp, old_path, new_path, directory, prefix_removed, operation_id, collision,
+                                  file_device, file_inode, file_size, file_modified, content_hash)
+             SELECT ?1, old_path, new_path, directory, prefix_removed, operation_id, collision,
+                    file_device, file_inode, file_size, file_modified, content_hash
+             FROM journal WHERE id = ?2",
+            params![Utc::now().to_rfc3339(), journal_id],
+        )?;
+        let record_id = tx.last_insert_rowid();
+        tx.execute("DELETE FROM journal WHERE id = ?1", params![journal_id])?;
+        tx.commit()?;
+        
+        Ok(record_id)
+    }
+    
+    /// Forget a journaled rename that did not happen
+    fn discard_journal_entry(&self, journal_id: i64) -> SqliteResult<()> {
+        let conn = Connection::open(&self.db_path)?;
+        conn.execute("DELETE FROM journal WHERE id = ?1", params![journal_id])?;
+        Ok(())
+    }
+    
+    /// Renames that were started but never recorded, oldest first
+    pub fn journal_entries(&self) -> SqliteResult<Vec<JournalEntry>> {
+        let conn = Connection::open(&self.db_path)?;
+        let mut stmt = conn.prepare(
+            "SELECT id, timestamp, old_path, new_path, temp_path, parked, directory, prefix_removed, operation_id
+             FROM journal
+             ORDER BY id ASC"
+        )?;
+        
+        let entries = stmt.query_map([], |row| {
+            let timestamp_str: String = row.get(1)?;
+            let timestamp = DateTime::parse_from_rfc3339(&timestamp_str)
+                .map_err(|_e| rusqlite::Error::InvalidColumnType(1, "timestamp".to_string(), rusqlite::types::Type::Text))?
+                .with_timezone(&Utc);
+            let temp_path = match row.get_ref(4)? {
+                ValueRef::Null => None,
+                value => Some(path_from_sql(value, 4)?),
+            };
+            
+            Ok(JournalEntry {
+                id: row.get(0)?,
+                timestamp,
+                old_path: path_from_sql(row.get_ref(2)?, 2)?,
+                new_path: path_from_sql(row.get_ref(3)?, 3)?,
+                temp_path,
+                parked: row.get(5)?,
+                directory: path_from_sql(row.get_ref(6)?, 6)?,
+                prefix_removed: row.get(7)?,
+                operation_id: row.get(8)?,
+            })
+        })?;
+        
+        entries.collect()
+    }
+    
+    /// Reconcile the unfinished journal entries left by an interrupted run with
+    /// the filesystem: renames that happened are added to the history, files
+    /// parked under a temporary name are moved back and renames that never
+    /// started are dropped. Stranded entries stay in the journal.
+    pub fn recover(&self) -> SqliteResult<Vec<(JournalEntry, Recovery)>> {
+        let mut results = Vec::new();
+        
+        for entry in self.journal_entries()? {
+            let exists = |path: &Path| path.symlink_metadata().is_ok();
+            let recovery = match &entry.temp_path {
+                Some(temp_path) if exists(temp_path) => {
+                    if !exists(&entry.old_path) && fs::rename(temp_path, &entry.old_path).is_ok() {
+                        Recovery::Restored
+                    } else if !exists(&entry.new_path) && fs::rename(temp_path, &entry.new_path).is_ok() {
+                        Recovery::Recorded
+                    } else {
+                        Recovery::Stranded
+                    }
+                }
+                // A parked file that left its temporary name reached its target
+                Some(_) if entry.parked && exists(&entry.new_path) => Recovery::Recorded,
+                Some(_) if entry.parked => Recovery::Missing,
+                Some(_) => Recovery::NotStarted,
+                None if exists(&entry.old_path) => Recovery::NotStarted,
+                None if exists(&entry.new_path) => Recovery::Recorded,
+                None => Recovery::Missing,
+            };
+            
+            match recovery {
+                Recovery::Recorded => {
+                    self.complete_rename(entry.id)?;
+                }
+                Recovery::Stranded => {}
+                _ => self.discard_journal_entry(entry.id)?,
+            }
+            results.push((entry, recovery));
+        }
+        
+        Ok(results)
+    }
+    
+    /// Operations with a legacy ID (see `is_legacy_operation_id`) whose renames
+    /// span more than one prefix or directory, oldest first. These may be
+    /// several operations that were started within the same second.
+    pub fn merged_operations(&self) -> SqliteResult<Vec<String>> {
+        let conn = Connection::open(&self.db_path)?;
+        let mut stmt = conn.prepare(
+            "SELECT operation_id FROM renames
+             GROUP BY operation_id
+             HAVING COUNT(DISTINCT prefix_removed) > 1 OR COUNT(DISTINCT directory) > 1
+             ORDER BY MIN(timestamp) ASC"
+        )?;
+        
+        let operation_ids = stmt.query_map([], |row| row.get::<_, String>(0))?
+            .collect::<SqliteResult<Vec<String>>>()?;
+        Ok(operation_ids.into_iter().filter(|id| is_legacy_operation_id(id)).collect())
+    }
+    
+    /// Split an operation into one operation per removed prefix and directory,
+    /// named `<operation_id>-1`, `<operation_id>-2`, ... in the order they were
+    /// recorded. Returns the new IDs, or nothing if the operation has a single
+    /// prefix and directory.
+    pub fn split_operation(&self, operation_id: &str) -> SqliteResult<Vec<String>> {
+        let records = self.get_operation_renames(operation_id)?;
+        
+        let mut parts: Vec<(&Path, &str)> = Vec::new();
+        for record in &records {
+            let part = (record.directory.as_path(), record.prefix_removed.as_str());
+            if !parts.contains(&part) {
+                parts.push(part);
+            }
+        }
+        if parts.len() < 2 {
+            return Ok(Vec::new());
+        }
+        
+        let mut conn = Connection::open(&self.db_path)?;
+        let tx = conn.transaction()?;
+        let mut new_ids = Vec::new();
+        for (n, (directory, prefix_removed)) in parts.iter().enumerate() {
+            let new_id = format!("{}-{}", operation_id, n + 1);
+            tx.execute(
+                "UPDATE renames SET operation_id = ?1
+                 WHERE operation_id = ?2 AND directory = ?3 AND prefix_removed = ?4",
+                params![new_id, operation_id, path_to_sql(directory), prefix_removed],
+            )?;
+            new_ids.push(new_id);
+        }
+        tx.commit()?;
+        
+        Ok(new_ids)
+    }
+    
+    /// Every operation with renames, oldest first
+    pub fn operation_ids(&self) -> SqliteResult<Vec<String>> {
+        let conn = Connection::open(&self.db_path)?;
+        let mut stmt = conn.prepare(
+            "SELECT operation_id FROM renames GROUP BY operation_id ORDER BY MIN(timestamp) ASC, MIN(id) ASC"
+        )?;
+        let ids = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        ids.collect()
+    }
+    
+    /// Merge operations exported from another history (`HistoryExport`) into
+    /// this one, in a single transaction. Operations this history already has
+    /// are skipped. The renames get new record IDs after the existing ones.
+    /// Device and inode only identify a file on the machine that recorded
+    /// them, so they are dropped; size, modification time and content hash
+    /// are still checked before an imported rename is undone.
+    pub fn import_history(&self, export: &HistoryExport) -> SqliteResult<ImportSummary> {
+        let mut conn = Connection::open(&self.db_path)?;
+        let tx = conn.transaction()?;
+        let mut summary = ImportSummary::default();
+        
+        for operation in &export.operations {
+            let known: bool = tx.query_row(
+                "SELECT EXISTS(SELECT 1 FROM operations WHERE id = ?1)
+                     OR EXISTS(SELECT 1 FROM renames WHERE operation_id = ?1)",
+                params![operation.id],
+                |row| row.get(0),
+            )?;
+            if known {
+                summary.skipped.push(operation.id.clone());
+                continue;
+            }
+            
+            if let Some(details) = &operation.details {
+                let context = &details.context;
+                tx.execute(
+                    "INSERT INTO operations (id, timestamp, kind, reverts, command_line, working_directory, user_name, hostname,
+                                             version, settings, note, tags, duration_ms, success_count, failure_count)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+                    params![
+                        operation.id,
+                        details.timestamp.to_rfc3339(),
+                        details.kind.name(),
+                        details.reverts,
+                        context.command_line,
+                        context.working_directory,
+                        context.user,
+                        context.hostname,
+                        context.version,
+                        context.settings,
+                        context.note,
+                        tags_to_sql(&context.tags),
+                        details.duration_ms,
+                        details.success_count,
+                        details.failure_count,
+                    ],
+                )?;
+            }
+            
+            for record in &operation.renames {
+                let (_, _, size, modified, content_hash) = identity_to_sql(record.identity.as_ref());
+                tx.execute(
+                    "INSERT INTO renames (timestamp, old_path, new_path, directory, prefix_removed, operation_id, collision,
+                                          reverted_by, file_size, file_modified, content_hash)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+                    params![
+                        record.timestamp.to_rfc3339(),
+                        path_to_sql(&record.old_path),
+                        path_to_sql(&record.new_path),
+                        path_to_sql(&record.directory),
+                        record.prefix_removed,
+                        operation.id,
+                        record.collision.map(CollisionStrategy::name),
+                        record.reverted_by,
+                        size,
+                        modified,
+                        content_hash,
+                    ],
+                )?;
+                summary.renames += 1;
+            }
+            summary.operations += 1;
+        }
+        
+        tx.commit()?;
+        Ok(summary)
+    }
+    
+    /// Delete operations started more than `days` days ago that can no longer
+    /// be undone. Returns the number of renames deleted.
+    pub fn cleanup_old_records(&self, days: u32) -> SqliteResult<usize> {
+        let options = PruneOptions {
+            older_than: Some(Utc::now() - chrono::Duration::days(days as i64)),
+            ..PruneOptions::default()
+        };
+        Ok(self.prune(&options)?.renames)
+    }
+    
+    /// Delete the operations matching `options`, with their renames, in a
+    /// single transaction. Unless `options.force` is set, operations with
+    /// renames still in effect are kept, so everything that could be undone
+    /// before can still be undone.
+    pub fn prune(&self, options: &PruneOptions) -> SqliteResult<PruneSummary> {
+        let operations = self.operation_summaries()?;
+        let keep_from = options.keep_last.map(|keep| operations.len().saturating_sub(keep));
+        
+        let mut summary = PruneSummary::default();
+        for (index, operation) in operations.into_iter().enumerate() {
+            let old = options.older_than.is_none_or(|cutoff| operation.started < cutoff);
+            let not_kept = keep_from.is_none_or(|keep_from| index < keep_from);
+            if !old || !not_kept {
+                continue;
+            }
+            if operation.reverted < operation.renames && !options.force {
+                summary.kept_undoable.push(operation.id);
+                continue;
+            }
+            summary.renames += operation.renames;
+            summary.operations.push(operation.id);
+        }
+        
+        if options.dry_run || summary.operations.is_empty() {
+            return Ok(summary);
+        }
+        
+        let mut conn = Connection::open(&self.db_path)?;
+        let tx = conn.transaction()?;
+        for operation_id in &summary.operations {
+            tx.execute("DELETE FROM renames WHERE operation_id = ?1", params![operation_id])?;
+            tx.execute("DELETE FROM operations WHERE id = ?1", params![operation_id])?;
+        }
+        tx.commit()?;
+        
+        Ok(summary)
+    }
+    
+    /// Rebuild the database file so the space of deleted records is given
+    /// back to the file system
+    pub fn vacuum(&self) -> SqliteResult<()> {
+        let conn = Connection::open(&self.db_path)?;
+        conn.execute_batch("VACUUM")
+    }
+    
+    /// Totals over the whole history
+    pub fn stats(&self) -> SqliteResult<HistoryStats> {
+        let mut stats = HistoryStats::default();
+        let mut per_day: BTreeMap<NaiveDate, usize> = BTreeMap::new();
+        let mut rename_operations: HashSet<String> = HashSet::new();
+        let mut undone: HashSet<String> = HashSet::new();
+        
+        // Operations that renamed nothing are only kept as a record of their failures
+        for operation in self.operation_summaries()?.into_iter().filter(|operation| operation.succeeded) {
+            stats.first = Some(stats.first.map_or(operation.started, |first| first.min(operation.started)));
+            stats.last = Some(stats.last.map_or(operation.started, |last| last.max(operation.started)));
+            match operation.kind {
+                OperationKind::Rename => {
+                    stats.rename_operations += 1;
+                    stats.files_renamed += operation.renames;
+                    *per_day.entry(operation.started.date_naive()).or_default() += 1;
+                    if operation.reverted > 0 {
+                        undone.insert(operation.id.clone());
+                    }
+                    rename_operations.insert(operation.id);
+                }
+                OperationKind::Undo => stats.undo_operations += 1,
+                OperationKind::Redo => stats.redo_operations += 1,
+            }
+        }
+        
+        let conn = Connection::open(&self.db_path)?;
+        // Redone operations are no longer marked as reverted, but their undo is still recorded
+        let mut stmt = conn.prepare("SELECT DISTINCT reverts FROM operations
+             WHERE kind = 'undo' AND reverts IS NOT NULL AND COALESCE(success_count, 1) > 0")?;
+        for reverts in stmt.query_map([], |row| row.get::<_, String>(0))? {
+            let reverts = reverts?;
+            if rename_operations.contains(&reverts) {
+                undone.insert(reverts);
+            }
+        }
+        stats.undone_operations = undone.len();
+        stats.operations_per_day = per_day.into_iter().collect();
+        
+        let mut stmt = conn.prepare(
+            "SELECT renames.prefix_removed, COUNT(*) FROM renames
+             LEFT JOIN operations ON operations.id = renames.operation_id
+             WHERE COALESCE(operations.kind, 'rename') = 'rename'
+             GROUP BY renames.prefix_removed
+             ORDER BY COUNT(*) DESC, renames.prefix_removed ASC"
+        )?;
+        let prefixes = stmt.query_map([], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as usize)))?;
+        stats.prefixes = prefixes.collect::<SqliteResult<_>>()?;
+        
+        Ok(stats)
+    }
+    
+    /// Every operation, oldest first, including operations recorded without
+    /// renames and renames recorded by older versions without an operation
+    fn operation_summaries(&self) -> SqliteResult<Vec<OperationSummary>> {
+        let conn = Connection::open(&self.db_path)?;
+        let mut stmt = conn.prepare(
+            "SELECT renames.operation_id, COALESCE(MIN(operations.timestamp), MIN(renames.timestamp)),
+                    MAX(operations.kind), COUNT(*), COUNT(renames.reverted_by),
+                    COALESCE(MAX(operations.success_count), 1) > 0
+             FROM renames LEFT JOIN operations ON operations.id = renames.operation_id
+             GROUP BY renames.operation_id
+             UNION ALL
+             SELECT id, timestamp, kind, 0, 0, COALESCE(success_count, 1) > 0 FROM operations
+             WHERE id NOT IN (SELECT operation_id FROM renames)"
+        )?;
+        let rows = stmt.query_map([], |row| {
+            let timestamp: String = row.get(1)?;
+            let started = DateTime::parse_from_rfc3339(&timestamp)
+                .map_err(|_e| rusqlite::Error::InvalidColumnType(1, "timestamp".to_string(), rusqlite::types::Type::Text))?
+                .with_timezone(&Utc);
+            let kind: Option<String> = row.get(2)?;
+            
+            Ok(OperationSummary {
+                id: row.get(0)?,
+                started,
+                kind: kind.as_deref().and_then(OperationKind::parse).unwrap_or(OperationKind::Rename),
+                renames: row.get::<_, i64>(3)? as usize,
+                reverted: row.get::<_, i64>(4)? as usize,
+                succeeded: row.get(5)?,
+            })
+        })?;
+        
+        let mut operations = rows.collect::<SqliteResult<Vec<_>>>()?;
+        operations.sort_by(|a, b| a.started.cmp(&b.started).then_with(|| a.id.cmp(&b.id)));
+        Ok(operations)
+    }
+}
+
+/// Tags are stored as a JSON array so they may contain any character
+fn tags_to_sql(tags: &[String]) -> Option<String> {
+    if tags.is_empty() {
+        None
+    } else {
+        serde_json::to_string(tags).ok()
+    }
+}
+
+fn tags_from_sql(value: Option<String>) -> Vec<String> {
+    value.and_then(|json| serde_json::from_str(&json).ok()).unwrap_or_default()
+}
+
+/// Columns read by `record_from_row`
+const RECORD_COLUMNS: &str =
+    "id, timestamp, old_path, new_path, directory, prefix_removed, operation_id, collision, reverted_by,
+     file_device, file_inode, file_size, file_modified, content_hash";
+
+fn record_from_row(row: &rusqlite::Row<'_>) -> SqliteResult<RenameRecord> {
+    let timestamp_str: String = row.get(1)?;
+    let timestamp = DateTime::parse_from_rfc3339(&timestamp_str)
+        .map_err(|_e| rusqlite::Error::InvalidColumnType(1, "timestamp".to_string(), rusqlite::types::Type::Text))?
+        .with_timezone(&Utc);
+    
+    Ok(RenameRecord {
+        id: row.get(0)?,
+        timestamp,
+        old_path: path_from_sql(row.get_ref(2)?, 2)?,
+        new_path: path_from_sql(row.get_ref(3)?, 3)?,
+        directory: path_from_sql(row.get_ref(4)?, 4)?,
+        prefix_removed: row.get(5)?,
+        operation_id: row.get(6)?,
+        collision: row.get::<_, Option<String>>(7)?.as_deref().and_then(CollisionStrategy::parse),
+        reverted_by: row.get(8)?,
+        identity: identity_from_row(row, 9)?,
+    })
+}
+
+/// Values of the file identity columns, `file_device` to `content_hash`
+type IdentityValues = (Option<i64>, Option<i64>, Option<i64>, Option<i64>, Option<String>);
+
+/// Values for the file identity columns. SQLite integers are signed, so the
+/// unsigned fields are stored bit for bit as `i64`.
+fn identity_to_sql(identity: Option<&FileIdentity>) -> IdentityValues {
+    match identity {
+        Some(identity) => (
+            identity.device.map(|device| device as i64),
+            identity.inode.map(|inode| inode as i64),
+            identity.size.map(|size| size as i64),
+            identity.modified,
+            identity.content_hash.clone(),
+        ),
+        None => (None, None, None, None, None),
+    }
+}
+
+/// Read the file identity columns starting at column `first`
+fn identity_from_row(row: &rusqlite::Row<'_>, first: usize) -> SqliteResult<Option<FileIdentity>> {
+    let identity = FileIdentity {
+        device: row.get::<_, Option<i64>>(first)?.map(|device| device as u64),
+        inode: row.get::<_, Option<i64>>(first + 1)?.map(|inode| inode as u64),
+        size: row.get::<_, Option<i64>>(first + 2)?.map(|size| size as u64),
+        modified: row.get(first + 3)?,
+        content_hash: row.get(first + 4)?,
+    };
+    
+    let known = identity.device.is_some() || identity.inode.is_some() || identity.size.is_some()
+        || identity.modified.is_some() || identity.content_hash.is_some();
+    Ok(known.then_some(identity))
+}
+
+/// Print the outcome of undoing one file
+fn print_undo(current: &Path, original: &Path, outcome: &RenameOutcome) {
+    let current = display_name(current.as_os_str());
+    let original = display_name(original.as_os_str());
+    match outcome {
+        RenameOutcome::Renamed => println!("✓ Undid: {} → {}", current, original),
+        RenameOutcome::Failed(e) => eprintln!("❌ Failed to undo: {} → {}: {}", current, original, e),
+        RenameOutcome::IdentityMismatch(mismatch) => {
+            eprintln!("❌ Not undoing {}: {} (use --force to undo anyway)", current, mismatch.describe());
+        }
+        _ => eprintln!("⚠️  Cannot undo: {} (file state changed)", current),
+    }
+}
+
+fn home_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+}
+
+fn create_parent_dir(path: &Path) -> SqliteResult<()> {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() && !dir.exists() => {
+            fs::create_dir_all(dir).map_err(|e| {
+                rusqlite::Error::InvalidPath(format!("Could not create {}: {}", display_name(dir.as_os_str()), e).into())
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Encode a path as the raw bytes stored in the BLOB path columns
+fn path_to_sql(path: &Path) -> Vec<u8> {
+    name_to_bytes(path.as_os_str()).into_owned()
+}
+
+/// `path` without `.` components, the form in which paths are recorded, so
+/// that `/music/./a.mp3` and `/music/a.mp3` are found as the same file
+fn normal_path(path: &Path) -> PathBuf {
+    path.components().collect()
+}
+
+/// Decode a path column, accepting both BLOB rows and legacy TEXT rows
+fn path_from_sql(value: ValueRef<'_>, column: usize) -> SqliteResult<PathBuf> {
+    match value {
+        ValueRef::Blob(bytes) | ValueRef::Text(bytes) => Ok(PathBuf::from(name_from_bytes(bytes.to_vec()))),
+        other => Err(rusqlite::Error::InvalidColumnType(column, "path".to_string(), other.data_type())),
+    }
+}
+
+/// Generate a unique operation ID for grouping related renames.
+/// IDs are `op_` followed by a ULID, so they sort by creation time and
+/// operations started within the same second never share an ID.
+pub fn generate_operation_id() -> String {
+    format!("op_{}", ulid::Ulid::new())
+}
+
+/// Whether `operation_id` was generated by older versions, which used whole
+/// seconds (`op_1700000000`) and could give several operations the same ID
+pub fn is_legacy_operation_id(operation_id: &str) -> bool {
+    operation_id.strip_prefix("op_")
+        .is_some_and(|seconds| !seconds.is_empty() && seconds.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Perform a rename operation with database tracking
+pub fn tracked_rename(
+    db: &RenameDatabase,
+    old_path: &Path,
+    new_path: &Path,
+    prefix_removed: &str,
+    operation_id: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if old_path.parent().is_none() {
+        return Err("Could not determine parent directory".into());
+    }
+    
+    // Journal the rename first so a crash never loses the undo information
+    let journal_id = db.begin_rename(old_path, new_path, None, None, prefix_removed, operation_id)?;
+    
+    // Perform the actual rename
+    if let Err(e) = fs::rename(old_path, new_path) {
+        let _ = db.discard_journal_entry(journal_id);
+        return Err(e.into());
+    }
+    
+    // Move the journal entry to the history
+    db.complete_rename(journal_id)?;
+    
+    Ok(())
+}
+
+/// Identifies a file on disk independently of how its path is spelled, so a
+/// case-only rename on a case-insensitive filesystem is not seen as a collision
+#[cfg(unix)]
+type FileKey = (u64, u64);
+#[cfg(not(unix))]
+type FileKey = String;
+
+#[cfg(unix)]
+fn file_key(path: &Path) -> Option<FileKey> {
+    use std::os::unix::fs::MetadataExt;
+    let metadata = path.symlink_metadata().ok()?;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_key(path: &Path) -> Option<FileKey> {
+    path.symlink_metadata().ok()?;
+    Some(path.to_string_lossy().to_lowercase())
+}
+
+/// Check a batch of `(old path, new path)` renames before anything is renamed.
+/// Returns, in the order of `renames`, the outcome of every rename that cannot
+/// (or need not) be done and `None` for the ones that can. A target may be the
+/// source of another rename of the batch as long as that file moves away, so
+/// swaps and chains are allowed; renames that depend on a conflicting one are
+/// conflicts too.
+pub fn batch_conflicts(renames: &[(PathBuf, PathBuf)]) -> Vec<Option<RenameOutcome>> {
+    let mut outcomes: Vec<Option<RenameOutcome>> = vec![None; renames.len()];
+    let mut sources: HashMap<&Path, usize> = HashMap::new();
+    let mut targets: HashMap<&Path, usize> = HashMap::new();
+    
+    for (i, (old_path, new_path)) in renames.iter().enumerate() {
+        if old_path == new_path {
+            outcomes[i] = Some(RenameOutcome::Unchanged);
+        } else if old_path.symlink_metadata().is_err() {
+            outcomes[i] = Some(RenameOutcome::SourceMissing);
+        } else if sources.insert(old_path, i).is_some() {
+            outcomes[i] = Some(RenameOutcome::Failed("listed more than once".to_string()));
+        } else if let Some(other) = targets.insert(new_path, i) {
+            outcomes[i] = Some(RenameOutcome::DuplicateTarget);
+            outcomes[other] = Some(RenameOutcome::DuplicateTarget);
+        }
+    }
+    
+    // A target is free if nothing is there or the file there moves away.
+    // Dropping a conflicting rename can block another one, so repeat until stable.
+    loop {
+        let moving: HashSet<FileKey> = renames.iter()
+            .zip(&outcomes)
+            .filter(|(_, outcome)| outcome.is_none())
+            .filter_map(|((old_path, _), _)| file_key(old_path))
+            .collect();
+        
+        let mut changed = false;
+        for (i, (_, new_path)) in renames.iter().enumerate() {
+            if outcomes[i].is_none() && file_key(new_path).is_some_and(|key| !moving.contains(&key)) {
+                outcomes[i] = Some(RenameOutcome::TargetExists);
+                changed = true;
+            }
+        }
+        
+        if !changed {
+            return outcomes;
+        }
+    }
+}
+
+/// Give every rename of a batch whose target is taken a free name according
+/// to `strategy`. `tag` is the text removed from the names, used by `Suffix`
+/// and `Subfolder`; a tag that is not a single valid file name falls back to
+/// `Number`. Of several renames to the same name the first keeps it.
+/// Returns the renames with their final targets and, in the same order, the
+/// strategy applied to each. Skipped renames keep their old name, and `Backup`
+/// appends the renames that move the existing files out of the way.
+pub fn resolve_collisions(
+    renames: &[(PathBuf, PathBuf)],
+    strategy: CollisionStrategy,
+    tag: &str,
+) -> (Vec<(PathBuf, PathBuf)>, Vec<Option<CollisionStrategy>>) {
+    let conflicts = batch_conflicts(renames);
+    let mut resolved = renames.to_vec();
+    let mut applied = vec![None; renames.len()];
+    let sources: HashSet<&Path> = renames.iter().map(|(old_path, _)| old_path.as_path()).collect();
+    let tag = tag.trim();
+    let strategy = match strategy {
+        CollisionStrategy::Suffix | CollisionStrategy::Subfolder if !is_valid_name(OsStr::new(tag)) => CollisionStrategy::Number,
+        strategy => strategy,
+    };
+    
+    // Names in use once the batch ran
+    let mut claimed: HashSet<PathBuf> = renames.iter()
+        .zip(&conflicts)
+        .filter(|(_, conflict)| conflict.is_none())
+        .map(|((_, new_path), _)| new_path.clone())
+        .collect();
+    let mut backups = Vec::new();
+    
+    for (i, conflict) in conflicts.iter().enumerate() {
+        let (old_path, new_path) = &renames[i];
+        let duplicate = match conflict {
+            Some(RenameOutcome::DuplicateTarget) => true,
+            Some(RenameOutcome::TargetExists) => false,
+            _ => continue,
+        };
+        if duplicate && !name_taken(new_path, &claimed) {
+            claimed.insert(new_path.clone());
+            continue;
+        }
+        
+        let target = match strategy {
+            CollisionStrategy::Skip => {
+                resolved[i].1 = old_path.clone();
+                applied[i] = Some(strategy);
+                continue;
+            }
+            // Only files outside the batch are backed up
+            CollisionStrategy::Backup if !duplicate && !sources.contains(new_path.as_path()) && !claimed.contains(new_path) => {
+                let mut backup_name = new_path.file_name().unwrap_or_default().to_os_string();
+                backup_name.push(".bak");
+                let backup = free_name(new_path.with_file_name(backup_name), &claimed);
+                claimed.insert(backup.clone());
+                backups.push((new_path.clone(), backup));
+                new_path.clone()
+            }
+            CollisionStrategy::Suffix => {
+                free_name(with_stem_suffix(new_path, &format!(" - {}", tag)), &claimed)
+            }
+            CollisionStrategy::Subfolder => {
+                let parent = new_path.parent().unwrap_or(Path::new(""));
+                free_name(parent.join(tag).join(new_path.file_name().unwrap_or_default()), &claimed)
+            }
+            _ => free_name(new_path.clone(), &claimed),
+        };
+        
+        claimed.insert(target.clone());
+        resolved[i].1 = target;
+        applied[i] = Some(strategy);
+    }
+    
+    for backup in backups {
+        resolved.push(backup);
+        applied.push(Some(CollisionStrategy::Backup));
+    }
+    
+    (resolved, applied)
+}
+
+fn name_taken(path: &Path, claimed: &HashSet<PathBuf>) -> bool {
+    claimed.contains(path) || path.symlink_metadata().is_ok()
+}
+
+/// `path` itself if the name is free, otherwise the first free numbered
+/// variant: `Intro.mp3` → `Intro (2).mp3`
+fn free_name(path: PathBuf, claimed: &HashSet<PathBuf>) -> PathBuf {
+    if !name_taken(&path, claimed) {
+        return path;
+    }
+    (2..)
+        .map(|n| with_stem_suffix(&path, &format!(" ({})", n)))
+        .find(|candidate| !name_taken(candidate, claimed))
+        .unwrap_or(path)
+}
+
+/// `path` with `text` added before the extension
+fn with_stem_suffix(path: &Path, text: &str) -> PathBuf {
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push(text);
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    path.with_file_name(name)
+}
+
+/// Rename a batch of files whose targets may overlap their sources, such as
+/// swaps, chains (a→b, b→c) or case-only renames. Conflicts are detected up
+/// front by `batch_conflicts` and those renames are skipped; files whose target
+/// is still in use are first moved to a temporary name in the same directory.
+/// Existing files outside the batch are never overwritten.
+/// Returns the outcome of every rename, in the order of `renames`.
+pub fn rename_batch(renames: &[(PathBuf, PathBuf)]) -> Vec<RenameOutcome> {
+    execute_batch(renames, prepare_batch(renames), |_, _| {})
+}
+
+/// Conflicts and temporary names of a batch, worked out before anything is renamed
+struct PreparedBatch {
+    outcomes: Vec<Option<RenameOutcome>>,
+    temporary: HashMap<usize, PathBuf>,
+}
+
+/// Progress of one rename of a batch, reported while the batch runs
+enum BatchStep<'a> {
+    /// The file was moved to its temporary name
+    Parked,
+    Finished(&'a RenameOutcome),
+}
+
+fn prepare_batch(renames: &[(PathBuf, PathBuf)]) -> PreparedBatch {
+    let outcomes = batch_conflicts(renames);
+    
+    // Files whose target is still in use go through a temporary name
+    let temporary = renames.iter()
+        .enumerate()
+        .filter(|(i, (_, new_path))| outcomes[*i].is_none() && new_path.symlink_metadata().is_ok())
+        .map(|(i, (old_path, _))| (i, temporary_path(old_path, i)))
+        .collect();
+    
+    PreparedBatch { outcomes, temporary }
+}
+
+fn execute_batch<F: FnMut(usize, BatchStep)>(
+    renames: &[(PathBuf, PathBuf)],
+    prepared: PreparedBatch,
+    mut progress: F,
+) -> Vec<RenameOutcome> {
+    let PreparedBatch { mut outcomes, mut temporary } = prepared;
+    
+    // Phase 1: move files out of the way of other renames
+    for (i, (old_path, _)) in renames.iter().enumerate() {
+        let Some(temp_path) = temporary.get(&i) else {
+            continue;
+        };
+        
+        match fs::rename(old_path, temp_path) {
+            Ok(_) => progress(i, BatchStep::Parked),
+            Err(e) => {
+                let outcome = RenameOutcome::Failed(e.to_string());
+                progress(i, BatchStep::Finished(&outcome));
+                outcomes[i] = Some(outcome);
+                temporary.remove(&i);
+            }
+        }
+    }
+    
+    // Phase 2: renames whose target was free from the start,
+    // then phase 3: move the temporary names to their targets
+    for temp_phase in [false, true] {
+        for (i, (old_path, new_path)) in renames.iter().enumerate() {
+            if outcomes[i].is_some() || temporary.contains_key(&i) != temp_phase {
+                continue;
+            }
+            
+            let from = temporary.get(&i).unwrap_or(old_path);
+            let outcome = if new_path.symlink_metadata().is_ok() {
+                // A rename this one depended on failed
+                RenameOutcome::TargetExists
+            } else {
+                match fs::rename(from, new_path) {
+                    Ok(_) => RenameOutcome::Renamed,
+                    Err(e) => RenameOutcome::Failed(e.to_string()),
+                }
+            };
+            
+            // Put files parked under a temporary name back where they were
+            if outcome != RenameOutcome::Renamed && temp_phase && old_path.symlink_metadata().is_err() {
+                let _ = fs::rename(from, old_path);
+            }
+            progress(i, BatchStep::Finished(&outcome));
+            outcomes[i] = Some(outcome);
+        }
+    }
+    
+    outcomes.into_iter()
+        .map(|outcome| outcome.unwrap_or(RenameOutcome::Renamed))
+        .collect()
+}
+
+/// An unused temporary name next to `path`
+fn temporary_path(path: &Path, index: usize) -> PathBuf {
+    let mut attempt = 0;
+    loop {
+        let candidate = path.with_file_name(format!(".ftmi-{}-{}-{}.tmp", std::process::id(), index, attempt));
+        if candidate.symlink_metadata().is_err() {
+            return candidate;
+        }
+        attempt += 1;
+    }
+}
+
+/// Rename a batch of files like `rename_batch` and record the successful
+/// renames (the net old → new mapping, without temporary names) as part of
+/// `operation_id`. Every rename is journaled before any file is touched, so an
+/// interrupted batch can be reconciled with `RenameDatabase::recover`.
+/// `collisions` holds the strategies returned by `resolve_collisions` (or is
+/// empty); they are recorded with the renames, and skipped renames are
+/// reported as `RenameOutcome::Skipped`.
+/// Returns the outcome of every rename, in the order of `renames`.
+pub fn tracked_rename_batch(
+    db: &RenameDatabase,
+    renames: &[(PathBuf, PathBuf)],
+    collisions: &[Option<CollisionStrategy>],
+    prefix_removed: &str,
+    operation_id: &str,
+) -> Vec<RenameOutcome> {
+    let collision = |i: usize| collisions.get(i).copied().flatten();
+    
+    // Subfolders for colliding files are created up front
+    for (i, (_, new_path)) in renames.iter().enumerate() {
+        if collision(i) == Some(CollisionStrategy::Subfolder) {
+            if let Some(parent) = new_path.parent() {
+                let _ = fs::create_dir_all(parent);
+            }
+        }
+    }
+    
+    let mut prepared = prepare_batch(renames);
+    for (i, outcome) in prepared.outcomes.iter_mut().enumerate() {
+        if collision(i) == Some(CollisionStrategy::Skip) {
+            *outcome = Some(RenameOutcome::Skipped);
+        }
+    }
+    
+    let mut journal: HashMap<usize, i64> = HashMap::new();
+    for (i, (old_path, new_path)) in renames.iter().enumerate() {
+        if prepared.outcomes[i].is_some() {
+            continue;
+        }
+        
+        let temp_path = prepared.temporary.get(&i).map(PathBuf::as_path);
+        match db.begin_rename(old_path, new_path, temp_path, collision(i), prefix_removed, operation_id) {
+            Ok(journal_id) => {
+                journal.insert(i, journal_id);
+            }
+            Err(e) => {
+                prepared.outcomes[i] = Some(RenameOutcome::Failed(format!("could not write the journal: {}", e)));
+                prepared.temporary.remove(&i);
+            }
+        }
+    }
+    
+    let temporary = prepared.temporary.clone();
+    let mut unrecorded = Vec::new();
+    let mut outcomes = execute_batch(renames, prepared, |i, step| {
+        let Some(&journal_id) = journal.get(&i) else {
+            return;
+        };
+        
+        match step {
+            BatchStep::Parked => {
+                let _ = db.mark_parked(journal_id);
+            }
+            BatchStep::Finished(RenameOutcome::Renamed) => {
+                if let Err(e) = db.complete_rename(journal_id) {
+                    unrecorded.push((i, e));
+                }
+            }
+            BatchStep::Finished(_) => {
+                // Keep the entry if the file could not leave its temporary name
+                if temporary.get(&i).is_none_or(|temp_path| temp_path.symlink_metadata().is_err()) {
+                    let _ = db.discard_journal_entry(journal_id);
+                }
+            }
+        }
+    });
+    
+    for (i, e) in unrecorded {
+        outcomes[i] = RenameOutcome::Failed(format!("renamed, but the rename could not be recorded: {} (run 'ftmi recover')", e));
+    }
+    
+    outcomes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+    use std::fs::File;
+    
+    #[test]
+    fn test_database_operations() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("test.db");
+        let db = RenameDatabase::new(db_path);
+        
+        // Initialize database
+        db.initialize()?;
         
         // Create test paths
         let old_path = temp_dir.path().join("[Artist] Song.mp3");
         let new_path = temp_dir.path().join("Song.mp3");
         let directory = temp_dir.path();
         
-        // Record a rename
-        let operation_id = generate_operation_id();
-        let record_id = db.record_rename(&old_path, &new_path, directory, "Artist", &operation_id)?;
+        // Record a rename
+        let operation_id = generate_operation_id();
+        let record_id = db.record_rename(&old_path, &new_path, directory, "Artist", &operation_id)?;
+        
+        assert!(record_id > 0);
+        
+        // Get recent operations
+        let recent = db.get_recent_operations(10)?;
+        assert_eq!(recent.len(), 1);
+        assert_eq!(recent[0], operation_id);
+        
+        // Get operation renames
+        let renames = db.get_operation_renames(&operation_id)?;
+        assert_eq!(renames.len(), 1);
+        assert_eq!(renames[0].prefix_removed, "Artist");
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_tracked_rename() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("test.db");
+        let db = RenameDatabase::new(db_path);
+        db.initialize()?;
+        
+        // Create a test file
+        let old_path = temp_dir.path().join("[Test] File.txt");
+        let new_path = temp_dir.path().join("File.txt");
+        File::create(&old_path)?;
+        
+        let operation_id = generate_operation_id();
+        
+        // Perform tracked rename
+        tracked_rename(&db, &old_path, &new_path, "Test", &operation_id)?;
+        
+        // Verify file was renamed
+        assert!(!old_path.exists());
+        assert!(new_path.exists());
+        
+        // Verify database record
+        let renames = db.get_operation_renames(&operation_id)?;
+        assert_eq!(renames.len(), 1);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_legacy_text_paths_are_readable() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let db_path = temp_dir.path().join("test.db");
+        
+        // A table written by an older version stored paths as TEXT
+        let conn = Connection::open(&db_path)?;
+        conn.execute(
+            "CREATE TABLE renames (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                timestamp TEXT NOT NULL,
+                old_path TEXT NOT NULL,
+                new_path TEXT NOT NULL,
+                directory TEXT NOT NULL,
+                prefix_removed TEXT NOT NULL,
+                operation_id TEXT NOT NULL
+            )",
+            [],
+        )?;
+        conn.execute(
+            "INSERT INTO renames (timestamp, old_path, new_path, directory, prefix_removed, operation_id)
+             VALUES (?1, '/music/[Artist] Song.mp3', '/music/Song.mp3', '/music', '[Artist]', 'op_1')",
+            params![Utc::now().to_rfc3339()],
+        )?;
+        drop(conn);
+        
+        let db = RenameDatabase::new(db_path);
+        db.initialize()?;
         
-        assert!(record_id > 0);
+        let renames = db.get_operation_renames("op_1")?;
+        assert_eq!(renames.len(), 1);
+        assert_eq!(renames[0].old_path, PathBuf::from("/music/[Artist] Song.mp3"));
+        assert_eq!(renames[0].new_path, PathBuf::from("/music/Song.mp3"));
         
-        // Get recent operations
-        let recent = db.get_recent_operations(10)?;
-        assert_eq!(recent.len(), 1);
-        assert_eq!(recent[0], operation_id);
+        Ok(())
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn test_tracked_rename_non_utf8() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let db = RenameDatabase::new(temp_dir.path().join("test.db"));
+        db.initialize()?;
         
-        // Get operation renames
+        let old_path = temp_dir.path().join(name_from_bytes(b"[Test] caf\xe9.txt".to_vec()));
+        let new_path = temp_dir.path().join(name_from_bytes(b"caf\xe9.txt".to_vec()));
+        File::create(&old_path)?;
+        
+        let operation_id = generate_operation_id();
+        tracked_rename(&db, &old_path, &new_path, "[Test]", &operation_id)?;
+        
+        // The exact bytes survive the round trip through the database
         let renames = db.get_operation_renames(&operation_id)?;
-        assert_eq!(renames.len(), 1);
-        assert_eq!(renames[0].prefix_removed, "Artist");
+        assert_eq!(renames[0].old_path, old_path);
+        assert_eq!(renames[0].new_path, new_path);
+        
+        let (success, errors) = db.undo_operation(&operation_id)?;
+        assert_eq!((success, errors), (1, 0));
+        assert!(old_path.exists());
         
         Ok(())
     }
     
     #[test]
-    fn test_tracked_rename() -> Result<(), Box<dyn std::error::Error>> {
+    fn test_rename_batch_swap_and_chain() -> Result<(), Box<dyn std::error::Error>> {
         let temp_dir = TempDir::new()?;
-        let db_path = temp_dir.path().join("test.db");
-        let db = RenameDatabase::new(db_path);
+        let dir = temp_dir.path();
+        for name in ["a", "b", "c", "d"] {
+            fs::write(dir.join(name), name)?;
+        }
+        
+        // Swap a and b, and shift c → d → e
+        let renames = vec![
+            (dir.join("a"), dir.join("b")),
+            (dir.join("b"), dir.join("a")),
+            (dir.join("c"), dir.join("d")),
+            (dir.join("d"), dir.join("e")),
+        ];
+        assert_eq!(rename_batch(&renames), vec![RenameOutcome::Renamed; 4]);
+        assert_eq!(fs::read_to_string(dir.join("a"))?, "b");
+        assert_eq!(fs::read_to_string(dir.join("b"))?, "a");
+        assert_eq!(fs::read_to_string(dir.join("d"))?, "c");
+        assert_eq!(fs::read_to_string(dir.join("e"))?, "d");
+        assert!(!dir.join("c").exists());
+        
+        // No temporary names are left behind
+        assert_eq!(fs::read_dir(dir)?.count(), 4);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_batch_conflicts() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path();
+        for name in ["x", "y", "z", "kept", "one", "two"] {
+            File::create(dir.join(name))?;
+        }
+        
+        let renames = vec![
+            // y → z is blocked by z, which in turn blocks x → y
+            (dir.join("x"), dir.join("y")),
+            (dir.join("y"), dir.join("z")),
+            (dir.join("one"), dir.join("same")),
+            (dir.join("two"), dir.join("same")),
+            (dir.join("missing"), dir.join("found")),
+            (dir.join("kept"), dir.join("kept")),
+        ];
+        assert_eq!(batch_conflicts(&renames), vec![
+            Some(RenameOutcome::TargetExists),
+            Some(RenameOutcome::TargetExists),
+            Some(RenameOutcome::DuplicateTarget),
+            Some(RenameOutcome::DuplicateTarget),
+            Some(RenameOutcome::SourceMissing),
+            Some(RenameOutcome::Unchanged),
+        ]);
+        
+        // Nothing is renamed when every rename conflicts
+        rename_batch(&renames);
+        assert!(dir.join("x").exists());
+        assert!(dir.join("one").exists());
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_undo_tracked_swap() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path();
+        let db = RenameDatabase::new(dir.join("test.db"));
         db.initialize()?;
+        fs::write(dir.join("a"), "a")?;
+        fs::write(dir.join("b"), "b")?;
         
-        // Create a test file
-        let old_path = temp_dir.path().join("[Test] File.txt");
-        let new_path = temp_dir.path().join("File.txt");
-        File::create(&old_path)?;
+        let renames = vec![(dir.join("a"), dir.join("b")), (dir.join("b"), dir.join("a"))];
+        let outcomes = tracked_rename_batch(&db, &renames, &[], "swap", "test_op_swap");
+        assert_eq!(outcomes, vec![RenameOutcome::Renamed; 2]);
+        assert_eq!(db.get_operation_renames("test_op_swap")?.len(), 2);
+        
+        assert_eq!(db.undo_operation("test_op_swap")?, (2, 0));
+        assert_eq!(fs::read_to_string(dir.join("a"))?, "a");
+        assert_eq!(fs::read_to_string(dir.join("b"))?, "b");
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_rollback_operation() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path();
+        let db = RenameDatabase::new(dir.join("test.db"));
+        db.initialize()?;
+        File::create(dir.join("[A] one"))?;
+        File::create(dir.join("[A] two"))?;
+        
+        let renames = vec![(dir.join("[A] one"), dir.join("one")), (dir.join("[A] two"), dir.join("two"))];
+        db.begin_operation("test_op_rollback", &OperationContext::current())?;
+        tracked_rename_batch(&db, &renames, &[], "[A]", "test_op_rollback");
+        assert!(dir.join("one").exists());
+        
+        // Renamed, but the rename could not be recorded
+        File::create(dir.join("[A] three"))?;
+        db.begin_rename(&dir.join("[A] three"), &dir.join("three"), None, None, "[A]", "test_op_rollback")?;
+        fs::rename(dir.join("[A] three"), dir.join("three"))?;
+        
+        // A restored file no longer appears in the history
+        assert_eq!(db.rollback_operation("test_op_rollback")?, (3, 0));
+        assert!(dir.join("[A] one").exists());
+        assert!(dir.join("[A] two").exists());
+        assert!(dir.join("[A] three").exists());
+        assert!(db.get_operation_renames("test_op_rollback")?.is_empty());
+        assert!(db.journal_entries()?.is_empty());
+        assert!(db.operation("test_op_rollback")?.is_none());
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_recover_interrupted_renames() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path();
+        let db = RenameDatabase::new(dir.join("test.db"));
+        db.initialize()?;
+        for name in ["[A] done", "[A] pending", "[A] parked", "done"] {
+            File::create(dir.join(name))?;
+        }
+        fs::remove_file(dir.join("done"))?;
+        
+        // Renamed, but the process died before recording it
+        db.begin_rename(&dir.join("[A] done"), &dir.join("done"), None, None, "[A]", "test_op_crash")?;
+        fs::rename(dir.join("[A] done"), dir.join("done"))?;
+        
+        // Journaled, but never renamed
+        db.begin_rename(&dir.join("[A] pending"), &dir.join("pending"), None, None, "[A]", "test_op_crash")?;
+        
+        // Left under its temporary name in the middle of a batch
+        let temp_path = dir.join(".ftmi-parked.tmp");
+        let parked = db.begin_rename(&dir.join("[A] parked"), &dir.join("parked"), Some(&temp_path), None, "[A]", "test_op_crash")?;
+        fs::rename(dir.join("[A] parked"), &temp_path)?;
+        db.mark_parked(parked)?;
+        
+        let recoveries: Vec<Recovery> = db.recover()?.into_iter().map(|(_, recovery)| recovery).collect();
+        assert_eq!(recoveries, vec![Recovery::Recorded, Recovery::NotStarted, Recovery::Restored]);
+        assert!(dir.join("[A] parked").exists());
+        assert!(db.journal_entries()?.is_empty());
+        
+        // The recovered rename can be undone like any other
+        let records = db.get_operation_renames("test_op_crash")?;
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].new_path, dir.join("done"));
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_tracked_renames_leave_no_journal() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path();
+        let db = RenameDatabase::new(dir.join("test.db"));
+        db.initialize()?;
+        fs::write(dir.join("a"), "a")?;
+        fs::write(dir.join("b"), "b")?;
+        File::create(dir.join("c"))?;
+        
+        tracked_rename(&db, &dir.join("c"), &dir.join("d"), "c", "test_op_journal")?;
+        let renames = vec![(dir.join("a"), dir.join("b")), (dir.join("b"), dir.join("a"))];
+        tracked_rename_batch(&db, &renames, &[], "swap", "test_op_journal");
+        
+        assert!(db.journal_entries()?.is_empty());
+        assert_eq!(db.get_operation_renames("test_op_journal")?.len(), 3);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_resolve_collisions() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path();
+        File::create(dir.join("[B] Intro.mp3"))?;
+        File::create(dir.join("Intro.mp3"))?;
+        File::create(dir.join("Intro (2).mp3"))?;
+        let renames = vec![(dir.join("[B] Intro.mp3"), dir.join("Intro.mp3"))];
+        
+        let target = |strategy| resolve_collisions(&renames, strategy, "B").0[0].1.clone();
+        assert_eq!(target(CollisionStrategy::Skip), dir.join("[B] Intro.mp3"));
+        assert_eq!(target(CollisionStrategy::Number), dir.join("Intro (3).mp3"));
+        assert_eq!(target(CollisionStrategy::Suffix), dir.join("Intro - B.mp3"));
+        assert_eq!(target(CollisionStrategy::Subfolder), dir.join("B").join("Intro.mp3"));
+        
+        let (resolved, applied) = resolve_collisions(&renames, CollisionStrategy::Backup, "B");
+        assert_eq!(resolved, vec![
+            (dir.join("[B] Intro.mp3"), dir.join("Intro.mp3")),
+            (dir.join("Intro.mp3"), dir.join("Intro.mp3.bak")),
+        ]);
+        assert_eq!(applied, vec![Some(CollisionStrategy::Backup); 2]);
+        
+        // Of two files with the same new name the first keeps it
+        File::create(dir.join("[C] Outro.mp3"))?;
+        File::create(dir.join("[D] Outro.mp3"))?;
+        let duplicates = vec![
+            (dir.join("[C] Outro.mp3"), dir.join("Outro.mp3")),
+            (dir.join("[D] Outro.mp3"), dir.join("Outro.mp3")),
+        ];
+        let (resolved, applied) = resolve_collisions(&duplicates, CollisionStrategy::Backup, "");
+        assert_eq!(resolved[0].1, dir.join("Outro.mp3"));
+        assert_eq!(resolved[1].1, dir.join("Outro (2).mp3"));
+        assert_eq!(applied, vec![None, Some(CollisionStrategy::Backup)]);
+        
+        // A removed text that is not a plain name is never used in a path
+        for tag in ["../../x", "a/b", "..", ".", "a\0b", ""] {
+            let (resolved, applied) = resolve_collisions(&renames, CollisionStrategy::Subfolder, tag);
+            assert_eq!(resolved[0].1, dir.join("Intro (3).mp3"), "tag {:?}", tag);
+            assert_eq!(applied, vec![Some(CollisionStrategy::Number)]);
+            assert_eq!(resolve_collisions(&renames, CollisionStrategy::Suffix, tag).0[0].1, dir.join("Intro (3).mp3"));
+        }
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_collision_backup_and_subfolder_undo() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path();
+        let db = RenameDatabase::new(dir.join("test.db"));
+        db.initialize()?;
+        fs::write(dir.join("[B] Intro.mp3"), "new")?;
+        fs::write(dir.join("[B] Outro.mp3"), "new")?;
+        fs::write(dir.join("Intro.mp3"), "old")?;
+        fs::write(dir.join("Outro.mp3"), "old")?;
+        
+        let (renames, collisions) = resolve_collisions(
+            &[(dir.join("[B] Intro.mp3"), dir.join("Intro.mp3"))], CollisionStrategy::Backup, "B");
+        let outcomes = tracked_rename_batch(&db, &renames, &collisions, "[B]", "test_op_backup");
+        assert_eq!(outcomes, vec![RenameOutcome::Renamed; 2]);
+        assert_eq!(fs::read_to_string(dir.join("Intro.mp3"))?, "new");
+        assert_eq!(fs::read_to_string(dir.join("Intro.mp3.bak"))?, "old");
+        
+        let records = db.get_operation_renames("test_op_backup")?;
+        assert!(records.iter().all(|record| record.collision == Some(CollisionStrategy::Backup)));
+        
+        let (renames, collisions) = resolve_collisions(
+            &[(dir.join("[B] Outro.mp3"), dir.join("Outro.mp3"))], CollisionStrategy::Subfolder, "B");
+        tracked_rename_batch(&db, &renames, &collisions, "[B]", "test_op_subfolder");
+        assert_eq!(fs::read_to_string(dir.join("B").join("Outro.mp3"))?, "new");
+        
+        // Undo puts the existing files back and removes the empty subfolder
+        assert_eq!(db.undo_operation("test_op_backup")?, (2, 0));
+        assert_eq!(db.undo_operation("test_op_subfolder")?, (1, 0));
+        assert_eq!(fs::read_to_string(dir.join("Intro.mp3"))?, "old");
+        assert_eq!(fs::read_to_string(dir.join("[B] Intro.mp3"))?, "new");
+        assert!(!dir.join("Intro.mp3.bak").exists());
+        assert!(dir.join("[B] Outro.mp3").exists());
+        assert!(!dir.join("B").exists());
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_collision_skip() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path();
+        let db = RenameDatabase::new(dir.join("test.db"));
+        db.initialize()?;
+        File::create(dir.join("[A] Intro.mp3"))?;
+        File::create(dir.join("[B] Intro.mp3"))?;
+        
+        let (renames, collisions) = resolve_collisions(&[
+            (dir.join("[A] Intro.mp3"), dir.join("Intro.mp3")),
+            (dir.join("[B] Intro.mp3"), dir.join("Intro.mp3")),
+        ], CollisionStrategy::Skip, "");
+        let outcomes = tracked_rename_batch(&db, &renames, &collisions, "", "test_op_skip");
+        assert_eq!(outcomes, vec![RenameOutcome::Renamed, RenameOutcome::Skipped]);
+        assert!(dir.join("Intro.mp3").exists());
+        assert!(dir.join("[B] Intro.mp3").exists());
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_operation_ids_are_unique() {
+        let first = generate_operation_id();
+        let second = generate_operation_id();
+        assert_ne!(first, second);
+        assert!(first.starts_with("op_"));
+        assert!(!is_legacy_operation_id(&first));
+        assert!(is_legacy_operation_id("op_1700000000"));
+    }
+    
+    #[test]
+    fn test_split_merged_operation() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path();
+        let db = RenameDatabase::new(dir.join("test.db"));
+        db.initialize()?;
+        
+        // Two groups confirmed within the same second by an older version
+        db.record_rename(&dir.join("[A] one"), &dir.join("one"), dir, "[A]", "op_1700000000")?;
+        db.record_rename(&dir.join("[B] two"), &dir.join("two"), dir, "[B]", "op_1700000000")?;
+        db.record_rename(&dir.join("[B] three"), &dir.join("three"), dir, "[B]", "op_1700000000")?;
+        db.record_rename(&dir.join("[C] four"), &dir.join("four"), dir, "[C]", "op_1700000001")?;
+        
+        assert_eq!(db.merged_operations()?, vec!["op_1700000000"]);
+        assert_eq!(db.split_operation("op_1700000000")?, vec!["op_1700000000-1", "op_1700000000-2"]);
+        assert_eq!(db.get_operation_renames("op_1700000000-1")?.len(), 1);
+        assert_eq!(db.get_operation_renames("op_1700000000-2")?.len(), 2);
+        assert!(db.get_operation_renames("op_1700000000")?.is_empty());
+        
+        assert!(db.merged_operations()?.is_empty());
+        assert!(db.split_operation("op_1700000001")?.is_empty());
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_undo_and_redo_are_recorded() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path();
+        let db = RenameDatabase::new(dir.join("test.db"));
+        db.initialize()?;
+        File::create(dir.join("[A] one"))?;
+        File::create(dir.join("[A] two"))?;
+        
+        let renames = vec![(dir.join("[A] one"), dir.join("one")), (dir.join("[A] two"), dir.join("two"))];
+        tracked_rename_batch(&db, &renames, &[], "[A]", "op_rename");
+        assert_eq!(db.reverted_by("op_rename")?, None);
+        
+        assert_eq!(db.undo_operation("op_rename")?, (2, 0));
+        let undo_id = db.reverted_by("op_rename")?.expect("undo is recorded");
+        assert_eq!(db.operation_kind(&undo_id)?, (OperationKind::Undo, Some("op_rename".to_string())));
+        assert_eq!(db.get_operation_renames(&undo_id)?.len(), 2);
+        assert!(dir.join("[A] one").exists());
+        
+        // Redo reverts the undo, which puts the original operation back in effect
+        assert_eq!(db.redo_operation(&undo_id)?, (2, 0));
+        let redo_id = db.reverted_by(&undo_id)?.expect("redo is recorded");
+        assert_eq!(db.operation_kind(&redo_id)?.0, OperationKind::Redo);
+        assert_eq!(db.reverted_by("op_rename")?, None);
+        assert!(dir.join("one").exists());
+        assert!(dir.join("two").exists());
+        
+        // An undo that restores nothing is not recorded
+        fs::remove_file(dir.join("one"))?;
+        fs::remove_file(dir.join("two"))?;
+        assert_eq!(db.undo_operation(&redo_id)?, (0, 2));
+        assert_eq!(db.reverted_by(&redo_id)?, None);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_undo_of_redo_reverts_the_original() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path();
+        let db = RenameDatabase::new(dir.join("test.db"));
+        db.initialize()?;
+        File::create(dir.join("[A] one"))?;
+        
+        tracked_rename_batch(&db, &[(dir.join("[A] one"), dir.join("one"))], &[], "[A]", "op_rename");
+        assert_eq!(db.undo_operation("op_rename")?, (1, 0));
+        let undo_id = db.reverted_by("op_rename")?.expect("undo is recorded");
+        assert_eq!(db.redo_operation(&undo_id)?, (1, 0));
+        let redo_id = db.reverted_by(&undo_id)?.expect("redo is recorded");
+        assert_eq!(db.reverted_by("op_rename")?, None);
+        
+        // Undoing the redo takes the original operation out of effect as well
+        assert_eq!(db.undo_operation(&redo_id)?, (1, 0));
+        assert!(dir.join("[A] one").exists());
+        let second_undo = db.reverted_by(&redo_id)?.expect("undo is recorded");
+        assert_eq!(db.reverted_by("op_rename")?, Some(second_undo.clone()));
+        assert_eq!(db.get_operation_renames("op_rename")?[0].reverted_by, Some(second_undo.clone()));
+        
+        // Redoing that undo puts both back in effect
+        assert_eq!(db.redo_operation(&second_undo)?, (1, 0));
+        assert!(dir.join("one").exists());
+        assert_eq!(db.reverted_by("op_rename")?, None);
+        assert_eq!(db.reverted_by(&redo_id)?, None);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_partial_undo() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path();
+        let db = RenameDatabase::new(dir.join("test.db"));
+        db.initialize()?;
+        for name in ["[A] one", "[A] two", "[A] three"] {
+            File::create(dir.join(name))?;
+        }
+        
+        let renames: Vec<(PathBuf, PathBuf)> = ["one", "two", "three"].iter()
+            .map(|name| (dir.join(format!("[A] {}", name)), dir.join(name)))
+            .collect();
+        tracked_rename_batch(&db, &renames, &[], "[A]", "op_partial");
+        let records = db.get_operation_renames("op_partial")?;
+        
+        assert_eq!(db.undo_with_options("op_partial", &UndoOptions { only: Some(&[records[1].id]), ..Default::default() })?, (1, 0));
+        assert!(dir.join("[A] two").exists());
+        assert!(dir.join("one").exists());
+        assert_eq!(db.reverted_by("op_partial")?, None);
+        let undo_id = db.reverting_operations("op_partial")?.remove(0);
+        
+        // A full undo restores the rest without touching the file already restored
+        assert_eq!(db.undo_operation("op_partial")?, (2, 0));
+        assert!(dir.join("[A] one").exists());
+        assert!(dir.join("[A] three").exists());
+        assert!(db.reverted_by("op_partial")?.is_some());
+        
+        // Redoing the partial undo only renames its file again
+        assert_eq!(db.redo_operation(&undo_id)?, (1, 0));
+        assert!(dir.join("two").exists());
+        assert!(dir.join("[A] one").exists());
+        let records = db.get_operation_renames("op_partial")?;
+        assert_eq!(records.iter().filter(|record| record.reverted_by.is_none()).count(), 1);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_undo_through_later_renames() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path();
+        let db = RenameDatabase::new(dir.join("test.db"));
+        db.initialize()?;
+        fs::write(dir.join("[A] [B] song"), "song")?;
+        
+        tracked_rename(&db, &dir.join("[A] [B] song"), &dir.join("[B] song"), "[A]", "op_first")?;
+        tracked_rename(&db, &dir.join("[B] song"), &dir.join("song"), "[B]", "op_second")?;
+        
+        let record = db.get_operation_renames("op_first")?.remove(0);
+        let chain = db.later_renames(&record)?;
+        assert_eq!(chain.len(), 1);
+        assert_eq!(chain[0].new_path, dir.join("song"));
+        assert_eq!(db.dependent_operations("op_first")?, vec!["op_second"]);
+        
+        // Without following, the file state changed
+        assert_eq!(db.undo_with_options("op_first", &UndoOptions { only: Some(&[record.id]), ..Default::default() })?, (0, 1));
+        // The failed undo is kept, but does not count as undoing the operation
+        assert_eq!(db.stats()?.undo_operations, 0);
+        assert_eq!(db.stats()?.undone_operations, 0);
+        
+        assert_eq!(db.undo_with_options("op_first", &UndoOptions { only: Some(&[record.id]), follow: true, ..Default::default() })?, (1, 0));
+        assert_eq!(fs::read_to_string(dir.join("[A] [B] song"))?, "song");
+        let undo_id = db.reverted_by("op_first")?.expect("first operation is undone");
+        assert_eq!(db.reverted_by("op_second")?, Some(undo_id.clone()));
+        
+        // Redo puts the file back at its latest name and both renames in effect
+        assert_eq!(db.redo_operation(&undo_id)?, (1, 0));
+        assert_eq!(fs::read_to_string(dir.join("song"))?, "song");
+        assert_eq!(db.reverted_by("op_first")?, None);
+        assert_eq!(db.reverted_by("op_second")?, None);
+        
+        Ok(())
+    }
+
+    
+    #[test]
+    fn test_undo_checks_file_identity() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path();
+        let db = RenameDatabase::new(dir.join("test.db"));
+        db.initialize()?;
+        fs::write(dir.join("[A] one"), "one")?;
+        fs::write(dir.join("[A] two"), "two")?;
+        
+        let renames = vec![
+            (dir.join("[A] one"), dir.join("one")),
+            (dir.join("[A] two"), dir.join("two")),
+        ];
+        tracked_rename_batch(&db, &renames, &[], "[A]", "op_identity");
+        let records = db.get_operation_renames("op_identity")?;
+        assert!(records.iter().all(|record| record.identity.as_ref().is_some_and(|identity| identity.content_hash.is_some())));
+        
+        // Another file took the first name, the second file was edited
+        fs::write(dir.join("replacement"), "one")?;
+        fs::rename(dir.join("replacement"), dir.join("one"))?;
+        fs::write(dir.join("two"), "two, edited")?;
+        
+        let mut outcomes = Vec::new();
+        assert_eq!(db.undo_operation_with("op_identity", |current, _, outcome| {
+            outcomes.push((current.to_path_buf(), outcome.clone()));
+        })?, (0, 2));
+        assert!(outcomes.contains(&(dir.join("two"), RenameOutcome::IdentityMismatch(IdentityMismatch::SizeChanged))));
+        #[cfg(unix)]
+        assert!(outcomes.contains(&(dir.join("one"), RenameOutcome::IdentityMismatch(IdentityMismatch::DifferentFile))));
+        assert!(dir.join("two").exists());
+        assert_eq!(db.reverted_by("op_identity")?, None);
+        
+        // --force renames them back anyway
+        assert_eq!(db.undo_with_options("op_identity", &UndoOptions { force: true, ..Default::default() })?, (2, 0));
+        assert!(dir.join("[A] one").exists());
+        assert_eq!(fs::read_to_string(dir.join("[A] two"))?, "two, edited");
+        
+        Ok(())
+    }
+
+    
+    #[test]
+    fn test_operation_details() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path();
+        let db = RenameDatabase::new(dir.join("test.db"));
+        db.initialize()?;
+        File::create(dir.join("[A] one"))?;
         
+        let context = OperationContext {
+            settings: Some(r#"{"regex":"\\[.*\\]"}"#.to_string()),
+            note: Some("cleanup".to_string()),
+            tags: vec!["music".to_string()],
+            ..OperationContext::current()
+        };
         let operation_id = generate_operation_id();
+        db.begin_operation(&operation_id, &context)?;
+        tracked_rename(&db, &dir.join("[A] one"), &dir.join("one"), "[A]", &operation_id)?;
+        db.finish_operation(&operation_id, 1, 0, Duration::from_millis(5))?;
         
-        // Perform tracked rename
-        tracked_rename(&db, &old_path, &new_path, "Test", &operation_id)?;
+        let operation = db.operation(&operation_id)?.ok_or("operation not recorded")?;
+        assert_eq!(operation.kind, OperationKind::Rename);
+        assert_eq!(operation.context, context);
+        assert_eq!(operation.context.version, env!("CARGO_PKG_VERSION"));
+        assert_eq!((operation.success_count, operation.failure_count, operation.duration_ms), (Some(1), Some(0), Some(5)));
         
-        // Verify file was renamed
-        assert!(!old_path.exists());
-        assert!(new_path.exists());
+        db.annotate_operation(&operation_id, None, &["music".to_string(), "2024".to_string()])?;
+        let operation = db.operation(&operation_id)?.ok_or("operation not recorded")?;
+        assert_eq!(operation.context.note.as_deref(), Some("cleanup"));
+        assert_eq!(operation.context.tags, vec!["music", "2024"]);
         
-        // Verify database record
-        let renames = db.get_operation_renames(&operation_id)?;
-        assert_eq!(renames.len(), 1);
+        // The undo is recorded with the context of whoever ran it
+        db.undo_operation(&operation_id)?;
+        let undo_id = db.reverted_by(&operation_id)?.ok_or("not undone")?;
+        let undo = db.operation(&undo_id)?.ok_or("undo not recorded")?;
+        assert_eq!(undo.kind, OperationKind::Undo);
+        assert_eq!(undo.reverts.as_deref(), Some(operation_id.as_str()));
+        assert_eq!(undo.success_count, Some(1));
+        
+        // Operations recorded by older versions can be annotated too
+        File::create(dir.join("[B] two"))?;
+        tracked_rename(&db, &dir.join("[B] two"), &dir.join("two"), "[B]", "op_1700000000")?;
+        assert_eq!(db.operation("op_1700000000")?, None);
+        db.annotate_operation("op_1700000000", Some("from the old laptop"), &[])?;
+        let legacy = db.operation("op_1700000000")?.ok_or("legacy operation not annotated")?;
+        assert_eq!(legacy.context.note.as_deref(), Some("from the old laptop"));
+        assert!(db.annotate_operation("op_missing", Some("note"), &[]).is_err());
+        
+        Ok(())
+    }
+
+    
+    #[test]
+    fn test_project_path_walks_up() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let root = fs::canonicalize(temp_dir.path())?;
+        let nested = root.join("library").join("artist").join("album");
+        fs::create_dir_all(&nested)?;
+        assert_eq!(RenameDatabase::project_path(&nested), None);
+        
+        fs::create_dir(root.join("library").join(DATABASE_DIR))?;
+        let expected = root.join("library").join(DATABASE_DIR).join(DATABASE_FILE);
+        assert_eq!(RenameDatabase::project_path(&nested), Some(expected.clone()));
+        assert_eq!(RenameDatabase::project_path(&root.join("library")), Some(expected));
+        
+        // The closest one wins
+        fs::create_dir(nested.join(DATABASE_DIR))?;
+        assert_eq!(RenameDatabase::project_path(&nested), Some(nested.join(DATABASE_DIR).join(DATABASE_FILE)));
+        
+        let explicit = root.join("elsewhere").join("history.db");
+        let (path, source) = RenameDatabase::locate(Some(&explicit), &nested)?;
+        assert_eq!((path, source), (explicit.clone(), DatabaseSource::Flag));
+        assert!(root.join("elsewhere").is_dir());
+        
+        Ok(())
+    }
+
+    
+    #[test]
+    fn test_import_history() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path();
+        let laptop = RenameDatabase::new(dir.join("laptop.db"));
+        laptop.initialize()?;
+        File::create(dir.join("[A] one"))?;
+        File::create(dir.join("[A] two"))?;
+        
+        let operation_id = generate_operation_id();
+        let context = OperationContext { note: Some("from the laptop".to_string()), ..OperationContext::current() };
+        laptop.begin_operation(&operation_id, &context)?;
+        tracked_rename(&laptop, &dir.join("[A] one"), &dir.join("one"), "[A]", &operation_id)?;
+        tracked_rename(&laptop, &dir.join("[A] two"), &dir.join("two"), "[A]", &operation_id)?;
+        laptop.finish_operation(&operation_id, 2, 0, Duration::from_millis(3))?;
+        let export = HistoryExport::from_database(&laptop, &laptop.operation_ids()?)?;
+        
+        let desktop = RenameDatabase::new(dir.join("desktop.db"));
+        desktop.initialize()?;
+        let summary = desktop.import_history(&export)?;
+        assert_eq!((summary.operations, summary.renames), (1, 2));
+        assert_eq!(desktop.operation(&operation_id)?, laptop.operation(&operation_id)?);
+        
+        let records = desktop.get_operation_renames(&operation_id)?;
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].new_path, dir.join("one"));
+        let identity = records[0].identity.as_ref().ok_or("identity not imported")?;
+        assert_eq!((identity.device, identity.inode), (None, None));
+        assert!(identity.content_hash.is_some());
+        
+        // Importing again changes nothing
+        let summary = desktop.import_history(&export)?;
+        assert_eq!(summary.operations, 0);
+        assert_eq!(summary.skipped, vec![operation_id.clone()]);
+        assert_eq!(desktop.get_operation_renames(&operation_id)?.len(), 2);
+        
+        // The imported operation can be undone
+        assert_eq!(desktop.undo_operation(&operation_id)?, (2, 0));
+        assert!(dir.join("[A] one").exists());
+        Ok(())
+    }
+
+    
+    #[test]
+    fn test_query_operations_and_lineage() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let music = temp_dir.path().join("music");
+        let rock = music.join("rock");
+        fs::create_dir_all(&rock)?;
+        let db = RenameDatabase::new(temp_dir.path().join("test.db"));
+        db.initialize()?;
+        File::create(rock.join("[A] [Live] song.mp3"))?;
+        File::create(temp_dir.path().join("[B] notes.txt"))?;
+        
+        let first = generate_operation_id();
+        tracked_rename(&db, &rock.join("[A] [Live] song.mp3"), &rock.join("[Live] song.mp3"), "[A]", &first)?;
+        let second = generate_operation_id();
+        db.begin_operation(&second, &OperationContext { tags: vec!["docs".to_string()], ..OperationContext::current() })?;
+        tracked_rename(&db, &temp_dir.path().join("[B] notes.txt"), &temp_dir.path().join("notes.txt"), "[B]", &second)?;
+        let third = generate_operation_id();
+        tracked_rename(&db, &rock.join("[Live] song.mp3"), &rock.join("song.mp3"), "[Live]", &third)?;
+        
+        let all = db.query_operations(&HistoryQuery::default())?;
+        assert_eq!(all, vec![third.clone(), second.clone(), first.clone()]);
+        
+        let in_music = HistoryQuery { directory: Some(music.clone()), ..HistoryQuery::default() };
+        assert_eq!(db.query_operations(&in_music)?, vec![third.clone(), first.clone()]);
+        let page = HistoryQuery { offset: 1, limit: Some(1), ..in_music };
+        assert_eq!(db.query_operations(&page)?, vec![first.clone()]);
+        
+        let prefix = HistoryQuery { prefix: Some("Liv".to_string()), ..HistoryQuery::default() };
+        assert_eq!(db.query_operations(&prefix)?, vec![third.clone()]);
+        let name = HistoryQuery { name: Some(glob::Pattern::new("*.txt")?), ..HistoryQuery::default() };
+        assert_eq!(db.query_operations(&name)?, vec![second.clone()]);
+        let tagged = HistoryQuery { tags: vec!["docs".to_string()], ..HistoryQuery::default() };
+        assert_eq!(db.query_operations(&tagged)?, vec![second.clone()]);
+        let future = HistoryQuery { since: Some(Utc::now() + chrono::Duration::days(1)), ..HistoryQuery::default() };
+        assert!(db.query_operations(&future)?.is_empty());
+        
+        // Traced from the current name, an intermediate one, or after an undo
+        let lineage = db.path_lineage(&rock.join("song.mp3"))?;
+        let operations: Vec<&str> = lineage.iter().map(|record| record.operation_id.as_str()).collect();
+        assert_eq!(operations, vec![first.as_str(), third.as_str()]);
+        assert_eq!(lineage[0].old_path, rock.join("[A] [Live] song.mp3"));
+        assert_eq!(db.path_lineage(&rock.join("[Live] song.mp3"))?.len(), 2);
+        
+        db.undo_operation(&third)?;
+        let lineage = db.path_lineage(&rock.join("[Live] song.mp3"))?;
+        assert_eq!(lineage.len(), 3);
+        assert_eq!(lineage[2].new_path, rock.join("[Live] song.mp3"));
+        assert!(db.path_lineage(&rock.join("unknown.mp3"))?.is_empty());
+        Ok(())
+    }
+
+    
+    #[test]
+    fn test_prune_keeps_undoable_operations() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let dir = temp_dir.path();
+        let db = RenameDatabase::new(dir.join("test.db"));
+        db.initialize()?;
+        let mut operations = Vec::new();
+        for name in ["one", "two", "three"] {
+            File::create(dir.join(format!("[A] {}", name)))?;
+            let operation_id = generate_operation_id();
+            tracked_rename(&db, &dir.join(format!("[A] {}", name)), &dir.join(name), "[A]", &operation_id)?;
+            operations.push(operation_id);
+        }
+        db.undo_operation(&operations[0])?;
+        let undo_id = db.reverted_by(&operations[0])?.ok_or("not undone")?;
+        
+        // An operation that renamed nothing is kept, but not counted
+        let failed = generate_operation_id();
+        db.begin_operation(&failed, &OperationContext::current())?;
+        db.finish_operation(&failed, 0, 1, Duration::from_millis(1))?;
+        let record = db.operation(&failed)?.ok_or("failed operation not kept")?;
+        assert_eq!((record.success_count, record.failure_count), (Some(0), Some(1)));
+        
+        let stats = db.stats()?;
+        assert_eq!((stats.rename_operations, stats.undo_operations, stats.files_renamed), (3, 1, 3));
+        assert_eq!(stats.undone_operations, 1);
+        assert_eq!(stats.prefixes, vec![("[A]".to_string(), 3)]);
+        assert_eq!(stats.operations_per_day.iter().map(|(_, count)| count).sum::<usize>(), 3);
+        
+        // Only the undone and the failed operation can go; the undo itself could be redone
+        let everything = PruneOptions { keep_last: Some(0), dry_run: true, ..PruneOptions::default() };
+        let summary = db.prune(&everything)?;
+        assert_eq!(summary.operations, vec![operations[0].clone(), failed.clone()]);
+        assert_eq!(summary.kept_undoable, vec![operations[1].clone(), operations[2].clone(), undo_id.clone()]);
+        assert_eq!(db.get_operation_renames(&operations[0])?.len(), 1);
+        
+        let recent = PruneOptions { older_than: Some(Utc::now() - chrono::Duration::days(1)), ..PruneOptions::default() };
+        assert!(db.prune(&recent)?.operations.is_empty());
+        
+        let summary = db.prune(&PruneOptions { keep_last: Some(0), ..PruneOptions::default() })?;
+        assert_eq!((summary.operations.len(), summary.renames), (2, 1));
+        assert!(db.get_operation_renames(&operations[0])?.is_empty());
+        assert_eq!(db.operation(&failed)?, None);
+        db.vacuum()?;
         
+        // Forced, the two most recent operations are kept anyway
+        let summary = db.prune(&PruneOptions { keep_last: Some(2), force: true, ..PruneOptions::default() })?;
+        assert_eq!(summary.operations, vec![operations[1].clone()]);
+        assert_eq!(db.query_operations(&HistoryQuery::default())?, vec![undo_id, operations[2].clone()]);
         Ok(())
     }
 }
\ No newline at end of file
@@ -0,0 +1,276 @@
+use std::collections::HashMap;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use crate::os_names::{display_name, name_from_bytes, name_to_bytes};
+use crate::rename_db::{batch_conflicts, RenameOutcome};
+use crate::rename_plan::PlanGroup;
+
+/// A problem found in an edited rename buffer
+#[derive(Debug, Clone, PartialEq)]
+pub enum EditError {
+    /// A line that is neither a comment nor `NUMBER<TAB>NAME`
+    Malformed { line: usize },
+    /// A line refers to a file number that was not in the buffer
+    UnknownEntry { line: usize, number: usize },
+    /// The same file number appears on more than one line
+    RepeatedEntry { line: usize, number: usize },
+    /// The new name is empty, "." or ".." or contains a path separator
+    InvalidName { number: usize, name: OsString },
+    /// Two files would end up with the same name
+    DuplicateTarget { number: usize, other: usize },
+    /// The new name is already taken by another file on disk
+    TargetExists { number: usize, path: PathBuf },
+    /// A name contains a line break, which the line-based buffer cannot hold
+    LineBreak { path: PathBuf },
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EditError::Malformed { line } => write!(f, "line {}: expected NUMBER<TAB>NAME", line),
+            EditError::UnknownEntry { line, number } => write!(f, "line {}: there is no file {}", line, number),
+            EditError::RepeatedEntry { line, number } => write!(f, "line {}: file {} is listed more than once", line, number),
+            EditError::InvalidName { number, name } => write!(f, "file {}: invalid name '{}'", number, display_name(name)),
+            EditError::DuplicateTarget { number, other } => write!(f, "file {}: same new name as file {}", number, other),
+            EditError::TargetExists { number, path } => {
+                write!(f, "file {}: {} already exists", number, display_name(path.as_os_str()))
+            }
+            EditError::LineBreak { path } => {
+                write!(f, "{} contains a line break and cannot be edited", display_name(path.as_os_str()).escape_debug())
+            }
+        }
+    }
+}
+
+impl std::error::Error for EditError {}
+
+/// Write the proposed new names of `groups` as an editable buffer.
+/// Every file gets a `NUMBER<TAB>NEW NAME` line preceded by a comment with its
+/// current name. Names are written as raw bytes so non-UTF-8 names survive.
+/// Fails for names with line breaks, which would read back as extra lines.
+pub fn edit_buffer(groups: &[PlanGroup]) -> Result<Vec<u8>, EditError> {
+    let has_line_break = |path: &Path| name_to_bytes(path.as_os_str()).iter().any(|b| *b == b'\n' || *b == b'\r');
+    for group in groups {
+        let paths = group.renames.iter().flat_map(|rename| [group.directory.join(&rename.from), group.directory.join(&rename.to)]);
+        if let Some(path) = std::iter::once(group.directory.clone()).chain(paths).find(|path| has_line_break(path)) {
+            return Err(EditError::LineBreak { path });
+        }
+    }
+    
+    let mut buffer = Vec::new();
+    buffer.extend_from_slice(b"# Edit the new names after the tab, then save and quit.\n");
+    buffer.extend_from_slice(b"# Delete a line to leave that file unchanged. Lines starting with # are ignored.\n");
+    
+    let mut number = 0;
+    for group in groups {
+        buffer.extend_from_slice(format!(
+            "\n# Directory: {}\n# {}: {}\n",
+            display_name(group.directory.as_os_str()), group.kind.title(), group.label()
+        ).as_bytes());
+        
+        for rename in &group.renames {
+            number += 1;
+            buffer.extend_from_slice(b"# ");
+            buffer.extend_from_slice(&name_to_bytes(rename.from.as_os_str()));
+            buffer.extend_from_slice(format!("\n{}\t", number).as_bytes());
+            buffer.extend_from_slice(&name_to_bytes(rename.to.file_name().unwrap_or_default()));
+            buffer.push(b'\n');
+        }
+    }
+    
+    Ok(buffer)
+}
+
+/// Parse an edited buffer written by `edit_buffer` and update the new names of
+/// `groups`. Files whose line was deleted keep their current name. Nothing is
+/// changed unless the whole buffer is valid, in which case the number of files
+/// that will be renamed is returned.
+pub fn apply_edits(groups: &mut [PlanGroup], buffer: &[u8]) -> Result<usize, Vec<EditError>> {
+    let entries: Vec<(usize, usize)> = groups.iter()
+        .enumerate()
+        .flat_map(|(g, group)| (0..group.renames.len()).map(move |r| (g, r)))
+        .collect();
+    
+    let mut errors = Vec::new();
+    let mut edited: HashMap<usize, OsString> = HashMap::new();
+    
+    for (index, line) in buffer.split(|b| *b == b'\n').enumerate() {
+        let line_number = index + 1;
+        let line = line.strip_suffix(b"\r").unwrap_or(line);
+        if line.iter().all(|b| b.is_ascii_whitespace()) || line.starts_with(b"#") {
+            continue;
+        }
+        
+        let Some(tab) = line.iter().position(|b| *b == b'\t') else {
+            errors.push(EditError::Malformed { line: line_number });
+            continue;
+        };
+        let Some(number) = std::str::from_utf8(&line[..tab]).ok().and_then(|n| n.trim().parse::<usize>().ok()) else {
+            errors.push(EditError::Malformed { line: line_number });
+            continue;
+        };
+        
+        if number == 0 || number > entries.len() {
+            errors.push(EditError::UnknownEntry { line: line_number, number });
+        } else if edited.insert(number, name_from_bytes(line[tab + 1..].to_vec())).is_some() {
+            errors.push(EditError::RepeatedEntry { line: line_number, number });
+        }
+    }
+    
+    // Resolve every file's (old path, new path), keeping deleted lines unchanged
+    let mut targets: Vec<(PathBuf, PathBuf)> = Vec::with_capacity(entries.len());
+    for (i, &(g, r)) in entries.iter().enumerate() {
+        let group = &groups[g];
+        let from = &group.renames[r].from;
+        let to = match edited.get(&(i + 1)) {
+            Some(name) => {
+                if !is_valid_name(name) {
+                    errors.push(EditError::InvalidName { number: i + 1, name: name.clone() });
+                }
+                from.with_file_name(name)
+            }
+            None => from.clone(),
+        };
+        targets.push((group.directory.join(from), group.directory.join(to)));
+    }
+    
+    let mut seen: HashMap<&Path, usize> = HashMap::new();
+    for (i, (_, new_path)) in targets.iter().enumerate() {
+        if let Some(other) = seen.insert(new_path, i + 1) {
+            errors.push(EditError::DuplicateTarget { number: i + 1, other });
+        }
+    }
+    
+    // Files may take each other's names (swaps, chains) as long as the file
+    // currently using the name is renamed as well. Duplicates already block
+    // the files they involve, so only look for collisions without them.
+    if !errors.iter().any(|error| matches!(error, EditError::DuplicateTarget { .. })) {
+        for (i, conflict) in batch_conflicts(&targets).into_iter().enumerate() {
+            if conflict == Some(RenameOutcome::TargetExists) {
+                errors.push(EditError::TargetExists { number: i + 1, path: targets[i].1.clone() });
+            }
+        }
+    }
+    
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    
+    for (i, &(g, r)) in entries.iter().enumerate() {
+        let rename = &mut groups[g].renames[r];
+        rename.to = match edited.remove(&(i + 1)) {
+            Some(name) => rename.from.with_file_name(name),
+            None => rename.from.clone(),
+        };
+    }
+    
+    Ok(targets.iter().filter(|(old_path, new_path)| old_path != new_path).count())
+}
+
+/// Whether `name` can be used as a new file name in the same directory
+pub(crate) fn is_valid_name(name: &OsStr) -> bool {
+    let bytes = name_to_bytes(name);
+    !bytes.is_empty()
+        && bytes.as_ref() != b"."
+        && bytes.as_ref() != b".."
+        && !bytes.iter().any(|b| *b == b'/' || *b == 0 || (cfg!(windows) && *b == b'\\'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rename_plan::{Affix, PlannedRename};
+    use std::fs::File;
+    use tempfile::TempDir;
+    
+    fn group(directory: &Path, names: &[(&str, &str)]) -> PlanGroup {
+        PlanGroup {
+            directory: directory.to_path_buf(),
+            kind: Affix::Prefix,
+            affix: "Artist".to_string(),
+            delimiter: Some(("[".to_string(), "]".to_string())),
+            renames: names.iter()
+                .map(|(from, to)| PlannedRename { from: PathBuf::from(from), to: PathBuf::from(to) })
+                .collect(),
+        }
+    }
+    
+    fn edit(groups: &mut [PlanGroup], find: &str, replace: &str) -> Result<usize, Vec<EditError>> {
+        let buffer = String::from_utf8(edit_buffer(groups).unwrap()).unwrap().replace(find, replace);
+        apply_edits(groups, buffer.as_bytes())
+    }
+    
+    #[test]
+    fn test_unedited_buffer_keeps_proposals() {
+        let mut groups = vec![group(Path::new("/music"), &[("[Artist] One.mp3", "One.mp3"), ("[Artist] Two.mp3", "Two.mp3")])];
+        let original = groups.clone();
+        
+        assert_eq!(edit(&mut groups, "", ""), Ok(2));
+        assert_eq!(groups, original);
+    }
+    
+    #[test]
+    fn test_edits_and_deleted_lines() {
+        let mut groups = vec![group(Path::new("/music"), &[("[Artist] One.mp3", "One.mp3"), ("[Artist] Two.mp3", "Two.mp3")])];
+        
+        assert_eq!(edit(&mut groups, "1\tOne.mp3\n", "1\t01 One.mp3\r\n"), Ok(2));
+        assert_eq!(groups[0].renames[0].to, PathBuf::from("01 One.mp3"));
+        
+        // A deleted line leaves the file unchanged
+        assert_eq!(edit(&mut groups, "2\tTwo.mp3\n", ""), Ok(1));
+        assert_eq!(groups[0].renames[1].to, PathBuf::from("[Artist] Two.mp3"));
+    }
+    
+    #[test]
+    fn test_rejects_invalid_buffers() {
+        let mut groups = vec![group(Path::new("/music"), &[("[Artist] One.mp3", "One.mp3"), ("[Artist] Two.mp3", "Two.mp3")])];
+        let original = groups.clone();
+        
+        assert_eq!(edit(&mut groups, "1\tOne.mp3", "One.mp3"), Err(vec![EditError::Malformed { line: 7 }]));
+        assert_eq!(edit(&mut groups, "1\tOne.mp3", "3\tOne.mp3"), Err(vec![EditError::UnknownEntry { line: 7, number: 3 }]));
+        assert_eq!(edit(&mut groups, "2\tTwo.mp3", "1\tTwo.mp3"), Err(vec![EditError::RepeatedEntry { line: 9, number: 1 }]));
+        assert_eq!(
+            edit(&mut groups, "1\tOne.mp3", "1\tsub/One.mp3"),
+            Err(vec![EditError::InvalidName { number: 1, name: OsString::from("sub/One.mp3") }])
+        );
+        assert_eq!(edit(&mut groups, "1\tOne.mp3", "1\t"), Err(vec![EditError::InvalidName { number: 1, name: OsString::new() }]));
+        assert_eq!(edit(&mut groups, "2\tTwo.mp3", "2\tOne.mp3"), Err(vec![EditError::DuplicateTarget { number: 2, other: 1 }]));
+        
+        // Nothing is changed when the buffer is rejected
+        assert_eq!(groups, original);
+    }
+    
+    #[test]
+    fn test_buffer_rejects_line_breaks() {
+        // The comment line of this name would otherwise add an entry for file 2
+        let groups = vec![group(Path::new("/music"), &[("[A] x\n2\tevil", "x\n2\tevil"), ("[A] Two.mp3", "Two.mp3")])];
+        assert_eq!(edit_buffer(&groups), Err(EditError::LineBreak { path: PathBuf::from("/music/[A] x\n2\tevil") }));
+    }
+    
+    #[test]
+    fn test_rejects_collisions() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let dir_path = temp_dir.path();
+        File::create(dir_path.join("Existing.mp3"))?;
+        File::create(dir_path.join("[Artist] One.mp3"))?;
+        File::create(dir_path.join("[Artist] Two.mp3"))?;
+        let mut groups = vec![group(dir_path, &[("[Artist] One.mp3", "One.mp3"), ("[Artist] Two.mp3", "Two.mp3")])];
+        
+        assert_eq!(
+            edit(&mut groups, "1\tOne.mp3", "1\tExisting.mp3"),
+            Err(vec![EditError::TargetExists { number: 1, path: dir_path.join("Existing.mp3") }])
+        );
+        
+        // Taking the name of a file that keeps it is a collision too
+        assert_eq!(
+            edit(&mut groups, "1\tOne.mp3\n# [Artist] Two.mp3\n2\tTwo.mp3", "1\t[Artist] Two.mp3"),
+            Err(vec![EditError::DuplicateTarget { number: 2, other: 1 }])
+        );
+        
+        // ...but files may swap names
+        assert_eq!(edit(&mut groups, "1\tOne.mp3\n# [Artist] Two.mp3\n2\tTwo.mp3", "1\t[Artist] Two.mp3\n2\t[Artist] One.mp3"), Ok(2));
+        
+        Ok(())
+    }
+}
@@ -0,0 +1,419 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+use crate::os_names::{display_name, serde_path};
+use crate::prefix_finder::{find_longest_prefix, PrefixOptions, PrefixedPath};
+use crate::rename_db::{batch_conflicts, resolve_collisions, tracked_rename_batch, CollisionStrategy, RenameDatabase, RenameOutcome};
+use crate::rename_edit::is_valid_name;
+use crate::suffix_finder::{find_longest_suffix, remove_suffix_from_name, SuffixedPath};
+
+/// Version written to plan files; newer plans are rejected by `RenamePlan::load`
+pub const PLAN_VERSION: u32 = 1;
+
+/// Whether a rename removes prefixes or suffixes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Affix {
+    Prefix,
+    Suffix,
+}
+
+impl Affix {
+    pub fn name(self) -> &'static str {
+        match self {
+            Affix::Prefix => "prefix",
+            Affix::Suffix => "suffix",
+        }
+    }
+    
+    pub fn title(self) -> &'static str {
+        match self {
+            Affix::Prefix => "Prefix",
+            Affix::Suffix => "Suffix",
+        }
+    }
+}
+
+/// A reviewable set of renames that can be saved as JSON and applied later
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RenamePlan {
+    pub version: u32,
+    pub created: DateTime<Utc>,
+    pub groups: Vec<PlanGroup>,
+}
+
+/// The renames proposed for one detected prefix or suffix in one directory
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlanGroup {
+    /// Directory the group was found in; rename paths are relative to it
+    #[serde(with = "serde_path")]
+    pub directory: PathBuf,
+    pub kind: Affix,
+    /// The detected prefix or suffix, without delimiters
+    pub affix: String,
+    pub delimiter: Option<(String, String)>,
+    pub renames: Vec<PlannedRename>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlannedRename {
+    #[serde(with = "serde_path")]
+    pub from: PathBuf,
+    #[serde(with = "serde_path")]
+    pub to: PathBuf,
+}
+
+impl RenamePlan {
+    pub fn new(groups: Vec<PlanGroup>) -> Self {
+        Self {
+            version: PLAN_VERSION,
+            created: Utc::now(),
+            groups,
+        }
+    }
+    
+    /// Number of files that would actually change name
+    pub fn rename_count(&self) -> usize {
+        self.groups.iter()
+            .flat_map(|group| &group.renames)
+            .filter(|rename| rename.from != rename.to)
+            .count()
+    }
+    
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+    
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+    
+    pub fn save(&self, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+        fs::write(path, self.to_json()? + "\n")?;
+        Ok(())
+    }
+    
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let plan = Self::from_json(&fs::read_to_string(path)?)?;
+        if plan.version > PLAN_VERSION {
+            return Err(format!(
+                "Plan version {} is newer than the supported version {}",
+                plan.version, PLAN_VERSION
+            ).into());
+        }
+        
+        // Plans may be edited by hand: every rename has to stay in its folder,
+        // and the removed text, which collisions can turn into a file or
+        // subfolder name, has to be a plain name
+        for group in &plan.groups {
+            let delimiters = group.delimiter.iter().flat_map(|(open, close)| [open, close]);
+            if !std::iter::once(&group.affix).chain(delimiters).all(|text| is_valid_name(OsStr::new(text))) {
+                return Err(format!(
+                    "Invalid {} in plan: {:?} (the removed text must be a plain file name)",
+                    group.kind.name(),
+                    group.label(),
+                ).into());
+            }
+            for rename in &group.renames {
+                if !is_relative_name(&rename.from) || !is_relative_name(&rename.to) || rename.from.parent() != rename.to.parent() {
+                    return Err(format!(
+                        "Invalid rename in plan: {} → {} (names must stay in the folder of {})",
+                        display_name(rename.from.as_os_str()),
+                        display_name(rename.to.as_os_str()),
+                        display_name(group.directory.as_os_str()),
+                    ).into());
+                }
+            }
+        }
+        Ok(plan)
+    }
+}
+
+/// Whether `path` is a file name, or a relative path of file names (pooled
+/// groups), that cannot leave the directory it is joined onto
+fn is_relative_name(path: &Path) -> bool {
+    path.components().next().is_some()
+        && path.components().all(|component| matches!(component, Component::Normal(name) if is_valid_name(name)))
+}
+
+impl PlanGroup {
+    /// The literal text being removed, e.g. "[Artist]" or "(Remastered 2011)"
+    pub fn label(&self) -> String {
+        match &self.delimiter {
+            Some((open, close)) => format!("{}{}{}", open, self.affix, close),
+            None => self.affix.clone(),
+        }
+    }
+    
+    /// Absolute `(old path, new path)` pairs of this group
+    pub fn paths(&self) -> impl Iterator<Item = (PathBuf, PathBuf)> + '_ {
+        self.renames.iter()
+            .map(|rename| (self.directory.join(&rename.from), self.directory.join(&rename.to)))
+    }
+    
+    /// Absolute `(old path, new path)` pairs of this group with taken target
+    /// names resolved by `on_collision`, and the strategy applied to each
+    pub fn resolved_paths(&self, on_collision: Option<CollisionStrategy>) -> (Vec<(PathBuf, PathBuf)>, Vec<Option<CollisionStrategy>>) {
+        let renames: Vec<(PathBuf, PathBuf)> = self.paths().collect();
+        match on_collision {
+            Some(strategy) => resolve_collisions(&renames, strategy, &self.affix),
+            None => (renames, Vec::new()),
+        }
+    }
+    
+    /// Rename every file of the group as one batch through `tracked_rename_batch`,
+    /// recording them under `operation_id`. Unchanged names are skipped and
+    /// existing targets are only replaced by `CollisionStrategy::Backup`, but
+    /// files of the group may take each other's names. Without `on_collision`
+    /// a taken name is an error. Returns the number of successful and failed renames.
+    pub fn execute(&self, db: &RenameDatabase, operation_id: &str, on_collision: Option<CollisionStrategy>) -> (usize, usize) {
+        self.execute_with(db, operation_id, on_collision, |old_path, new_path, outcome| {
+            let filename = display_name(old_path.file_name().unwrap_or_default()).into_owned();
+            let new_filename = display_name(new_path.strip_prefix(&self.directory).unwrap_or(new_path).as_os_str()).into_owned();
+            match outcome {
+                RenameOutcome::Unchanged => println!("   ⏭️  {} (no change needed)", filename),
+                RenameOutcome::Skipped => println!("   ⏭️  {} (target exists, skipped)", filename),
+                RenameOutcome::TargetExists => eprintln!("   ❌ Target file already exists: {}", new_filename),
+                RenameOutcome::DuplicateTarget => eprintln!("   ❌ Another file would also be renamed to: {}", new_filename),
+                RenameOutcome::SourceMissing => eprintln!("   ❌ File no longer exists: {}", filename),
+                RenameOutcome::IdentityMismatch(mismatch) => eprintln!("   ❌ {}: {}", filename, mismatch.describe()),
+                RenameOutcome::Renamed => {
+                    println!("   🔄 Renaming: {} → {}", filename, new_filename);
+                    println!("   ✓ Success!");
+                }
+                RenameOutcome::Failed(e) => {
+                    println!("   🔄 Renaming: {} → {}", filename, new_filename);
+                    eprintln!("   ❌ Failed: {}", e);
+                }
+            }
+        })
+    }
+    
+    /// Like `execute`, but reports the outcome of each file to `report`
+    /// instead of printing it
+    pub fn execute_with<F: FnMut(&Path, &Path, &RenameOutcome)>(
+        &self,
+        db: &RenameDatabase,
+        operation_id: &str,
+        on_collision: Option<CollisionStrategy>,
+        mut report: F,
+    ) -> (usize, usize) {
+        let (renames, collisions) = self.resolved_paths(on_collision);
+        let outcomes = tracked_rename_batch(db, &renames, &collisions, &self.label(), operation_id);
+        let mut success_count = 0;
+        let mut error_count = 0;
+        
+        for ((old_path, new_path), outcome) in renames.iter().zip(&outcomes) {
+            match outcome {
+                RenameOutcome::Renamed => success_count += 1,
+                RenameOutcome::Unchanged | RenameOutcome::Skipped => {}
+                _ => error_count += 1,
+            }
+            report(old_path, new_path, outcome);
+        }
+        
+        (success_count, error_count)
+    }
+}
+
+/// Find every rename of `groups` that would fail a conflict check, as
+/// `(old path, new path, conflict)`, after resolving taken names with
+/// `on_collision`. Unchanged and skipped files are not conflicts.
+pub fn plan_conflicts(groups: &[PlanGroup], on_collision: Option<CollisionStrategy>) -> Vec<(PathBuf, PathBuf, RenameOutcome)> {
+    let mut conflicts = Vec::new();
+    for group in groups {
+        let (renames, _) = group.resolved_paths(on_collision);
+        let group_conflicts = batch_conflicts(&renames);
+        for ((old_path, new_path), conflict) in renames.into_iter().zip(group_conflicts) {
+            match conflict {
+                None | Some(RenameOutcome::Unchanged) => {}
+                Some(conflict) => conflicts.push((old_path, new_path, conflict)),
+            }
+        }
+    }
+    conflicts
+}
+
+/// Build the plan groups for the most common prefixes or suffixes of a single
+/// directory (subdirectories are only included with pooled recursion).
+/// Files are sorted by name so numbered listings are stable.
+pub fn plan_directory(directory: &Path, options: &PrefixOptions, kind: Affix) -> Result<Vec<PlanGroup>, std::io::Error> {
+    let groups: Vec<PlanGroup> = match kind {
+        Affix::Prefix => find_longest_prefix(directory, options)?
+            .iter()
+            .map(|prefixed_path| prefix_group(directory, prefixed_path))
+            .collect(),
+        Affix::Suffix => find_longest_suffix(directory, options)?
+            .iter()
+            .map(|suffixed_path| suffix_group(directory, suffixed_path))
+            .collect(),
+    };
+    
+    Ok(groups)
+}
+
+pub(crate) fn prefix_group(directory: &Path, prefixed_path: &PrefixedPath) -> PlanGroup {
+    let mut renames: Vec<PlannedRename> = prefixed_path.paths.iter()
+        .filter_map(|path| Some(planned_rename(directory, path, prefixed_path.remove_from_name(path.file_name()?))))
+        .collect();
+    renames.sort_by(|a, b| a.from.cmp(&b.from));
+    
+    PlanGroup {
+        directory: directory.to_path_buf(),
+        kind: Affix::Prefix,
+        affix: prefixed_path.prefix.clone(),
+        delimiter: prefixed_path.delimiter.clone(),
+        renames,
+    }
+}
+
+fn suffix_group(directory: &Path, suffixed_path: &SuffixedPath) -> PlanGroup {
+    let mut group = PlanGroup {
+        directory: directory.to_path_buf(),
+        kind: Affix::Suffix,
+        affix: suffixed_path.suffix.clone(),
+        delimiter: suffixed_path.delimiter.clone(),
+        renames: Vec::new(),
+    };
+    let label = group.label();
+    group.renames = suffixed_path.paths.iter()
+        .filter_map(|path| Some(planned_rename(directory, path, remove_suffix_from_name(path.file_name()?, &label))))
+        .collect();
+    group.renames.sort_by(|a, b| a.from.cmp(&b.from));
+    group
+}
+
+fn planned_rename(directory: &Path, path: &Path, new_name: OsString) -> PlannedRename {
+    let from = path.strip_prefix(directory).unwrap_or(path).to_path_buf();
+    let to = from.with_file_name(new_name);
+    PlannedRename { from, to }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use tempfile::TempDir;
+    
+    #[test]
+    fn test_prefix_group_honors_delimiter() {
+        let directory = Path::new("/docs");
+        let group = prefix_group(directory, &PrefixedPath {
+            paths: vec![PathBuf::from("/docs/(Draft) Report.pdf"), PathBuf::from("/docs/(Draft)_Notes.txt")],
+            prefix: "Draft".to_string(),
+            delimiter: Some(("(".to_string(), ")".to_string())),
+        });
+        assert_eq!(group.label(), "(Draft)");
+        assert_eq!(group.renames[0].to, PathBuf::from("Report.pdf"));
+        assert_eq!(group.renames[1].to, PathBuf::from("Notes.txt"));
+        
+        let group = prefix_group(Path::new("/photos"), &PrefixedPath {
+            paths: vec![PathBuf::from("/photos/IMG_001.jpg")],
+            prefix: "IMG".to_string(),
+            delimiter: None,
+        });
+        assert_eq!(group.label(), "IMG");
+        assert_eq!(group.renames[0].to, PathBuf::from("001.jpg"));
+    }
+    
+    #[test]
+    fn test_plan_round_trip_and_execute() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let dir_path = temp_dir.path().join("music");
+        fs::create_dir_all(&dir_path)?;
+        File::create(dir_path.join("[Artist] One.mp3"))?;
+        File::create(dir_path.join("[Artist] Two.mp3"))?;
+        
+        let plan = RenamePlan::new(plan_directory(&dir_path, &PrefixOptions::default(), Affix::Prefix)?);
+        assert_eq!(plan.rename_count(), 2);
+        
+        // Planning never touches the disk
+        assert!(dir_path.join("[Artist] One.mp3").exists());
+        
+        let plan_path = temp_dir.path().join("plan.json");
+        plan.save(&plan_path)?;
+        let loaded = RenamePlan::load(&plan_path)?;
+        assert_eq!(loaded, plan);
+        assert_eq!(loaded.groups[0].kind, Affix::Prefix);
+        assert_eq!(loaded.groups[0].label(), "[Artist]");
+        
+        let db = RenameDatabase::new(temp_dir.path().join("test.db"));
+        db.initialize()?;
+        assert_eq!(loaded.groups[0].execute(&db, "op_plan", None), (2, 0));
+        assert!(dir_path.join("One.mp3").exists());
+        assert!(dir_path.join("Two.mp3").exists());
+        assert_eq!(db.get_operation_renames("op_plan")?.len(), 2);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_load_rejects_newer_version() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let plan_path = temp_dir.path().join("plan.json");
+        let mut plan = RenamePlan::new(Vec::new());
+        plan.version = PLAN_VERSION + 1;
+        plan.save(&plan_path)?;
+        
+        assert!(RenamePlan::load(&plan_path).is_err());
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_load_rejects_renames_leaving_the_directory() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let plan_path = temp_dir.path().join("plan.json");
+        let plan_with = |from: &str, to: &str| RenamePlan::new(vec![PlanGroup {
+            directory: temp_dir.path().to_path_buf(),
+            kind: Affix::Prefix,
+            affix: "A".to_string(),
+            delimiter: None,
+            renames: vec![PlannedRename { from: PathBuf::from(from), to: PathBuf::from(to) }],
+        }]);
+        
+        for (from, to) in [("A one", "../../one"), ("A one", "/tmp/one"), ("A one", "sub/one"), ("../A one", "../one"), ("A one", "")] {
+            plan_with(from, to).save(&plan_path)?;
+            assert!(RenamePlan::load(&plan_path).is_err(), "{} → {} was accepted", from, to);
+        }
+        
+        // Pooled groups rename files in subfolders, within the same subfolder
+        plan_with("album/A one", "album/one").save(&plan_path)?;
+        assert!(RenamePlan::load(&plan_path).is_ok());
+        
+        // The removed text and its delimiters have to be plain names as well
+        let mut plan = plan_with("A one", "one");
+        for (affix, delimiter) in [("../../x", None), ("a/b", None), ("", None), ("A", Some(("[", "/]"))), ("A", Some(("..", "\0")))] {
+            plan.groups[0].affix = affix.to_string();
+            plan.groups[0].delimiter = delimiter.map(|(open, close)| (open.to_string(), close.to_string()));
+            plan.save(&plan_path)?;
+            assert!(RenamePlan::load(&plan_path).is_err(), "{:?} was accepted", plan.groups[0].label());
+        }
+        
+        Ok(())
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn test_plan_keeps_non_utf8_names() -> Result<(), Box<dyn std::error::Error>> {
+        use crate::os_names::name_from_bytes;
+        
+        let group = PlanGroup {
+            directory: PathBuf::from("/music"),
+            kind: Affix::Prefix,
+            affix: "Artist".to_string(),
+            delimiter: Some(("[".to_string(), "]".to_string())),
+            renames: vec![PlannedRename {
+                from: PathBuf::from(name_from_bytes(b"[Artist] Caf\xe9.mp3".to_vec())),
+                to: PathBuf::from(name_from_bytes(b"Caf\xe9.mp3".to_vec())),
+            }],
+        };
+        let plan = RenamePlan::new(vec![group]);
+        
+        assert_eq!(RenamePlan::from_json(&plan.to_json()?)?, plan);
+        
+        Ok(())
+    }
+}
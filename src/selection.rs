@@ -0,0 +1,219 @@
+use std::fmt;
+
+/// Which files of a rename group are selected at the confirmation prompt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selection {
+    selected: Vec<bool>,
+}
+
+/// An edit to a `Selection` typed at the confirmation prompt.
+/// File numbers are shown to the user starting at 1 but stored here 0-based.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SelectionCommand {
+    /// `1-5,8`: select exactly these files
+    Only(Vec<usize>),
+    /// `+9,10`: also select these files
+    Include(Vec<usize>),
+    /// `-3`: deselect these files ("all but 3")
+    Exclude(Vec<usize>),
+    /// `all`
+    All,
+    /// `none`
+    Clear,
+    /// `!`: flip the selection of every file
+    Invert,
+    /// `e 3 New name.mp3`: change the new name of one file
+    Rename(usize, String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SelectionError {
+    /// The input is not a selection command
+    Malformed(String),
+    /// A file number outside `1..=count`
+    OutOfRange { number: usize, count: usize },
+}
+
+impl fmt::Display for SelectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SelectionError::Malformed(input) => write!(f, "'{}' is not a valid selection", input),
+            SelectionError::OutOfRange { number, count } => {
+                write!(f, "file {} does not exist (files are numbered 1-{})", number, count)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SelectionError {}
+
+impl Selection {
+    /// Select all `count` files
+    pub fn all(count: usize) -> Self {
+        Self { selected: vec![true; count] }
+    }
+    
+    pub fn is_selected(&self, index: usize) -> bool {
+        self.selected.get(index).copied().unwrap_or(false)
+    }
+    
+    /// Number of selected files
+    pub fn count(&self) -> usize {
+        self.selected.iter().filter(|selected| **selected).count()
+    }
+    
+    /// Apply a command; `Rename` does not change the selection
+    pub fn apply(&mut self, command: &SelectionCommand) {
+        match command {
+            SelectionCommand::Only(indices) => {
+                self.selected.fill(false);
+                self.set(indices, true);
+            }
+            SelectionCommand::Include(indices) => self.set(indices, true),
+            SelectionCommand::Exclude(indices) => self.set(indices, false),
+            SelectionCommand::All => self.selected.fill(true),
+            SelectionCommand::Clear => self.selected.fill(false),
+            SelectionCommand::Invert => self.selected.iter_mut().for_each(|selected| *selected = !*selected),
+            SelectionCommand::Rename(..) => {}
+        }
+    }
+    
+    /// The selected items of `items`, in order
+    pub fn filter<T: Clone>(&self, items: &[T]) -> Vec<T> {
+        items.iter()
+            .enumerate()
+            .filter(|(i, _)| self.is_selected(*i))
+            .map(|(_, item)| item.clone())
+            .collect()
+    }
+    
+    fn set(&mut self, indices: &[usize], value: bool) {
+        for &index in indices {
+            if let Some(selected) = self.selected.get_mut(index) {
+                *selected = value;
+            }
+        }
+    }
+}
+
+/// Parse a selection command for a group of `count` files
+pub fn parse_selection_command(input: &str, count: usize) -> Result<SelectionCommand, SelectionError> {
+    let input = input.trim();
+    match input.to_lowercase().as_str() {
+        "a" | "all" => return Ok(SelectionCommand::All),
+        "none" => return Ok(SelectionCommand::Clear),
+        "!" | "i" | "invert" => return Ok(SelectionCommand::Invert),
+        _ => {}
+    }
+    
+    if let Some(rest) = input.strip_prefix("e ") {
+        let rest = rest.trim_start();
+        let (number, name) = rest.split_once(char::is_whitespace)
+            .ok_or_else(|| SelectionError::Malformed(input.to_string()))?;
+        let index = parse_ranges(number, count)
+            .ok()
+            .filter(|indices| indices.len() == 1)
+            .ok_or_else(|| SelectionError::Malformed(input.to_string()))?[0];
+        return Ok(SelectionCommand::Rename(index, name.trim().to_string()));
+    }
+    
+    if let Some(spec) = input.strip_prefix('+') {
+        Ok(SelectionCommand::Include(parse_ranges(spec, count)?))
+    } else if let Some(spec) = input.strip_prefix('-') {
+        Ok(SelectionCommand::Exclude(parse_ranges(spec, count)?))
+    } else {
+        Ok(SelectionCommand::Only(parse_ranges(input, count)?))
+    }
+}
+
+/// Parse a comma separated list of 1-based numbers and ranges such as `1-5,8`
+/// into sorted, deduplicated 0-based indices
+pub fn parse_ranges(spec: &str, count: usize) -> Result<Vec<usize>, SelectionError> {
+    let malformed = || SelectionError::Malformed(spec.to_string());
+    let number = |text: &str| -> Result<usize, SelectionError> {
+        let number: usize = text.trim().parse().map_err(|_| malformed())?;
+        if number == 0 || number > count {
+            return Err(SelectionError::OutOfRange { number, count });
+        }
+        Ok(number)
+    };
+    
+    let mut indices = Vec::new();
+    for part in spec.split(',') {
+        let part = part.trim();
+        if part.is_empty() {
+            return Err(malformed());
+        }
+        
+        let (start, end) = match part.split_once('-') {
+            Some((start, end)) => (number(start)?, number(end)?),
+            None => {
+                let single = number(part)?;
+                (single, single)
+            }
+        };
+        if start > end {
+            return Err(malformed());
+        }
+        indices.extend(start - 1..end);
+    }
+    
+    indices.sort_unstable();
+    indices.dedup();
+    Ok(indices)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_parse_ranges() {
+        assert_eq!(parse_ranges("1-5,8", 10), Ok(vec![0, 1, 2, 3, 4, 7]));
+        assert_eq!(parse_ranges(" 3 , 1-2, 2 ", 3), Ok(vec![0, 1, 2]));
+        assert_eq!(parse_ranges("4", 3), Err(SelectionError::OutOfRange { number: 4, count: 3 }));
+        assert_eq!(parse_ranges("0", 3), Err(SelectionError::OutOfRange { number: 0, count: 3 }));
+        assert!(matches!(parse_ranges("3-1", 3), Err(SelectionError::Malformed(_))));
+        assert!(matches!(parse_ranges("1,,2", 3), Err(SelectionError::Malformed(_))));
+        assert!(matches!(parse_ranges("one", 3), Err(SelectionError::Malformed(_))));
+    }
+    
+    #[test]
+    fn test_parse_selection_command() {
+        assert_eq!(parse_selection_command("1-2", 5), Ok(SelectionCommand::Only(vec![0, 1])));
+        assert_eq!(parse_selection_command("-3", 5), Ok(SelectionCommand::Exclude(vec![2])));
+        assert_eq!(parse_selection_command("+4-5", 5), Ok(SelectionCommand::Include(vec![3, 4])));
+        assert_eq!(parse_selection_command("!", 5), Ok(SelectionCommand::Invert));
+        assert_eq!(parse_selection_command("ALL", 5), Ok(SelectionCommand::All));
+        assert_eq!(parse_selection_command("none", 5), Ok(SelectionCommand::Clear));
+        assert_eq!(
+            parse_selection_command("e 2 My Song (Live).mp3", 5),
+            Ok(SelectionCommand::Rename(1, "My Song (Live).mp3".to_string()))
+        );
+        assert!(parse_selection_command("e 2", 5).is_err());
+        assert!(parse_selection_command("e 1-2 name", 5).is_err());
+        assert!(parse_selection_command("maybe", 5).is_err());
+    }
+    
+    #[test]
+    fn test_selection_apply() {
+        let mut selection = Selection::all(5);
+        assert_eq!(selection.count(), 5);
+        
+        // All but 3
+        selection.apply(&SelectionCommand::Exclude(vec![2]));
+        assert_eq!(selection.filter(&["a", "b", "c", "d", "e"]), vec!["a", "b", "d", "e"]);
+        
+        selection.apply(&SelectionCommand::Invert);
+        assert_eq!(selection.filter(&["a", "b", "c", "d", "e"]), vec!["c"]);
+        
+        selection.apply(&SelectionCommand::Only(vec![0, 1]));
+        selection.apply(&SelectionCommand::Include(vec![4]));
+        assert_eq!(selection.filter(&[1, 2, 3, 4, 5]), vec![1, 2, 5]);
+        
+        selection.apply(&SelectionCommand::Clear);
+        assert_eq!(selection.count(), 0);
+        selection.apply(&SelectionCommand::All);
+        assert_eq!(selection.count(), 5);
+    }
+}
@@ -0,0 +1,152 @@
+use crate::{display_name, OperationContext, RenamePlan, generate_operation_id};
+use crate::subcommands::{
+    atomic_precheck, open_database, parse_db_flag, parse_on_collision, roll_back, warn_about_journal
+};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+pub fn apply_command(args: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut plan_path: Option<PathBuf> = None;
+    let mut atomic = false;
+    let mut on_collision = None;
+    let mut db_path: Option<PathBuf> = None;
+    let mut context = OperationContext::current();
+    let mut i = 2; // Skip "ftmi" and "apply"
+    
+    while i < args.len() {
+        match args[i].as_str() {
+            "--atomic" => {
+                atomic = true;
+                i += 1;
+            }
+            arg if arg == "--on-collision" || arg.starts_with("--on-collision=") => {
+                let Some((strategy, consumed)) = parse_on_collision(&args, i) else {
+                    return Ok(());
+                };
+                on_collision = Some(strategy);
+                i += consumed;
+            }
+            arg if arg == "--db" || arg.starts_with("--db=") => {
+                let Some((path, consumed)) = parse_db_flag(&args, i) else {
+                    return Ok(());
+                };
+                db_path = Some(path);
+                i += consumed;
+            }
+            "--note" | "--tag" => {
+                let Some(value) = args.get(i + 1) else {
+                    eprintln!("❌ Error: {} requires an argument", args[i]);
+                    return Ok(());
+                };
+                if args[i] == "--note" {
+                    context.note = Some(value.clone());
+                } else {
+                    context.tags.push(value.clone());
+                }
+                i += 2;
+            }
+            "--help" | "-h" => {
+                print_help();
+                return Ok(());
+            }
+            arg if arg.starts_with("--") => {
+                eprintln!("❌ Unknown option: {}", arg);
+                print_help();
+                return Ok(());
+            }
+            _ => {
+                plan_path = Some(PathBuf::from(&args[i]));
+                i += 1;
+            }
+        }
+    }
+    
+    let Some(plan_path) = plan_path else {
+        eprintln!("❌ No plan file provided.");
+        eprintln!("Usage: ftmi apply plan.json");
+        return Ok(());
+    };
+    
+    println!("🔧 FTMI Apply Rename Plan");
+    
+    let plan = RenamePlan::load(&plan_path)?;
+    println!("📄 Plan: {} ({} group(s), {} rename(s), created {})",
+             plan_path.display(), plan.groups.len(), plan.rename_count(),
+             plan.created.format("%Y-%m-%d %H:%M:%S UTC"));
+    
+    // The history next to the renamed files, if they have one
+    let start = plan.groups.first().map_or(Path::new("."), |group| group.directory.as_path());
+    let db = open_database(db_path.as_deref(), start)?;
+    warn_about_journal(&db);
+    
+    if atomic && atomic_precheck(&plan.groups, on_collision) > 0 {
+        std::process::exit(2);
+    }
+    
+    // The whole plan is recorded as a single operation
+    context.settings = Some(serde_json::json!({
+        "plan": display_name(plan_path.as_os_str()),
+        "on_collision": on_collision,
+        "atomic": atomic,
+    }).to_string());
+    let operation_id = generate_operation_id();
+    let started = Instant::now();
+    db.begin_operation(&operation_id, &context)?;
+    let mut success_count = 0;
+    let mut error_count = 0;
+    
+    for group in &plan.groups {
+        println!();
+        println!("📁 Directory: {}", display_name(group.directory.as_os_str()));
+        println!("🏷️  {}: {}", group.kind.title(), group.label());
+        
+        let (successes, errors) = group.execute(&db, &operation_id, on_collision);
+        success_count += successes;
+        error_count += errors;
+    }
+    db.finish_operation(&operation_id, success_count, error_count, started.elapsed())?;
+    
+    println!();
+    println!("📊 Results: {} successful, {} failed", success_count, error_count);
+    if atomic && error_count > 0 {
+        roll_back(&db, &operation_id);
+    } else if success_count > 0 {
+        println!("💾 Operation ID: {} (use 'ftmi rename --undo {}' to undo)", operation_id, operation_id);
+    }
+    
+    if error_count > 0 {
+        std::process::exit(2);
+    }
+    
+    Ok(())
+}
+
+fn print_help() {
+    println!("apply - Execute a rename plan written by 'ftmi rename --plan'");
+    println!();
+    println!("USAGE:");
+    println!("    ftmi apply [--atomic] [--on-collision STRATEGY] [--note TEXT] [--tag TAG]... [--db PATH] PLAN_FILE");
+    println!();
+    println!("OPTIONS:");
+    println!("    --atomic      All or nothing: check the whole plan first and roll back");
+    println!("                  every rename if any of them fails");
+    println!("    --on-collision STRATEGY");
+    println!("                  What to do when a target name is taken: skip, number,");
+    println!("                  suffix, subfolder or backup (see 'ftmi rename --help')");
+    println!("    --note TEXT   Record a note with the operation");
+    println!("    --tag TAG     Tag the operation; may be repeated");
+    println!("    --db PATH     Use this history database (see 'ftmi rename --help')");
+    println!("    -h, --help    Show this help message");
+    println!();
+    println!("DESCRIPTION:");
+    println!("    Renames every file listed in the plan and records all of them as one");
+    println!("    operation, so the whole plan can be undone with 'ftmi rename --undo'.");
+    println!("    Plans may be edited by hand before applying. Files whose target name");
+    println!("    already exists fail unless --on-collision says how to resolve them;");
+    println!("    existing files are only replaced by the backup strategy. Exits with");
+    println!("    status 2 when some files could not be renamed.");
+    println!();
+    println!("EXAMPLES:");
+    println!("    ftmi rename --plan plan.json ./music");
+    println!("    ftmi apply plan.json");
+}
@@ -1,4 +1,6 @@
-use crate::prefix_finder::{find_common_prefix, PrefixOptions};
+use crate::os_names::display_name;
+use crate::prefix_finder::{find_common_prefix, directories_to_analyze, PrefixOptions};
+use crate::subcommands::ScanFlags;
 use std::io::{self, BufRead};
 use std::path::Path;
 
@@ -11,11 +13,21 @@ pub fn detect_all_command(args: Vec<String>) -> Result<(), Box<dyn std::error::E
     
     // Parse options
     let mut min_occurrences = 2;
+    let mut scan_flags = ScanFlags::default();
     let mut directories = Vec::new();
     let mut i = 2;
     
     while i < args.len() {
-        if args[i] == "--min" && i + 1 < args.len() {
+        let consumed = match scan_flags.parse(&args, i) {
+            Ok(consumed) => consumed,
+            Err(e) => {
+                eprintln!("❌ Error: {}", e);
+                std::process::exit(1);
+            }
+        };
+        if consumed > 0 {
+            i += consumed;
+        } else if args[i] == "--min" && i + 1 < args.len() {
             if let Ok(min) = args[i + 1].parse::<usize>() {
                 min_occurrences = min;
             }
@@ -28,11 +40,12 @@ pub fn detect_all_command(args: Vec<String>) -> Result<(), Box<dyn std::error::E
         }
     }
     
-    let options = PrefixOptions {
+    let mut options = PrefixOptions {
         min_occurrences,
         filter_regex: None, // No filtering - show all prefixes
         ..PrefixOptions::default()
     };
+    scan_flags.apply(&mut options);
     
     // Process directories from command line or stdin
     if !directories.is_empty() {
@@ -69,7 +82,22 @@ fn process_directory(dir_path: &str, options: &PrefixOptions) -> Result<(), Box<
         return Ok(());
     }
     
-    println!("Directory: {}", dir_path);
+    match directories_to_analyze(path, options) {
+        Ok(directories) => {
+            for directory in directories {
+                report_directory(&directory, options);
+            }
+        }
+        Err(e) => {
+            eprintln!("Error processing directory {}: {}", dir_path, e);
+        }
+    }
+    
+    Ok(())
+}
+
+fn report_directory(path: &Path, options: &PrefixOptions) {
+    println!("Directory: {}", path.display());
     println!("Minimum occurrences: {}", options.min_occurrences);
     println!("{}", "-".repeat(50));
     
@@ -91,18 +119,16 @@ fn process_directory(dir_path: &str, options: &PrefixOptions) -> Result<(), Box<
                     }
                     
                     for file in &prefix.files {
-                        println!("   - {}", file);
+                        println!("   - {}", display_name(file.as_os_str()));
                     }
                     println!();
                 }
             }
         }
         Err(e) => {
-            eprintln!("Error processing directory {}: {}", dir_path, e);
+            eprintln!("Error processing directory {}: {}", path.display(), e);
         }
     }
-    
-    Ok(())
 }
 
 fn print_help() {
@@ -114,6 +140,10 @@ fn print_help() {
     println!();
     println!("OPTIONS:");
     println!("    --min NUM          Minimum occurrences required (default: 2)");
+    println!("    --recursive        Also analyze every subdirectory as its own group");
+    println!("    --max-depth NUM    Limit recursion depth (implies --recursive)");
+    println!("    --pool             Analyze the whole tree as a single pool of files");
+    println!("    --normalize FORM   Unicode-normalize names (nfc or nfd) before comparing");
     println!("    -h, --help        Show this help message");
     println!();
     println!("DESCRIPTION:");
@@ -133,4 +163,7 @@ fn print_help() {
     println!();
     println!("    # Process multiple directories");
     println!("    ftmi detect-all ./music ./photos ./documents");
+    println!();
+    println!("    # Analyze every album folder below ~/Music separately");
+    println!("    ftmi detect-all --recursive ~/Music");
 }
\ No newline at end of file
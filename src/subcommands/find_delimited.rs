@@ -1,4 +1,6 @@
-use crate::prefix_finder::{find_common_prefix, PrefixOptions, PrefixMode};
+use crate::os_names::display_name;
+use crate::prefix_finder::{find_common_prefix, directories_to_analyze, PrefixOptions, PrefixMode};
+use crate::subcommands::ScanFlags;
 use std::io::{self, BufRead};
 use std::path::Path;
 
@@ -17,14 +19,24 @@ pub fn find_delimited_command(args: Vec<String>) -> Result<(), Box<dyn std::erro
     ];
     
     // Check for custom delimiter argument
+    let mut scan_flags = ScanFlags::default();
     let mut directories = Vec::new();
     let mut i = 2;
     while i < args.len() {
-        if args[i] == "--delimiter" && i + 1 < args.len() {
+        let consumed = match scan_flags.parse(&args, i) {
+            Ok(consumed) => consumed,
+            Err(e) => {
+                eprintln!("❌ Error: {}", e);
+                std::process::exit(1);
+            }
+        };
+        if consumed > 0 {
+            i += consumed;
+        } else if args[i] == "--delimiter" && i + 1 < args.len() {
             // Parse custom delimiter like "--delimiter []" or "--delimiter ()"
             let delim_str = &args[i + 1];
             if delim_str.len() >= 2 {
-                let open = delim_str.chars().nth(0).unwrap().to_string();
+                let open = delim_str.chars().next().unwrap().to_string();
                 let close = delim_str.chars().nth(1).unwrap().to_string();
                 delimiters = vec![(open, close)];
             }
@@ -37,11 +49,14 @@ pub fn find_delimited_command(args: Vec<String>) -> Result<(), Box<dyn std::erro
         }
     }
     
-    let options = PrefixOptions {
+    let mut options = PrefixOptions {
         mode: PrefixMode::DelimiterOnly { delimiters },
         min_occurrences: 2,
         filter_regex: None,
+        recursion: None,
+        normalization: None,
     };
+    scan_flags.apply(&mut options);
     
     // Process directories from command line or stdin
     if !directories.is_empty() {
@@ -78,7 +93,22 @@ fn process_directory(dir_path: &str, options: &PrefixOptions) -> Result<(), Box<
         return Ok(());
     }
     
-    println!("Directory: {}", dir_path);
+    match directories_to_analyze(path, options) {
+        Ok(directories) => {
+            for directory in directories {
+                report_directory(&directory, options);
+            }
+        }
+        Err(e) => {
+            eprintln!("Error processing directory {}: {}", dir_path, e);
+        }
+    }
+    
+    Ok(())
+}
+
+fn report_directory(path: &Path, options: &PrefixOptions) {
+    println!("Directory: {}", path.display());
     println!("{}", "-".repeat(50));
     
     match find_common_prefix(path, options) {
@@ -90,7 +120,7 @@ fn process_directory(dir_path: &str, options: &PrefixOptions) -> Result<(), Box<
                     if let Some((open, close)) = &prefix.delimiter {
                         println!("Delimited prefix: {}{}{} - {} files", open, prefix.prefix, close, prefix.occurrences);
                         for file in &prefix.files {
-                            println!("  - {}", file);
+                            println!("  - {}", display_name(file.as_os_str()));
                         }
                         println!();
                     }
@@ -98,11 +128,9 @@ fn process_directory(dir_path: &str, options: &PrefixOptions) -> Result<(), Box<
             }
         }
         Err(e) => {
-            eprintln!("Error processing directory {}: {}", dir_path, e);
+            eprintln!("Error processing directory {}: {}", path.display(), e);
         }
     }
-    
-    Ok(())
 }
 
 fn print_help() {
@@ -114,6 +142,10 @@ fn print_help() {
     println!();
     println!("OPTIONS:");
     println!("    --delimiter DELIM    Custom delimiter pair (e.g., [], (), {{}})");
+    println!("    --recursive          Also analyze every subdirectory as its own group");
+    println!("    --max-depth NUM      Limit recursion depth (implies --recursive)");
+    println!("    --pool               Analyze the whole tree as a single pool of files");
+    println!("    --normalize FORM     Unicode-normalize names (nfc or nfd) before comparing");
     println!("    -h, --help          Show this help message");
     println!();
     println!("DESCRIPTION:");
@@ -129,4 +161,7 @@ fn print_help() {
     println!();
     println!("    # Process multiple directories");
     println!("    ftmi find-delimited ./music ./photos ./documents");
+    println!();
+    println!("    # Find artists across a whole library, two levels deep");
+    println!("    ftmi find-delimited --pool --max-depth 2 ~/Music");
 }
\ No newline at end of file
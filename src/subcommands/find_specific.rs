@@ -1,3 +1,4 @@
+use crate::os_names::display_name;
 use crate::prefix_finder::{find_common_prefix, PrefixOptions, PrefixMode};
 use std::io::{self, BufRead};
 use std::path::Path;
@@ -42,6 +43,8 @@ pub fn find_specific_command(args: Vec<String>) -> Result<(), Box<dyn std::error
         mode: PrefixMode::SpecificPrefixes { prefixes: prefixes.clone() },
         min_occurrences: 1,
         filter_regex: None,
+        recursion: None,
+        normalization: None,
     };
     
     // Process directories from command line or stdin
@@ -91,7 +94,7 @@ fn process_directory(dir_path: &str, options: &PrefixOptions, search_prefixes: &
                 for prefix in prefixes {
                     println!("Found prefix: {} - {} files", prefix.prefix, prefix.occurrences);
                     for file in &prefix.files {
-                        println!("  - {}", file);
+                        println!("  - {}", display_name(file.as_os_str()));
                     }
                     println!();
                 }
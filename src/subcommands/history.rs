@@ -0,0 +1,642 @@
+use crate::{display_name, HistoryQuery, PruneOptions, RenameDatabase};
+use crate::history_export::{ExportFormat, HistoryExport, ScriptDirection};
+use crate::subcommands::{open_database, parse_db_flag};
+use crate::subcommands::rename::print_operations;
+use chrono::{DateTime, NaiveDate, Utc};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// Operations per page of `ftmi history`
+const PAGE_SIZE: usize = 20;
+
+/// Days shown by `ftmi history stats`, and prefixes listed
+const STATS_DAYS: usize = 14;
+const STATS_PREFIXES: usize = 10;
+
+pub fn history_command(args: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
+    match args.get(2).map(String::as_str) {
+        Some("export") => export_command(&args[3..]),
+        Some("import") => import_command(&args[3..]),
+        Some("trace") => trace_command(&args[3..]),
+        Some("prune") => prune_command(&args[3..]),
+        Some("vacuum") => vacuum_command(&args[3..]),
+        Some("stats") => stats_command(&args[3..]),
+        Some("--help") | Some("-h") => {
+            print_help();
+            Ok(())
+        }
+        Some(other) if !other.starts_with('-') => {
+            eprintln!("❌ Unknown history command: {}", other);
+            print_help();
+            std::process::exit(1);
+        }
+        _ => query_command(&args[2..]),
+    }
+}
+
+fn query_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut query = HistoryQuery::default();
+    let mut page_size = PAGE_SIZE;
+    let mut page = 1;
+    let mut db_path: Option<PathBuf> = None;
+    let mut i = 0;
+    
+    while i < args.len() {
+        let value = args.get(i + 1).map(String::as_str);
+        match (args[i].as_str(), value) {
+            ("--since", Some(value)) | ("--until", Some(value)) => {
+                let until = args[i] == "--until";
+                let Some(date) = parse_date(value, until) else {
+                    eprintln!("❌ Error: Invalid date: {} (expected YYYY-MM-DD or RFC 3339)", value);
+                    std::process::exit(1);
+                };
+                if until {
+                    query.until = Some(date);
+                } else {
+                    query.since = Some(date);
+                }
+                i += 2;
+            }
+            ("--dir", Some(value)) => {
+                query.directory = Some(absolute(Path::new(value))?);
+                i += 2;
+            }
+            ("--prefix", Some(value)) => {
+                query.prefix = Some(value.to_string());
+                i += 2;
+            }
+            ("--name", Some(value)) => {
+                match glob::Pattern::new(value) {
+                    Ok(pattern) => query.name = Some(pattern),
+                    Err(e) => {
+                        eprintln!("❌ Error: Invalid --name pattern {}: {}", value, e);
+                        std::process::exit(1);
+                    }
+                }
+                i += 2;
+            }
+            ("--tag", Some(value)) => {
+                query.tags.push(value.to_string());
+                i += 2;
+            }
+            ("--limit", Some(value)) | ("--page", Some(value)) => {
+                let Some(number) = value.parse::<usize>().ok().filter(|number| *number > 0) else {
+                    eprintln!("❌ Error: {} requires a positive number", args[i]);
+                    std::process::exit(1);
+                };
+                if args[i] == "--limit" {
+                    page_size = number;
+                } else {
+                    page = number;
+                }
+                i += 2;
+            }
+            (arg, _) if arg == "--db" || arg.starts_with("--db=") => {
+                let Some((path, consumed)) = parse_db_flag(args, i) else {
+                    std::process::exit(1);
+                };
+                db_path = Some(path);
+                i += consumed;
+            }
+            ("--help", _) | ("-h", _) => {
+                print_help();
+                return Ok(());
+            }
+            (arg, _) => {
+                eprintln!("❌ Unknown option or missing value: {}", arg);
+                print_help();
+                std::process::exit(1);
+            }
+        }
+    }
+    
+    let start = query.directory.clone().unwrap_or_else(|| PathBuf::from("."));
+    let db = open_database(db_path.as_deref(), &start)?;
+    
+    // One more than a page tells whether there is a next page
+    query.offset = (page - 1) * page_size;
+    query.limit = Some(page_size + 1);
+    let mut operations = db.query_operations(&query)?;
+    let more = operations.len() > page_size;
+    operations.truncate(page_size);
+    
+    println!("📋 Rename operations, most recent first (page {}):", page);
+    if operations.is_empty() {
+        println!("   No operations found.");
+        return Ok(());
+    }
+    print_operations(&db, &operations, query.offset + 1)?;
+    
+    if more {
+        println!("📄 More operations match; add --page {} to see them.", page + 1);
+    }
+    println!("💡 'ftmi rename --undo <operation_id>' undoes an operation, 'ftmi rename --show <operation_id>'");
+    println!("   shows its details and 'ftmi history trace <file>' what a file was called before.");
+    
+    Ok(())
+}
+
+fn trace_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut db_path: Option<PathBuf> = None;
+    let mut target: Option<PathBuf> = None;
+    let mut i = 0;
+    
+    while i < args.len() {
+        match args[i].as_str() {
+            arg if arg == "--db" || arg.starts_with("--db=") => {
+                let Some((path, consumed)) = parse_db_flag(args, i) else {
+                    std::process::exit(1);
+                };
+                db_path = Some(path);
+                i += consumed;
+            }
+            "--help" | "-h" => {
+                print_help();
+                return Ok(());
+            }
+            arg if arg.starts_with('-') || target.is_some() => {
+                eprintln!("❌ Unknown option: {}", arg);
+                print_help();
+                std::process::exit(1);
+            }
+            path => {
+                target = Some(PathBuf::from(path));
+                i += 1;
+            }
+        }
+    }
+    
+    let Some(target) = target else {
+        eprintln!("❌ Error: history trace requires the path of a file");
+        std::process::exit(1);
+    };
+    let path = absolute(&target)?;
+    let db = open_database(db_path.as_deref(), path.parent().unwrap_or(Path::new(".")))?;
+    
+    let mut lineage = db.path_lineage(&path)?;
+    let mut traced = path.clone();
+    if lineage.is_empty() {
+        // The history may know the path through a symlink
+        if let Ok(canonical) = path.canonicalize() {
+            if canonical != path {
+                lineage = db.path_lineage(&canonical)?;
+                traced = canonical;
+            }
+        }
+    }
+    
+    if lineage.is_empty() {
+        println!("📭 No renames of {} are recorded.", display_name(path.as_os_str()));
+        return Ok(());
+    }
+    
+    println!("🧬 Rename history of {}:", display_name(path.as_os_str()));
+    for record in &lineage {
+        let (kind, _) = db.operation_kind(&record.operation_id)?;
+        let new_name = if record.old_path.parent() == record.new_path.parent() {
+            record.new_path.file_name().map(|name| display_name(name).into_owned()).unwrap_or_default()
+        } else {
+            display_name(record.new_path.as_os_str()).into_owned()
+        };
+        let undone = if record.reverted_by.is_some() { ", undone" } else { "" };
+        println!("   {}  {} → {}  ({} {}{})",
+                 record.timestamp.format("%Y-%m-%d %H:%M:%S UTC"),
+                 display_name(record.old_path.as_os_str()),
+                 new_name,
+                 kind.name(),
+                 record.operation_id,
+                 undone);
+    }
+    
+    // Names the file had before it got the traced one, oldest first
+    let arrived = lineage.iter().rposition(|record| record.new_path == traced);
+    let mut earlier: Vec<String> = Vec::new();
+    for record in arrived.map_or(&[][..], |index| &lineage[..=index]) {
+        let name = display_name(record.old_path.as_os_str()).into_owned();
+        if record.old_path != traced && !earlier.contains(&name) {
+            earlier.push(name);
+        }
+    }
+    if !earlier.is_empty() {
+        println!("📛 Earlier names: {}", earlier.join(", "));
+    }
+    if let Some(last) = lineage.last().filter(|last| last.new_path != traced) {
+        println!("➡️  Now called: {}", display_name(last.new_path.as_os_str()));
+    }
+    
+    Ok(())
+}
+
+fn prune_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut options = PruneOptions::default();
+    let mut db_path: Option<PathBuf> = None;
+    let mut i = 0;
+    
+    while i < args.len() {
+        let value = args.get(i + 1).map(String::as_str);
+        match (args[i].as_str(), value) {
+            ("--older-than", Some(value)) => {
+                let Some(age) = parse_age(value) else {
+                    eprintln!("❌ Error: Invalid age: {} (expected e.g. 90d, 12w or 48h)", value);
+                    std::process::exit(1);
+                };
+                options.older_than = Some(Utc::now() - age);
+                i += 2;
+            }
+            ("--keep-last", Some(value)) => {
+                let Ok(keep) = value.parse::<usize>() else {
+                    eprintln!("❌ Error: --keep-last requires a number");
+                    std::process::exit(1);
+                };
+                options.keep_last = Some(keep);
+                i += 2;
+            }
+            ("--force", _) => {
+                options.force = true;
+                i += 1;
+            }
+            ("--dry-run", _) => {
+                options.dry_run = true;
+                i += 1;
+            }
+            ("--help", _) | ("-h", _) => {
+                print_help();
+                return Ok(());
+            }
+            (arg, _) if arg == "--db" || arg.starts_with("--db=") => {
+                let Some((path, consumed)) = parse_db_flag(args, i) else {
+                    std::process::exit(1);
+                };
+                db_path = Some(path);
+                i += consumed;
+            }
+            (arg, _) => {
+                eprintln!("❌ Unknown option or missing value: {}", arg);
+                print_help();
+                std::process::exit(1);
+            }
+        }
+    }
+    
+    if options.older_than.is_none() && options.keep_last.is_none() {
+        eprintln!("❌ Error: history prune requires --older-than AGE or --keep-last N");
+        std::process::exit(1);
+    }
+    
+    let db = open_database(db_path.as_deref(), Path::new("."))?;
+    let summary = db.prune(&options)?;
+    
+    if summary.operations.is_empty() {
+        println!("✅ Nothing to prune.");
+    } else if options.dry_run {
+        println!("🔍 Would remove {} operation(s) with {} rename(s):", summary.operations.len(), summary.renames);
+        for operation_id in &summary.operations {
+            println!("   {}", operation_id);
+        }
+    } else {
+        println!("🗑️  Removed {} operation(s) with {} rename(s).", summary.operations.len(), summary.renames);
+    }
+    if !summary.kept_undoable.is_empty() {
+        println!("🔒 Kept {} operation(s) that can still be undone (use --force to remove them as well).",
+                 summary.kept_undoable.len());
+    }
+    
+    if !options.dry_run && !summary.operations.is_empty() {
+        compact(&db)?;
+    }
+    Ok(())
+}
+
+fn vacuum_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let db = open_database(parse_only_db_flag(args).as_deref(), Path::new("."))?;
+    compact(&db)
+}
+
+fn stats_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let db = open_database(parse_only_db_flag(args).as_deref(), Path::new("."))?;
+    let stats = db.stats()?;
+    
+    println!("📊 History statistics ({})", display_name(db.path().as_os_str()));
+    if stats.rename_operations + stats.undo_operations + stats.redo_operations == 0 {
+        println!("   No operations found.");
+        return Ok(());
+    }
+    println!("   Operations: {} rename, {} undo, {} redo",
+             stats.rename_operations, stats.undo_operations, stats.redo_operations);
+    println!("   Files renamed: {}", stats.files_renamed);
+    println!("   Undo rate: {:.0}% ({} of {} rename operations undone)",
+             stats.undo_rate() * 100.0, stats.undone_operations, stats.rename_operations);
+    if let (Some(first), Some(last)) = (stats.first, stats.last) {
+        println!("   Period: {} to {}", first.format("%Y-%m-%d"), last.format("%Y-%m-%d"));
+    }
+    if let Ok(metadata) = std::fs::metadata(db.path()) {
+        println!("   Database size: {}", format_size(metadata.len()));
+    }
+    
+    if !stats.operations_per_day.is_empty() {
+        let days = &stats.operations_per_day[stats.operations_per_day.len().saturating_sub(STATS_DAYS)..];
+        let busiest = days.iter().map(|(_, count)| *count).max().unwrap_or(1);
+        println!();
+        println!("📅 Rename operations per day (last {} active days):", days.len());
+        for (day, count) in days {
+            let width = (count * 30).div_ceil(busiest);
+            println!("   {}  {} {}", day.format("%Y-%m-%d"), "█".repeat(width), count);
+        }
+    }
+    
+    if !stats.prefixes.is_empty() {
+        println!();
+        println!("🏷️  Most removed prefixes:");
+        for (prefix, files) in stats.prefixes.iter().take(STATS_PREFIXES) {
+            println!("   {:>6} file(s)  {}", files, prefix);
+        }
+    }
+    
+    Ok(())
+}
+
+/// Vacuum the database and report how much smaller it got
+fn compact(db: &RenameDatabase) -> Result<(), Box<dyn std::error::Error>> {
+    let size = || std::fs::metadata(db.path()).map(|metadata| metadata.len()).unwrap_or(0);
+    let before = size();
+    db.vacuum()?;
+    println!("💾 Compacted the history: {} → {}", format_size(before), format_size(size()));
+    Ok(())
+}
+
+/// Parse the arguments of commands whose only option is `--db`
+fn parse_only_db_flag(args: &[String]) -> Option<PathBuf> {
+    let mut db_path = None;
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            arg if arg == "--db" || arg.starts_with("--db=") => {
+                let Some((path, consumed)) = parse_db_flag(args, i) else {
+                    std::process::exit(1);
+                };
+                db_path = Some(path);
+                i += consumed;
+            }
+            arg => {
+                if arg != "--help" && arg != "-h" {
+                    eprintln!("❌ Unknown option: {}", arg);
+                }
+                print_help();
+                std::process::exit(if arg == "--help" || arg == "-h" { 0 } else { 1 });
+            }
+        }
+    }
+    db_path
+}
+
+/// An age like `90d`, `12w` or `48h`; a bare number is days
+fn parse_age(text: &str) -> Option<chrono::Duration> {
+    let (number, unit) = match text.find(|c: char| !c.is_ascii_digit()) {
+        Some(index) => text.split_at(index),
+        None => (text, "d"),
+    };
+    let number: i64 = number.parse().ok()?;
+    match unit {
+        "h" => chrono::Duration::try_hours(number),
+        "d" => chrono::Duration::try_days(number),
+        "w" => chrono::Duration::try_weeks(number),
+        _ => None,
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    if bytes < 1024 {
+        format!("{} B", bytes)
+    } else if bytes < 1024 * 1024 {
+        format!("{:.1} KiB", bytes as f64 / 1024.0)
+    } else {
+        format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+    }
+}
+
+/// Midnight UTC of a `YYYY-MM-DD` date (of the next day when `end_of_day`,
+/// so that `--until` includes the whole day), or an RFC 3339 time
+fn parse_date(text: &str, end_of_day: bool) -> Option<DateTime<Utc>> {
+    if let Ok(time) = DateTime::parse_from_rfc3339(text) {
+        return Some(time.with_timezone(&Utc));
+    }
+    
+    let date = NaiveDate::parse_from_str(text, "%Y-%m-%d").ok()?;
+    let date = if end_of_day { date.succ_opt()? } else { date };
+    Some(date.and_hms_opt(0, 0, 0)?.and_utc())
+}
+
+/// `path` relative to the working directory, the way renamed paths are recorded
+fn absolute(path: &Path) -> std::io::Result<PathBuf> {
+    if path.is_relative() {
+        Ok(std::env::current_dir()?.join(path))
+    } else {
+        Ok(path.to_path_buf())
+    }
+}
+
+fn export_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut format = ExportFormat::Json;
+    let mut direction = ScriptDirection::Replay;
+    let mut output: Option<PathBuf> = None;
+    let mut db_path: Option<PathBuf> = None;
+    let mut operation_ids: Vec<String> = Vec::new();
+    let mut i = 0;
+    
+    while i < args.len() {
+        match args[i].as_str() {
+            "--format" if i + 1 < args.len() => {
+                match ExportFormat::parse(&args[i + 1]) {
+                    Some(parsed) => format = parsed,
+                    None => {
+                        eprintln!("❌ Error: Unknown export format: {} (expected json, csv or sh)", args[i + 1]);
+                        std::process::exit(1);
+                    }
+                }
+                i += 2;
+            }
+            "--revert" => {
+                direction = ScriptDirection::Revert;
+                i += 1;
+            }
+            "--output" | "-o" if i + 1 < args.len() => {
+                output = Some(PathBuf::from(&args[i + 1]));
+                i += 2;
+            }
+            arg if arg == "--db" || arg.starts_with("--db=") => {
+                let Some((path, consumed)) = parse_db_flag(args, i) else {
+                    std::process::exit(1);
+                };
+                db_path = Some(path);
+                i += consumed;
+            }
+            "--help" | "-h" => {
+                print_help();
+                return Ok(());
+            }
+            arg if arg.starts_with('-') => {
+                eprintln!("❌ Unknown option: {}", arg);
+                print_help();
+                std::process::exit(1);
+            }
+            id => {
+                operation_ids.push(id.to_string());
+                i += 1;
+            }
+        }
+    }
+    
+    if direction == ScriptDirection::Revert && format != ExportFormat::Shell {
+        eprintln!("❌ Error: --revert only applies to --format sh");
+        std::process::exit(1);
+    }
+    
+    let db = open_database(db_path.as_deref(), Path::new("."))?;
+    if operation_ids.is_empty() {
+        operation_ids = db.operation_ids()?;
+    }
+    let export = HistoryExport::from_database(&db, &operation_ids)?;
+    
+    let content = match format {
+        ExportFormat::Json => (export.to_json()? + "\n").into_bytes(),
+        ExportFormat::Csv => export.to_csv().into_bytes(),
+        ExportFormat::Shell => export.to_shell(direction),
+    };
+    
+    match output {
+        Some(path) => {
+            std::fs::write(&path, &content)?;
+            #[cfg(unix)]
+            if format == ExportFormat::Shell {
+                use std::os::unix::fs::PermissionsExt;
+                std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+            }
+            eprintln!("✅ Exported {} operation(s) with {} rename(s) to {}",
+                      export.operations.len(), export.rename_count(), display_name(path.as_os_str()));
+        }
+        None => std::io::stdout().write_all(&content)?,
+    }
+    
+    Ok(())
+}
+
+fn import_command(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut db_path: Option<PathBuf> = None;
+    let mut files: Vec<PathBuf> = Vec::new();
+    let mut i = 0;
+    
+    while i < args.len() {
+        match args[i].as_str() {
+            arg if arg == "--db" || arg.starts_with("--db=") => {
+                let Some((path, consumed)) = parse_db_flag(args, i) else {
+                    std::process::exit(1);
+                };
+                db_path = Some(path);
+                i += consumed;
+            }
+            "--help" | "-h" => {
+                print_help();
+                return Ok(());
+            }
+            arg if arg.starts_with('-') => {
+                eprintln!("❌ Unknown option: {}", arg);
+                print_help();
+                std::process::exit(1);
+            }
+            file => {
+                files.push(PathBuf::from(file));
+                i += 1;
+            }
+        }
+    }
+    
+    if files.is_empty() {
+        eprintln!("❌ Error: history import requires the path of an exported JSON file");
+        std::process::exit(1);
+    }
+    
+    let db = open_database(db_path.as_deref(), Path::new("."))?;
+    for file in &files {
+        let export = HistoryExport::load(file)
+            .map_err(|e| format!("Could not read {}: {}", display_name(file.as_os_str()), e))?;
+        let summary = db.import_history(&export)?;
+        
+        println!("📥 {}: imported {} operation(s) with {} rename(s)",
+                 display_name(file.as_os_str()), summary.operations, summary.renames);
+        if !summary.skipped.is_empty() {
+            println!("⏭️  Skipped {} operation(s) already in the history: {}",
+                     summary.skipped.len(), summary.skipped.join(", "));
+        }
+    }
+    
+    Ok(())
+}
+
+fn print_help() {
+    println!("history - Search, trace, export and import the rename history");
+    println!();
+    println!("USAGE:");
+    println!("    ftmi history [FILTERS] [--page N] [--limit N]");
+    println!("    ftmi history trace [--db PATH] FILE");
+    println!("    ftmi history export [OPTIONS] [OPERATION_ID...]");
+    println!("    ftmi history import [--db PATH] FILE...");
+    println!("    ftmi history prune (--older-than AGE | --keep-last N) [--force] [--dry-run]");
+    println!("    ftmi history vacuum [--db PATH]");
+    println!("    ftmi history stats [--db PATH]");
+    println!();
+    println!("FILTERS:");
+    println!("    --since DATE          Operations with renames on or after DATE");
+    println!("    --until DATE          Operations with renames on or before DATE");
+    println!("                          (YYYY-MM-DD in UTC, or an RFC 3339 time)");
+    println!("    --dir DIR             Operations that renamed files in DIR or below it");
+    println!("    --prefix TEXT         Operations that removed a prefix containing TEXT");
+    println!("    --name GLOB           Operations that renamed a file from or to a name matching GLOB");
+    println!("    --tag TAG             Operations tagged TAG; may be repeated (all must match)");
+    println!("    --limit N             Operations per page (default {})", PAGE_SIZE);
+    println!("    --page N              Show page N, most recent operations first (default 1)");
+    println!("    --db PATH             Use this history database (see 'ftmi rename --help')");
+    println!();
+    println!("EXPORT OPTIONS:");
+    println!("    --format FORMAT       json (default), csv or sh");
+    println!("    --revert              With --format sh: write a script that renames the files back");
+    println!("    -o, --output FILE     Write to FILE instead of standard output");
+    println!("    --db PATH             Use this history database (see 'ftmi rename --help')");
+    println!("    -h, --help            Show this help message");
+    println!();
+    println!("PRUNE OPTIONS:");
+    println!("    --older-than AGE      Operations started more than AGE ago: 90d, 12w or 48h");
+    println!("    --keep-last N         All but the N most recent operations");
+    println!("                          (with both, operations matching both are pruned)");
+    println!("    --force               Also remove operations that could still be undone");
+    println!("    --dry-run             Only list the operations that would be removed");
+    println!();
+    println!("DESCRIPTION:");
+    println!("    Without a command, lists the operations matching every filter given.");
+    println!("    trace shows every rename of a file: the names it had before, with the");
+    println!("    operation that renamed it each time, and what it was renamed to later.");
+    println!("    export writes the given operations, or the whole history when no ID is");
+    println!("    given, oldest first:");
+    println!("      - json: everything recorded, for archiving and 'ftmi history import'");
+    println!("      - csv:  one row per rename, for spreadsheets");
+    println!("      - sh:   a shell script of mv commands that replays the renames, or");
+    println!("              with --revert renames the files back; it never overwrites a");
+    println!("              file and exits with status 2 if a rename was skipped or failed");
+    println!("    import merges JSON exports, e.g. from another machine, into the history.");
+    println!("    Operations already in the history are skipped, so importing the same");
+    println!("    file twice is harmless. Imported operations can be listed and undone.");
+    println!("    prune deletes old operations and then vacuums the database. Operations");
+    println!("    with renames still in effect are kept unless --force is given, so");
+    println!("    everything that could be undone before still can. vacuum compacts the");
+    println!("    database on its own. stats summarizes the history: operations per day,");
+    println!("    files renamed, the most removed prefixes and how often operations were undone.");
+    println!();
+    println!("EXAMPLES:");
+    println!("    ftmi history --since 2024-06-01 --dir ~/Music --name '*.flac'");
+    println!("    ftmi history --tag music --page 2");
+    println!("    ftmi history trace ~/Music/Rock/Song.mp3");
+    println!("    ftmi history export > history.json");
+    println!("    ftmi history export --format sh --revert -o undo.sh op_01J...");
+    println!("    ftmi history import laptop-history.json");
+    println!("    ftmi history prune --older-than 90d --dry-run");
+    println!("    ftmi history stats");
+}
@@ -1,13 +1,196 @@
+pub mod apply;
 pub mod extract_paths;
 pub mod find_delimited;
 pub mod find_specific;
+pub mod history;
+pub mod recover;
 pub mod detect_all;
 pub mod remove_prefix;
 pub mod rename;
+pub mod tui;
 
+pub use apply::apply_command;
 pub use extract_paths::extract_paths_command;
 pub use find_delimited::find_delimited_command;
 pub use find_specific::find_specific_command;
+pub use history::history_command;
+pub use recover::recover_command;
 pub use detect_all::detect_all_command;
 pub use remove_prefix::remove_prefix_command;
-pub use rename::rename_command;
\ No newline at end of file
+pub use rename::rename_command;
+pub use tui::tui_command;
+
+use crate::os_names::display_name;
+use crate::prefix_finder::{PrefixOptions, Recursion, TreeGrouping, UnicodeNormalization};
+use crate::rename_db::{CollisionStrategy, DatabaseSource, RenameDatabase, RenameOutcome};
+use crate::rename_plan::{plan_conflicts, PlanGroup};
+use std::path::{Path, PathBuf};
+
+/// Scanning flags shared by the directory scanning subcommands
+/// (`--recursive`, `--max-depth N`, `--pool` and `--normalize FORM`)
+#[derive(Debug, Default)]
+pub(crate) struct ScanFlags {
+    recursive: bool,
+    max_depth: Option<usize>,
+    pooled: bool,
+    normalization: Option<UnicodeNormalization>,
+}
+
+impl ScanFlags {
+    /// Try to consume a scanning flag at `args[i]`.
+    /// Returns the number of arguments used, or 0 if `args[i]` is not a scanning flag.
+    /// A missing or invalid value is an error: guessing could scan far more of
+    /// the tree than intended.
+    pub(crate) fn parse(&mut self, args: &[String], i: usize) -> Result<usize, String> {
+        match args[i].as_str() {
+            "--recursive" => {
+                self.recursive = true;
+                Ok(1)
+            }
+            "--max-depth" => {
+                let value = args.get(i + 1).ok_or("--max-depth requires a number")?;
+                let depth = value.parse::<usize>()
+                    .map_err(|_| format!("Invalid --max-depth value: {} (expected a number)", value))?;
+                self.max_depth = Some(depth);
+                self.recursive = true;
+                Ok(2)
+            }
+            "--pool" => {
+                self.recursive = true;
+                self.pooled = true;
+                Ok(1)
+            }
+            "--normalize" => {
+                let value = args.get(i + 1).ok_or("--normalize requires a form (nfc or nfd)")?;
+                let form = UnicodeNormalization::parse(value)
+                    .ok_or_else(|| format!("Unknown --normalize form: {} (expected nfc or nfd)", value))?;
+                self.normalization = Some(form);
+                Ok(2)
+            }
+            _ => Ok(0),
+        }
+    }
+    
+    /// Build the recursion settings for `PrefixOptions`
+    pub(crate) fn recursion(&self) -> Option<Recursion> {
+        if !self.recursive {
+            return None;
+        }
+        
+        Some(Recursion {
+            max_depth: self.max_depth,
+            grouping: if self.pooled { TreeGrouping::Pooled } else { TreeGrouping::PerDirectory },
+        })
+    }
+    
+    /// Copy the parsed flags into `options`
+    pub(crate) fn apply(&self, options: &mut PrefixOptions) {
+        options.recursion = self.recursion();
+        options.normalization = self.normalization;
+    }
+}
+
+/// Parse `--on-collision STRATEGY` or `--on-collision=STRATEGY` at `args[i]`.
+/// Returns the strategy and the number of arguments used, or prints an error.
+pub(crate) fn parse_on_collision(args: &[String], i: usize) -> Option<(CollisionStrategy, usize)> {
+    let (value, consumed) = match args[i].strip_prefix("--on-collision=") {
+        Some(value) => (Some(value), 1),
+        None => (args.get(i + 1).map(String::as_str), 2),
+    };
+    
+    let Some(value) = value else {
+        eprintln!("❌ Error: --on-collision requires skip, number, suffix, subfolder or backup");
+        return None;
+    };
+    match CollisionStrategy::parse(value) {
+        Some(strategy) => Some((strategy, consumed)),
+        None => {
+            eprintln!("❌ Error: Unknown --on-collision (expected skip, number, suffix, subfolder or backup): {}", value);
+            None
+        }
+    }
+}
+
+/// `--atomic`: check every rename of an operation before touching any file,
+/// after resolving taken names with `on_collision`.
+/// Prints the conflicts found and returns how many there are.
+pub(crate) fn atomic_precheck(groups: &[PlanGroup], on_collision: Option<CollisionStrategy>) -> usize {
+    let conflicts = plan_conflicts(groups, on_collision);
+    if conflicts.is_empty() {
+        return 0;
+    }
+    
+    eprintln!("❌ --atomic: {} conflict(s) found, nothing was renamed:", conflicts.len());
+    for (old_path, new_path, conflict) in &conflicts {
+        let reason = match conflict {
+            RenameOutcome::SourceMissing => "file no longer exists".to_string(),
+            RenameOutcome::TargetExists => "target already exists".to_string(),
+            RenameOutcome::DuplicateTarget => "another file gets the same name".to_string(),
+            RenameOutcome::Failed(e) => e.clone(),
+            RenameOutcome::IdentityMismatch(mismatch) => mismatch.describe().to_string(),
+            RenameOutcome::Renamed | RenameOutcome::Unchanged | RenameOutcome::Skipped => continue,
+        };
+        eprintln!("   {} → {}: {}",
+                  display_name(old_path.file_name().unwrap_or_default()),
+                  display_name(new_path.file_name().unwrap_or_default()),
+                  reason);
+    }
+    conflicts.len()
+}
+
+/// `--atomic`: undo the renames an operation already did after one of them failed
+pub(crate) fn roll_back(db: &RenameDatabase, operation_id: &str) {
+    println!("↩️  --atomic: rolling back operation {}...", operation_id);
+    match db.rollback_operation(operation_id) {
+        Ok((restored, 0)) => println!("↩️  Restored {} file(s), nothing was renamed", restored),
+        Ok((restored, failed)) => {
+            eprintln!("⚠️  Restored {} file(s), {} could not be restored and remain in the history", restored, failed);
+        }
+        Err(e) => eprintln!("❌ Rollback failed: {} (use 'ftmi rename --undo {}')", e, operation_id),
+    }
+}
+
+/// Parse `--db PATH` or `--db=PATH` at `args[i]`.
+/// Returns the path and the number of arguments used, or prints an error.
+pub(crate) fn parse_db_flag(args: &[String], i: usize) -> Option<(PathBuf, usize)> {
+    let (value, consumed) = match args[i].strip_prefix("--db=") {
+        Some(value) => (Some(value), 1),
+        None => (args.get(i + 1).map(String::as_str), 2),
+    };
+    
+    match value.filter(|value| !value.is_empty()) {
+        Some(value) => Some((PathBuf::from(value), consumed)),
+        None => {
+            eprintln!("❌ Error: --db requires the path of a history database");
+            None
+        }
+    }
+}
+
+/// Open (creating or upgrading) the history database for work on `start`,
+/// see `RenameDatabase::locate`. Says which history is used unless it is the
+/// user's own, on standard error so exports to standard output stay clean.
+pub(crate) fn open_database(db_flag: Option<&Path>, start: &Path) -> Result<RenameDatabase, Box<dyn std::error::Error>> {
+    let (path, source) = RenameDatabase::locate(db_flag, start)?;
+    match source {
+        DatabaseSource::Project => eprintln!("🗄️  Using project history: {}", display_name(path.as_os_str())),
+        DatabaseSource::Flag | DatabaseSource::Environment => {
+            eprintln!("🗄️  Using history: {}", display_name(path.as_os_str()));
+        }
+        DatabaseSource::User => {}
+    }
+    
+    let db = RenameDatabase::new(path);
+    db.initialize()?;
+    Ok(db)
+}
+
+/// Point out renames left unfinished by an interrupted run
+pub(crate) fn warn_about_journal(db: &RenameDatabase) {
+    if let Ok(entries) = db.journal_entries() {
+        if !entries.is_empty() {
+            eprintln!("⚠️  {} rename(s) from an interrupted run are not in the history yet.", entries.len());
+            eprintln!("   Run 'ftmi recover' to reconcile them with the filesystem.");
+        }
+    }
+}
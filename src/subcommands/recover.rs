@@ -0,0 +1,88 @@
+use crate::{display_name, Recovery};
+use crate::subcommands::{open_database, parse_db_flag};
+use std::path::{Path, PathBuf};
+
+pub fn recover_command(args: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut db_path: Option<PathBuf> = None;
+    let mut i = 2; // Skip "ftmi" and "recover"
+    
+    while i < args.len() {
+        match args[i].as_str() {
+            arg if arg == "--db" || arg.starts_with("--db=") => {
+                let Some((path, consumed)) = parse_db_flag(&args, i) else {
+                    return Ok(());
+                };
+                db_path = Some(path);
+                i += consumed;
+            }
+            arg => {
+                if arg != "--help" && arg != "-h" {
+                    eprintln!("❌ Unknown option: {}", arg);
+                }
+                print_help();
+                return Ok(());
+            }
+        }
+    }
+    
+    println!("🔧 FTMI Recover Interrupted Renames");
+    
+    let db = open_database(db_path.as_deref(), Path::new("."))?;
+    
+    let results = db.recover()?;
+    if results.is_empty() {
+        println!("✅ Nothing to recover, the history is complete.");
+        return Ok(());
+    }
+    
+    println!("📋 Found {} unfinished rename(s) in the journal:", results.len());
+    
+    let mut stranded = 0;
+    for (entry, recovery) in &results {
+        let old_name = display_name(entry.old_path.as_os_str());
+        let new_name = display_name(entry.new_path.as_os_str());
+        match recovery {
+            Recovery::Recorded => {
+                println!("✓ Recorded: {} → {} ({})", old_name, new_name, entry.operation_id);
+            }
+            Recovery::Restored => println!("↩️  Restored: {} (moved back from its temporary name)", old_name),
+            Recovery::NotStarted => println!("⏭️  Not started: {} → {}", old_name, new_name),
+            Recovery::Missing => eprintln!("⚠️  Missing: neither {} nor {} exists", old_name, new_name),
+            Recovery::Stranded => {
+                stranded += 1;
+                let temp_name = entry.temp_path.as_deref().map(|path| display_name(path.as_os_str()).into_owned());
+                eprintln!("❌ Stranded: {} is still at {} because both names are taken",
+                          old_name, temp_name.unwrap_or_default());
+            }
+        }
+    }
+    
+    if stranded > 0 {
+        eprintln!("⚠️  Move the stranded files by hand, then run 'ftmi recover' again.");
+        std::process::exit(2);
+    }
+    
+    println!("✅ Recovery complete.");
+    Ok(())
+}
+
+fn print_help() {
+    println!("recover - Finish the history of renames interrupted by a crash");
+    println!();
+    println!("USAGE:");
+    println!("    ftmi recover [--db PATH]");
+    println!();
+    println!("OPTIONS:");
+    println!("    --db PATH     Use this history database (see 'ftmi rename --help')");
+    println!("    -h, --help    Show this help message");
+    println!();
+    println!("DESCRIPTION:");
+    println!("    Every rename is written to a journal before the file is touched and");
+    println!("    moved to the history once it is done. If ftmi was killed or crashed in");
+    println!("    between, the journal still lists the rename. This command compares those");
+    println!("    entries with the filesystem:");
+    println!("      - renames that happened are added to the history so they can be undone");
+    println!("      - files left under a temporary name are moved back to their old name");
+    println!("      - renames that never started are dropped");
+    println!("    Exits with status 2 when a file could not be moved back.");
+}
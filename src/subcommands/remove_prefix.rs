@@ -1,4 +1,7 @@
-use crate::prefix_finder::{find_longest_prefix, PrefixOptions, remove_prefix};
+use crate::os_names::display_name;
+use crate::prefix_finder::{directories_to_analyze, PrefixOptions};
+use crate::rename_plan::{plan_directory, Affix};
+use crate::subcommands::ScanFlags;
 use std::io::{self, BufRead};
 use std::path::Path;
 
@@ -12,11 +15,21 @@ pub fn remove_prefix_command(args: Vec<String>) -> Result<(), Box<dyn std::error
     // Parse options
     let mut preview_only = true; // Default to preview mode
     let mut custom_regex: Option<String> = None;
+    let mut scan_flags = ScanFlags::default();
     let mut directories = Vec::new();
     let mut i = 2;
     
     while i < args.len() {
-        if args[i] == "--execute" {
+        let consumed = match scan_flags.parse(&args, i) {
+            Ok(consumed) => consumed,
+            Err(e) => {
+                eprintln!("❌ Error: {}", e);
+                std::process::exit(1);
+            }
+        };
+        if consumed > 0 {
+            i += consumed;
+        } else if args[i] == "--execute" {
             preview_only = false;
             i += 1;
         } else if args[i] == "--regex" && i + 1 < args.len() {
@@ -30,11 +43,12 @@ pub fn remove_prefix_command(args: Vec<String>) -> Result<(), Box<dyn std::error
         }
     }
     
-    let options = if let Some(regex) = custom_regex {
+    let mut options = if let Some(regex) = custom_regex {
         PrefixOptions::with_regex(&regex)
     } else {
         PrefixOptions::default()
     };
+    scan_flags.apply(&mut options);
     
     // Process directories from command line or stdin
     if !directories.is_empty() {
@@ -71,7 +85,22 @@ fn process_directory(dir_path: &str, options: &PrefixOptions, preview_only: bool
         return Ok(());
     }
     
-    println!("Directory: {}", dir_path);
+    match directories_to_analyze(path, options) {
+        Ok(directories) => {
+            for directory in directories {
+                report_directory(&directory, options, preview_only);
+            }
+        }
+        Err(e) => {
+            eprintln!("Error processing directory {}: {}", dir_path, e);
+        }
+    }
+    
+    Ok(())
+}
+
+fn report_directory(path: &Path, options: &PrefixOptions, preview_only: bool) {
+    println!("Directory: {}", path.display());
     if preview_only {
         println!("Mode: PREVIEW ONLY (use --execute to actually rename files)");
     } else {
@@ -79,27 +108,27 @@ fn process_directory(dir_path: &str, options: &PrefixOptions, preview_only: bool
     }
     println!("{}", "-".repeat(50));
     
-    match find_longest_prefix(path, options) {
-        Ok(prefixed_paths) => {
-            if prefixed_paths.is_empty() {
+    // Previews come from the same plan that 'ftmi rename --plan' writes
+    match plan_directory(path, options, Affix::Prefix) {
+        Ok(groups) => {
+            if groups.is_empty() {
                 println!("No common prefixes found for removal");
             } else {
-                for prefixed_path in prefixed_paths {
-                    println!("Prefix to remove: {}", prefixed_path.prefix);
-                    println!("Files ({}):", prefixed_path.paths.len());
+                for group in groups {
+                    println!("Prefix to remove: {}", group.label());
+                    println!("Files ({}):", group.renames.len());
                     
-                    for file_path in &prefixed_path.paths {
-                        if let Some(filename) = file_path.file_name().and_then(|s| s.to_str()) {
-                            let new_name = remove_prefix(filename, &prefixed_path.prefix);
+                    for (file_path, new_path) in group.paths() {
+                        if let (Some(filename), Some(new_name)) = (file_path.file_name(), new_path.file_name()) {
+                            let (shown_old, shown_new) = (display_name(filename), display_name(new_name));
                             
                             if preview_only {
-                                println!("  {} → {}", filename, new_name);
+                                println!("  {} → {}", shown_old, shown_new);
                             } else {
                                 // Actually rename the file
-                                let new_path = file_path.with_file_name(&new_name);
-                                match std::fs::rename(file_path, &new_path) {
-                                    Ok(_) => println!("  ✓ {} → {}", filename, new_name),
-                                    Err(e) => eprintln!("  ✗ {} → {}: {}", filename, new_name, e),
+                                match std::fs::rename(&file_path, &new_path) {
+                                    Ok(_) => println!("  ✓ {} → {}", shown_old, shown_new),
+                                    Err(e) => eprintln!("  ✗ {} → {}: {}", shown_old, shown_new, e),
                                 }
                             }
                         }
@@ -110,15 +139,14 @@ fn process_directory(dir_path: &str, options: &PrefixOptions, preview_only: bool
                 if preview_only {
                     println!("💡 This was a preview. Use --execute to actually rename files.");
                     println!("💡 For interactive renaming with undo support, use: ftmi rename");
+                    println!("💡 To save these renames for review, use: ftmi rename --plan plan.json");
                 }
             }
         }
         Err(e) => {
-            eprintln!("Error processing directory {}: {}", dir_path, e);
+            eprintln!("Error processing directory {}: {}", path.display(), e);
         }
     }
-    
-    Ok(())
 }
 
 fn print_help() {
@@ -131,6 +159,10 @@ fn print_help() {
     println!("OPTIONS:");
     println!("    --execute          Actually perform the renames (default is preview only)");
     println!("    --regex PATTERN    Custom regex pattern for prefix filtering");
+    println!("    --recursive        Also process every subdirectory as its own group");
+    println!("    --max-depth NUM    Limit recursion depth (implies --recursive)");
+    println!("    --pool             Treat the whole tree as a single pool of files");
+    println!("    --normalize FORM   Unicode-normalize names (nfc or nfd) before comparing");
     println!("    -h, --help        Show this help message");
     println!();
     println!("DESCRIPTION:");
@@ -1,18 +1,55 @@
-use crate::{find_longest_prefix, PrefixOptions, PrefixedPath, RenameDatabase, generate_operation_id, tracked_rename};
+use crate::{
+    apply_edits, display_name, edit_buffer, plan_directory, Affix, CollisionStrategy, HistoryQuery, OperationKind, PlanGroup,
+    OperationContext, PrefixOptions, RenameDatabase, RenamePlan, RenameRecord, UndoOptions, generate_operation_id
+};
+use crate::prefix_finder::directories_to_analyze;
+use crate::rename_edit::is_valid_name;
+use crate::selection::{parse_ranges, parse_selection_command, Selection, SelectionCommand};
+use crate::subcommands::{
+    atomic_precheck, open_database, parse_db_flag, parse_on_collision, roll_back, warn_about_journal, ScanFlags
+};
+use std::ffi::OsStr;
 use std::io::{self, BufRead, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus};
 use std::env;
+use std::fs;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+/// Number of recent operations searched for the one to undo or redo
+const HISTORY_SEARCH_LIMIT: usize = 100;
+
+/// Number of operations shown by `--list`
+const LIST_LIMIT: usize = 20;
 
 pub fn rename_command(args: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
     // Parse command line arguments
     let mut options = PrefixOptions::default();
     let mut directories: Vec<String> = Vec::new();
     let mut continuous_mode = false;
+    let mut affix = Affix::Prefix;
+    let mut edit = false;
+    let mut atomic = false;
+    let mut assume: Option<bool> = None;
+    let mut policy = GroupPolicy::AllGroups;
+    let mut on_collision = None;
     let mut undo_mode = false;
     let mut list_operations = false;
     let mut undo_operation_id: Option<String> = None;
+    let mut only: Option<String> = None;
+    let mut force = false;
+    let mut note: Option<String> = None;
+    let mut tags: Vec<String> = Vec::new();
+    let mut show_operation_id: Option<String> = None;
+    let mut annotate_operation_id: Option<String> = None;
+    let mut redo_mode = false;
+    let mut redo_operation_id: Option<String> = None;
+    let mut split_mode = false;
+    let mut split_operation_id: Option<String> = None;
+    let mut plan_path: Option<PathBuf> = None;
+    let mut db_path: Option<PathBuf> = None;
+    let mut scan_flags = ScanFlags::default();
     let mut i = 2; // Skip "ftmi" and "rename"
     
     while i < args.len() {
@@ -23,7 +60,7 @@ pub fn rename_command(args: Vec<String>) -> Result<(), Box<dyn std::error::Error
                     i += 2;
                 } else {
                     eprintln!("❌ Error: --regex requires a pattern argument");
-                    return Ok(());
+                    std::process::exit(1);
                 }
             }
             "--no-filter" => {
@@ -34,6 +71,83 @@ pub fn rename_command(args: Vec<String>) -> Result<(), Box<dyn std::error::Error
                 continuous_mode = true;
                 i += 1;
             }
+            "--suffix" => {
+                affix = Affix::Suffix;
+                i += 1;
+            }
+            "--edit" | "-e" => {
+                edit = true;
+                i += 1;
+            }
+            "--atomic" => {
+                atomic = true;
+                i += 1;
+            }
+            "--yes" | "-y" => {
+                assume = Some(true);
+                i += 1;
+            }
+            "--assume-no" => {
+                assume = Some(false);
+                i += 1;
+            }
+            "--policy" => {
+                if i + 1 < args.len() {
+                    match GroupPolicy::parse(&args[i + 1]) {
+                        Some(parsed) => policy = parsed,
+                        None => {
+                            eprintln!("❌ Error: Unknown --policy (expected first-group or all-groups): {}", args[i + 1]);
+                            std::process::exit(1);
+                        }
+                    }
+                    i += 2;
+                } else {
+                    eprintln!("❌ Error: --policy requires first-group or all-groups");
+                    std::process::exit(1);
+                }
+            }
+            arg if arg.starts_with("--policy=") => {
+                match GroupPolicy::parse(&arg["--policy=".len()..]) {
+                    Some(parsed) => policy = parsed,
+                    None => {
+                        eprintln!("❌ Error: Unknown --policy (expected first-group or all-groups): {}", &arg["--policy=".len()..]);
+                        std::process::exit(1);
+                    }
+                }
+                i += 1;
+            }
+            arg if arg == "--on-collision" || arg.starts_with("--on-collision=") => {
+                let Some((strategy, consumed)) = parse_on_collision(&args, i) else {
+                    std::process::exit(1);
+                };
+                on_collision = Some(strategy);
+                i += consumed;
+            }
+            arg if arg == "--db" || arg.starts_with("--db=") => {
+                let Some((path, consumed)) = parse_db_flag(&args, i) else {
+                    std::process::exit(1);
+                };
+                db_path = Some(path);
+                i += consumed;
+            }
+            "--plan" => {
+                if i + 1 < args.len() {
+                    plan_path = Some(PathBuf::from(&args[i + 1]));
+                    i += 2;
+                } else {
+                    eprintln!("❌ Error: --plan requires an output file");
+                    std::process::exit(1);
+                }
+            }
+            "--recursive" | "--max-depth" | "--pool" | "--normalize" => {
+                match scan_flags.parse(&args, i) {
+                    Ok(consumed) => i += consumed,
+                    Err(e) => {
+                        eprintln!("❌ Error: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+            }
             "--undo" | "-u" => {
                 undo_mode = true;
                 if i + 1 < args.len() && !args[i + 1].starts_with("-") {
@@ -46,6 +160,50 @@ pub fn rename_command(args: Vec<String>) -> Result<(), Box<dyn std::error::Error
                     i += 1;
                 }
             }
+            "--redo" => {
+                redo_mode = true;
+                if i + 1 < args.len() && !args[i + 1].starts_with("-") {
+                    redo_operation_id = Some(args[i + 1].clone());
+                    i += 2;
+                } else {
+                    i += 1;
+                }
+            }
+            "--split" => {
+                split_mode = true;
+                if i + 1 < args.len() && !args[i + 1].starts_with("-") {
+                    split_operation_id = Some(args[i + 1].clone());
+                    i += 2;
+                } else {
+                    i += 1;
+                }
+            }
+            "--only" => {
+                if i + 1 < args.len() {
+                    only = Some(args[i + 1].clone());
+                    i += 2;
+                } else {
+                    eprintln!("❌ Error: --only requires file numbers (e.g. 2,4-5) or a glob pattern");
+                    std::process::exit(1);
+                }
+            }
+            "--force" => {
+                force = true;
+                i += 1;
+            }
+            "--note" | "--tag" | "--show" | "--annotate" => {
+                let Some(value) = args.get(i + 1) else {
+                    eprintln!("❌ Error: {} requires an argument", args[i]);
+                    std::process::exit(1);
+                };
+                match args[i].as_str() {
+                    "--note" => note = Some(value.clone()),
+                    "--tag" => tags.push(value.clone()),
+                    "--show" => show_operation_id = Some(value.clone()),
+                    _ => annotate_operation_id = Some(value.clone()),
+                }
+                i += 2;
+            }
             "--list" | "-l" => {
                 list_operations = true;
                 i += 1;
@@ -57,7 +215,7 @@ pub fn rename_command(args: Vec<String>) -> Result<(), Box<dyn std::error::Error
             arg if arg.starts_with("--") => {
                 eprintln!("❌ Unknown option: {}", arg);
                 print_help();
-                return Ok(());
+                std::process::exit(1);
             }
             _ => {
                 directories.push(args[i].clone());
@@ -66,32 +224,95 @@ pub fn rename_command(args: Vec<String>) -> Result<(), Box<dyn std::error::Error
         }
     }
     
+    if only.is_some() && !undo_mode {
+        eprintln!("❌ Error: --only can only be used with --undo");
+        std::process::exit(1);
+    }
+    
+    if force && !undo_mode && !redo_mode {
+        eprintln!("❌ Error: --force can only be used with --undo or --redo");
+        std::process::exit(1);
+    }
+    
+    if edit && assume.is_some() {
+        eprintln!("❌ Error: --edit cannot be combined with --yes or --assume-no");
+        std::process::exit(1);
+    }
+    
+    if annotate_operation_id.is_some() && note.is_none() && tags.is_empty() {
+        eprintln!("❌ Error: --annotate requires --note or --tag");
+        std::process::exit(1);
+    }
+    
+    scan_flags.apply(&mut options);
+    let mut context = OperationContext::current();
+    context.note = note;
+    context.tags = tags;
+    let mut settings = RenameSettings { options, affix, edit, atomic, assume, policy, on_collision, context };
+    settings.context.settings = Some(settings.to_json());
+    
     println!("🔧 FTMI Interactive Prefix Removal Tool");
     
-    // Initialize database
-    let db_path = RenameDatabase::default_path()?;
-    let db = RenameDatabase::new(db_path);
-    db.initialize()?;
+    // Initialize database: a project history next to the first directory
+    // given (or the working directory) takes precedence over the user's
+    let start = directories.first().map_or(Path::new("."), |dir| Path::new(dir.as_str()));
+    let db = open_database(db_path.as_deref(), start)?;
+    warn_about_journal(&db);
     
     // Handle different modes
     if list_operations {
         return list_recent_operations(&db);
     }
     
+    if let Some(op_id) = show_operation_id {
+        return show_operation(&db, &op_id);
+    }
+    
+    if let Some(op_id) = annotate_operation_id {
+        if db.get_operation_renames(&op_id)?.is_empty() {
+            eprintln!("❌ Operation ID '{}' not found.", op_id);
+            std::process::exit(1);
+        }
+        db.annotate_operation(&op_id, settings.context.note.as_deref(), &settings.context.tags)?;
+        println!("📝 Annotated operation {}", op_id);
+        return Ok(());
+    }
+    
+    // Undo and redo record who ran them, but not the rename settings
+    let revert_context = OperationContext { settings: None, ..settings.context.clone() };
+    let revert_options = UndoOptions { force, context: Some(&revert_context), ..Default::default() };
     if undo_mode {
-        if let Some(op_id) = undo_operation_id {
-            return undo_operation(&db, &op_id);
+        let failures = if let Some(op_id) = undo_operation_id {
+            undo_operation(&db, &op_id, only.as_deref(), revert_options, settings.assume)?
         } else {
-            return undo_most_recent_operation(&db);
-        }
+            undo_most_recent_operation(&db, only.as_deref(), revert_options, settings.assume)?
+        };
+        return exit_on_failures(failures);
+    }
+    
+    if redo_mode {
+        let failures = match redo_operation_id {
+            Some(op_id) => redo_operation(&db, &op_id, revert_options, settings.assume)?,
+            None => redo_most_recent_operation(&db, revert_options, settings.assume)?,
+        };
+        return exit_on_failures(failures);
+    }
+    
+    if split_mode {
+        return split_merged_operations(&db, split_operation_id.as_deref());
     }
     
     if continuous_mode {
-        return run_continuous_mode(&db, &options);
+        if plan_path.is_some() {
+            eprintln!("❌ Error: --plan cannot be combined with --continuous");
+            std::process::exit(1);
+        }
+        let failures = run_continuous_mode(&db, &settings)?;
+        return exit_on_failures(failures);
     }
     
     // Normal mode
-    if let Some(regex_pattern) = &options.filter_regex {
+    if let Some(regex_pattern) = &settings.options.filter_regex {
         println!("🔍 Using regex filter: {}", regex_pattern);
     } else {
         println!("🔍 No regex filter (accepting all prefixes)");
@@ -119,12 +340,15 @@ pub fn rename_command(args: Vec<String>) -> Result<(), Box<dyn std::error::Error
         eprintln!("  ftmi rename ./music ./photos");
         eprintln!("  echo './music' | ftmi rename");
         eprintln!("  echo './music' | ftmi rename ./photos");
-        return Ok(());
+        std::process::exit(1);
     }
     
     println!("📊 Processing {} directories total\n", directories.len());
     
     // Phase 2: Process each directory
+    // With --plan, groups are collected into a plan instead of being applied
+    let mut plan = plan_path.as_ref().map(|_| RenamePlan::new(Vec::new()));
+    let mut failures = 0;
     for dir_path in directories {
         let dir_path = dir_path.trim();
         
@@ -132,179 +356,100 @@ pub fn rename_command(args: Vec<String>) -> Result<(), Box<dyn std::error::Error
             continue;
         }
         
-        // Convert relative paths to absolute paths
-        let path = if Path::new(dir_path).is_relative() {
-            env::current_dir()?.join(dir_path)
-        } else {
-            Path::new(dir_path).to_path_buf()
-        };
-        if !path.exists() {
-            eprintln!("❌ Warning: Directory does not exist: {}", dir_path);
-            continue;
-        }
-        
-        if !path.is_dir() {
-            eprintln!("❌ Warning: Not a directory: {}", dir_path);
-            continue;
-        }
-        
-        match find_longest_prefix(&path, &options) {
-            Ok(prefixed_paths) => {
-                if prefixed_paths.is_empty() {
-                    println!("📁 Directory: {}", dir_path);
-                    println!("ℹ️  No bracket-delimited prefixes found\n");
-                    continue;
-                }
-                
-                println!("📁 Directory: {}", dir_path);
-                println!("Found {} prefix group(s) with highest occurrence count:\n", prefixed_paths.len());
-                
-                for (i, prefixed_path) in prefixed_paths.iter().enumerate() {
-                    println!("🏷️  Prefix {}: [{}]", i + 1, prefixed_path.prefix);
-                    println!("   Files ({}):", prefixed_path.paths.len());
-                    
-                    // Show preview of what files would look like after prefix removal
-                    for path in &prefixed_path.paths {
-                        if let Some(filename) = path.file_name().and_then(|s| s.to_str()) {
-                            let new_filename = remove_bracket_prefix(filename, &prefixed_path.prefix);
-                            println!("   {} → {}", filename, new_filename);
-                        }
-                    }
-                    
-                    // Ask for confirmation
-                    print!("\n💡 Remove prefix [{}] from these {} files? (Y/n/s=skip, default=Y): ", 
-                           prefixed_path.prefix, prefixed_path.paths.len());
-                    io::stdout().flush()?;
-                    
-                    let mut response = String::new();
-                    
-                    // Read user input from terminal even when stdin is piped
-                    #[cfg(unix)]
-                    {
-                        use std::fs::OpenOptions;
-                        use std::io::BufReader;
-                        let tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
-                        let mut tty_reader = BufReader::new(tty);
-                        tty_reader.read_line(&mut response)?;
-                    }
-                    
-                    #[cfg(not(unix))]
-                    {
-                        // On Windows, try to read from CONIN$
-                        use std::fs::OpenOptions;
-                        use std::io::BufReader;
-                        match OpenOptions::new().read(true).open("CONIN$") {
-                            Ok(con) => {
-                                let mut con_reader = BufReader::new(con);
-                                con_reader.read_line(&mut response)?;
-                            }
-                            Err(_) => {
-                                // Fallback to regular stdin
-                                io::stdin().read_line(&mut response)?;
-                            }
-                        }
-                    }
-                    let response = response.trim().to_lowercase();
-                    
-                    match response.as_str() {
-                        "y" | "yes" | "" => {  // Empty string (just Enter) defaults to yes
-                            println!("✅ Proceeding with prefix removal...");
-                            
-                            let operation_id = generate_operation_id();
-                            let mut success_count = 0;
-                            let mut error_count = 0;
-                            
-                            for old_path in &prefixed_path.paths {
-                                if let Some(filename) = old_path.file_name().and_then(|s| s.to_str()) {
-                                    let new_filename = remove_bracket_prefix(filename, &prefixed_path.prefix);
-                                    
-                                    // Skip if new filename would be the same
-                                    if new_filename == filename {
-                                        println!("   ⏭️  {} (no change needed)", filename);
-                                        continue;
-                                    }
-                                    
-                                    let new_path = old_path.with_file_name(&new_filename);
-                                    
-                                    // Check if target file already exists
-                                    if new_path.exists() {
-                                        error_count += 1;
-                                        eprintln!("   ❌ Target file already exists: {}", new_filename);
-                                        continue;
-                                    }
-                                    
-                                    println!("   🔄 Renaming: {} → {}", filename, new_filename);
-                                    
-                                    match tracked_rename(&db, old_path, &new_path, &prefixed_path.prefix, &operation_id) {
-                                        Ok(_) => {
-                                            success_count += 1;
-                                            println!("   ✓ Success!");
-                                        }
-                                        Err(e) => {
-                                            error_count += 1;
-                                            eprintln!("   ❌ Failed: {}", e);
-                                        }
-                                    }
-                                }
-                            }
-                            
-                            println!("📊 Results: {} successful, {} failed", success_count, error_count);
-                            if success_count > 0 {
-                                println!("💾 Operation ID: {} (use this to undo if needed)", operation_id);
-                            }
-                        }
-                        "n" | "no" => {
-                            println!("❌ Skipped prefix removal for [{}]", prefixed_path.prefix);
-                        }
-                        "s" | "skip" => {
-                            println!("⏭️  Skipped prefix [{}]", prefixed_path.prefix);
-                        }
-                        _ => {
-                            println!("❓ Unknown response '{}', skipping...", response);
-                        }
-                    }
-                    
-                    println!();
-                }
-            }
-            Err(e) => {
-                eprintln!("❌ Error processing directory {}: {}", dir_path, e);
-            }
-        }
+        failures += process_directory(&db, &settings, dir_path, plan.as_mut())?;
         
         println!("{}", "─".repeat(60));
     }
     
+    if let (Some(plan), Some(plan_path)) = (plan, plan_path) {
+        plan.save(&plan_path)?;
+        println!("💾 Wrote rename plan to {} ({} group(s), {} rename(s))",
+                 plan_path.display(), plan.groups.len(), plan.rename_count());
+        println!("💡 Review or edit it, then run 'ftmi apply {}'", plan_path.display());
+        return Ok(());
+    }
+    
     println!("🏁 Interactive prefix removal completed!");
+    exit_on_failures(failures)
+}
+
+/// Exit with status 2 when some renames failed, so scripts can detect partial failures
+fn exit_on_failures(failures: usize) -> Result<(), Box<dyn std::error::Error>> {
+    if failures > 0 {
+        eprintln!("⚠️  {} file(s) could not be renamed", failures);
+        std::process::exit(2);
+    }
+    
     Ok(())
 }
 
-/// Remove bracket-delimited prefix from filename
-fn remove_bracket_prefix(filename: &str, prefix: &str) -> String {
-    let prefix_pattern = format!("[{}]", prefix);
-    if let Some(pos) = filename.find(&prefix_pattern) {
-        if pos == 0 {
-            // Prefix is at the beginning
-            let remaining = &filename[prefix_pattern.len()..];
-            // Remove leading whitespace and underscores, but preserve dashes and dots
-            remaining.trim_start_matches(&[' ', '_'][..]).to_string()
-        } else {
-            // Prefix is not at the beginning, return as-is
-            filename.to_string()
+/// Settings shared by every directory processed in one `rename` invocation
+struct RenameSettings {
+    options: PrefixOptions,
+    affix: Affix,
+    /// Adjust the proposed names in an editor instead of confirming each group
+    edit: bool,
+    /// Roll back an operation when any of its renames fails (`--atomic`)
+    atomic: bool,
+    /// Answer every confirmation with yes (`--yes`) or no (`--assume-no`)
+    /// instead of asking on the terminal
+    assume: Option<bool>,
+    policy: GroupPolicy,
+    /// How taken target names are resolved (`--on-collision`)
+    on_collision: Option<CollisionStrategy>,
+    /// Recorded with every operation (`--note`, `--tag`)
+    context: OperationContext,
+}
+
+impl RenameSettings {
+    /// The settings that decide the new names, as recorded with each operation
+    fn to_json(&self) -> String {
+        serde_json::json!({
+            "affix": self.affix,
+            "options": self.options,
+            "policy": self.policy.name(),
+            "on_collision": self.on_collision,
+            "atomic": self.atomic,
+            "edit": self.edit,
+        }).to_string()
+    }
+}
+
+/// Which of the detected groups of a directory are processed (`--policy`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum GroupPolicy {
+    /// Only the first (most common) group
+    FirstGroup,
+    /// Every group with the highest occurrence count
+    AllGroups,
+}
+
+impl GroupPolicy {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "first-group" => Some(GroupPolicy::FirstGroup),
+            "all-groups" => Some(GroupPolicy::AllGroups),
+            _ => None,
+        }
+    }
+    
+    fn name(self) -> &'static str {
+        match self {
+            GroupPolicy::FirstGroup => "first-group",
+            GroupPolicy::AllGroups => "all-groups",
         }
-    } else {
-        // No matching prefix found, return as-is
-        filename.to_string()
     }
 }
 
-/// Run continuous mode that listens for pasted paths
-fn run_continuous_mode(db: &RenameDatabase, options: &PrefixOptions) -> Result<(), Box<dyn std::error::Error>> {
+/// Run continuous mode that listens for pasted paths.
+/// Returns the number of files that could not be renamed.
+fn run_continuous_mode(db: &RenameDatabase, settings: &RenameSettings) -> Result<usize, Box<dyn std::error::Error>> {
     println!("🔄 Continuous mode started. Paste directory paths and press Enter.");
     println!("💡 Each path will be processed immediately after a brief delay.");
     println!("   Press Ctrl+C to exit.\n");
     
     let stdin = io::stdin();
+    let mut failures = 0;
     
     loop {
         // Read a line from stdin
@@ -327,7 +472,7 @@ fn run_continuous_mode(db: &RenameDatabase, options: &PrefixOptions) -> Result<(
                 thread::sleep(Duration::from_millis(200));
                 
                 // Process the paths (could be one or multiple)
-                process_paths_batch(db, options, &paths)?;
+                failures += process_paths_batch(db, settings, &paths)?;
             }
             Err(e) => {
                 eprintln!("❌ Error reading input: {}", e);
@@ -336,17 +481,17 @@ fn run_continuous_mode(db: &RenameDatabase, options: &PrefixOptions) -> Result<(
         }
     }
     
-    Ok(())
+    Ok(failures)
 }
 
 /// Process a batch of directory paths one at a time
 fn process_paths_batch(
     db: &RenameDatabase, 
-    options: &PrefixOptions, 
+    settings: &RenameSettings, 
     paths: &[String]
-) -> Result<(), Box<dyn std::error::Error>> {
+) -> Result<usize, Box<dyn std::error::Error>> {
     if paths.is_empty() {
-        return Ok(());
+        return Ok(0);
     }
     
     if paths.len() == 1 {
@@ -359,202 +504,463 @@ fn process_paths_batch(
     }
     println!();
     
+    let mut failures = 0;
     for (i, dir_path) in paths.iter().enumerate() {
         if paths.len() > 1 {
             println!("🔍 Processing directory {} of {}: {}", i + 1, paths.len(), dir_path);
         }
         
-        // Convert relative paths to absolute paths
-        let path = if Path::new(dir_path).is_relative() {
-            env::current_dir()?.join(dir_path)
-        } else {
-            Path::new(dir_path).to_path_buf()
-        };
+        failures += process_directory(db, settings, dir_path, None)?;
         
-        if !path.exists() {
-            eprintln!("❌ Warning: Directory does not exist: {}", dir_path);
-            continue;
+        if paths.len() > 1 && i < paths.len() - 1 {
+            println!("{}", "─".repeat(40));
         }
-        
-        if !path.is_dir() {
-            eprintln!("❌ Warning: Not a directory: {}", dir_path);
-            continue;
+    }
+    
+    println!("{}", "═".repeat(60));
+    println!("✅ Batch processing completed! Waiting for more paths...\n");
+    
+    Ok(failures)
+}
+
+/// Analyze a directory argument (and its subdirectories when recursing) and
+/// interactively process the prefix groups found in each.
+/// When `plan` is given the groups are added to it and nothing is renamed.
+/// Returns the number of files that could not be renamed.
+fn process_directory(
+    db: &RenameDatabase,
+    settings: &RenameSettings,
+    dir_path: &str,
+    mut plan: Option<&mut RenamePlan>,
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let options = &settings.options;
+    // Convert relative paths to absolute paths
+    let path = if Path::new(dir_path).is_relative() {
+        env::current_dir()?.join(dir_path)
+    } else {
+        Path::new(dir_path).to_path_buf()
+    };
+    
+    if !path.exists() {
+        eprintln!("❌ Warning: Directory does not exist: {}", dir_path);
+        return Ok(0);
+    }
+    
+    if !path.is_dir() {
+        eprintln!("❌ Warning: Not a directory: {}", dir_path);
+        return Ok(0);
+    }
+    
+    let directories = match directories_to_analyze(&path, options) {
+        Ok(directories) => directories,
+        Err(e) => {
+            eprintln!("❌ Error processing directory {}: {}", dir_path, e);
+            return Ok(0);
         }
+    };
+    
+    let mut failures = 0;
+    for directory in directories {
+        // Keep the path as typed for the top-level directory
+        let display_path = if directory == path {
+            dir_path.to_string()
+        } else {
+            display_name(directory.as_os_str()).into_owned()
+        };
         
-        match find_longest_prefix(&path, options) {
-            Ok(prefixed_paths) => {
-                if prefixed_paths.is_empty() {
-                    println!("📁 Directory: {}", dir_path);
-                    println!("ℹ️  No bracket-delimited prefixes found");
+        match plan_directory(&directory, options, settings.affix) {
+            Ok(mut groups) => {
+                if settings.policy == GroupPolicy::FirstGroup {
+                    groups.truncate(1);
+                }
+                
+                if groups.is_empty() {
+                    println!("📁 Directory: {}", display_path);
+                    println!("ℹ️  No matching {}es found", settings.affix.name());
+                } else if settings.edit && !edit_groups(&display_path, &mut groups)? {
+                    continue;
+                } else if let Some(plan) = plan.as_deref_mut() {
+                    println!("📁 Directory: {}", display_path);
+                    for group in &groups {
+                        println!("📝 Planned {} {} for {} files", settings.affix.name(), group.label(), group.renames.len());
+                    }
+                    plan.groups.extend(groups);
+                } else if settings.edit {
+                    failures += apply_edited_groups(db, &display_path, &groups, settings)?;
                 } else {
-                    process_directory_prefixes(db, &path, dir_path, &prefixed_paths)?;
+                    failures += process_rename_groups(db, &display_path, settings, &groups)?;
                 }
             }
             Err(e) => {
-                eprintln!("❌ Error processing directory {}: {}", dir_path, e);
+                eprintln!("❌ Error processing directory {}: {}", display_path, e);
             }
         }
-        
-        if paths.len() > 1 && i < paths.len() - 1 {
-            println!("{}", "─".repeat(40));
+    }
+    
+    Ok(failures)
+}
+
+/// Let the user adjust the proposed new names in `$VISUAL` or `$EDITOR`.
+/// Returns false when the edited names were rejected or nothing would change.
+fn edit_groups(dir_path: &str, groups: &mut [PlanGroup]) -> Result<bool, Box<dyn std::error::Error>> {
+    let buffer = match edit_buffer(groups) {
+        Ok(buffer) => buffer,
+        Err(e) => {
+            eprintln!("❌ Cannot edit the names in {}: {}", dir_path, e);
+            return Ok(false);
+        }
+    };
+    
+    // A new file with a random name (created exclusively, readable only by
+    // the user) so nobody else on the machine can swap it or plant a symlink
+    let mut buffer_file = tempfile::Builder::new().prefix("ftmi-rename-").suffix(".txt").tempfile()?;
+    buffer_file.write_all(&buffer)?;
+    buffer_file.flush()?;
+    
+    let status = run_editor(buffer_file.path());
+    let edited = fs::read(buffer_file.path());
+    drop(buffer_file);
+    
+    match status {
+        Ok(status) if status.success() => {}
+        Ok(status) => {
+            eprintln!("❌ Editor exited with {}, nothing was renamed in {}", status, dir_path);
+            return Ok(false);
+        }
+        Err(e) => {
+            eprintln!("❌ Could not start editor: {}", e);
+            return Ok(false);
         }
     }
     
-    println!("{}", "═".repeat(60));
-    println!("✅ Batch processing completed! Waiting for more paths...\n");
+    match apply_edits(groups, &edited?) {
+        Ok(0) => {
+            println!("📁 Directory: {}", dir_path);
+            println!("ℹ️  No names were changed");
+            Ok(false)
+        }
+        Ok(_) => Ok(true),
+        Err(errors) => {
+            eprintln!("❌ The edited names were rejected, nothing was renamed in {}:", dir_path);
+            for error in errors {
+                eprintln!("   {}", error);
+            }
+            Ok(false)
+        }
+    }
+}
+
+/// Open `path` in the user's editor and wait for it to exit
+fn run_editor(path: &Path) -> io::Result<ExitStatus> {
+    let default_editor = if cfg!(windows) { "notepad" } else { "vi" };
+    let editor = env::var("VISUAL")
+        .ok()
+        .filter(|editor| !editor.trim().is_empty())
+        .or_else(|| env::var("EDITOR").ok().filter(|editor| !editor.trim().is_empty()))
+        .unwrap_or_else(|| default_editor.to_string());
     
-    Ok(())
+    // Allow editors with arguments, e.g. EDITOR="code --wait"
+    let mut parts = editor.split_whitespace();
+    let mut command = Command::new(parts.next().unwrap_or(default_editor));
+    command.args(parts).arg(path);
+    
+    // Give the editor the terminal even when directories are piped in
+    #[cfg(unix)]
+    if let Ok(tty) = fs::File::open("/dev/tty") {
+        command.stdin(tty);
+    }
+    
+    command.status()
 }
 
-/// Process the prefixes found in a directory (extracted from main function)
-fn process_directory_prefixes(
+/// Rename the edited groups of a directory as a single tracked operation.
+/// Returns the number of files that could not be renamed.
+fn apply_edited_groups(
     db: &RenameDatabase,
-    _path: &Path,
     dir_path: &str,
-    prefixed_paths: &[PrefixedPath],
-) -> Result<(), Box<dyn std::error::Error>> {
+    groups: &[PlanGroup],
+    settings: &RenameSettings,
+) -> Result<usize, Box<dyn std::error::Error>> {
     println!("📁 Directory: {}", dir_path);
-    println!("Found {} prefix group(s) with highest occurrence count:", prefixed_paths.len());
+    println!("✏️  Applying edited names...");
     
-    for (i, prefixed_path) in prefixed_paths.iter().enumerate() {
-        println!("🏷️  Prefix {}: [{}]", i + 1, prefixed_path.prefix);
-        println!("   Files ({}):", prefixed_path.paths.len());
-        
-        // Show preview of what files would look like after prefix removal
-        for path in &prefixed_path.paths {
-            if let Some(filename) = path.file_name().and_then(|s| s.to_str()) {
-                let new_filename = remove_bracket_prefix(filename, &prefixed_path.prefix);
-                println!("   {} → {}", filename, new_filename);
-            }
+    let atomic = settings.atomic;
+    if atomic {
+        let conflicts = atomic_precheck(groups, settings.on_collision);
+        if conflicts > 0 {
+            return Ok(conflicts);
         }
-        
-        // Ask for confirmation
-        print!("\n💡 Remove prefix [{}] from these {} files? (Y/n/s=skip, default=Y): ", 
-               prefixed_path.prefix, prefixed_path.paths.len());
-        io::stdout().flush()?;
-        
-        let mut response = String::new();
-        
-        // Read user input from terminal even when stdin is piped
-        #[cfg(unix)]
-        {
-            use std::fs::OpenOptions;
-            use std::io::BufReader;
-            let tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
-            let mut tty_reader = BufReader::new(tty);
-            tty_reader.read_line(&mut response)?;
+    }
+    
+    let operation_id = generate_operation_id();
+    let started = Instant::now();
+    db.begin_operation(&operation_id, &settings.context)?;
+    let mut success_count = 0;
+    let mut error_count = 0;
+    for group in groups {
+        let (successes, errors) = group.execute(db, &operation_id, settings.on_collision);
+        success_count += successes;
+        error_count += errors;
+    }
+    db.finish_operation(&operation_id, success_count, error_count, started.elapsed())?;
+    
+    println!("📊 Results: {} successful, {} failed", success_count, error_count);
+    if atomic && error_count > 0 {
+        roll_back(db, &operation_id);
+    } else if success_count > 0 {
+        println!("💾 Operation ID: {} (use this to undo if needed)", operation_id);
+    }
+    Ok(error_count)
+}
+
+/// Change the proposed new name of one file in a group
+fn rename_entry(group: &mut PlanGroup, index: usize, name: &str) -> Result<(), String> {
+    if !is_valid_name(OsStr::new(name)) {
+        return Err(format!("Invalid file name '{}'", name));
+    }
+    
+    let to = group.renames[index].from.with_file_name(name);
+    if let Some(other) = group.renames.iter().position(|rename| rename.to == to) {
+        if other != index {
+            return Err(format!("File {} is already renamed to '{}'", other + 1, name));
         }
-        
-        #[cfg(not(unix))]
-        {
-            // On Windows, try to read from CONIN$
-            use std::fs::OpenOptions;
-            use std::io::BufReader;
-            match OpenOptions::new().read(true).open("CONIN$") {
-                Ok(con) => {
-                    let mut con_reader = BufReader::new(con);
-                    con_reader.read_line(&mut response)?;
-                }
-                Err(_) => {
-                    // Fallback to regular stdin
-                    io::stdin().read_line(&mut response)?;
-                }
+    }
+    
+    group.renames[index].to = to;
+    Ok(())
+}
+
+fn print_selection_help() {
+    println!("   Selecting files (numbers as shown in the list):");
+    println!("     1-5,8          Select only these files");
+    println!("     -3             Deselect files (all but 3)");
+    println!("     +9,10          Also select these files");
+    println!("     !              Invert the selection");
+    println!("     all / none     Select all or no files");
+    println!("     e 3 NEW NAME   Change the new name of file 3");
+}
+
+/// Read one line of user input from the terminal, even when stdin is piped
+fn read_terminal_line() -> io::Result<String> {
+    let mut response = String::new();
+    
+    #[cfg(unix)]
+    {
+        use std::fs::OpenOptions;
+        use std::io::BufReader;
+        let tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
+        let mut tty_reader = BufReader::new(tty);
+        tty_reader.read_line(&mut response)?;
+    }
+    
+    #[cfg(not(unix))]
+    {
+        // On Windows, try to read from CONIN$
+        use std::fs::OpenOptions;
+        use std::io::BufReader;
+        match OpenOptions::new().read(true).open("CONIN$") {
+            Ok(con) => {
+                let mut con_reader = BufReader::new(con);
+                con_reader.read_line(&mut response)?;
+            }
+            Err(_) => {
+                // Fallback to regular stdin
+                io::stdin().read_line(&mut response)?;
             }
         }
-        let response = response.trim().to_lowercase();
+    }
+    
+    Ok(response)
+}
+
+/// Show the detected groups of a directory and interactively remove them.
+/// Returns the number of files that could not be renamed.
+fn process_rename_groups(
+    db: &RenameDatabase,
+    dir_path: &str,
+    settings: &RenameSettings,
+    groups: &[PlanGroup],
+) -> Result<usize, Box<dyn std::error::Error>> {
+    let affix = settings.affix;
+    println!("📁 Directory: {}", dir_path);
+    println!("Found {} {} group(s) with highest occurrence count:", groups.len(), affix.name());
+    
+    let mut failures = 0;
+    for (i, group) in groups.iter().enumerate() {
+        let label = group.label();
+        let mut group = group.clone();
+        let mut selection = Selection::all(group.renames.len());
+        println!("🏷️  {} {}: {}", affix.title(), i + 1, label);
         
-        match response.as_str() {
-            "y" | "yes" | "" => {  // Empty string (just Enter) defaults to yes
-                println!("✅ Proceeding with prefix removal...");
-                
-                let operation_id = generate_operation_id();
-                let mut success_count = 0;
-                let mut error_count = 0;
-                
-                for old_path in &prefixed_path.paths {
-                    if let Some(filename) = old_path.file_name().and_then(|s| s.to_str()) {
-                        let new_filename = remove_bracket_prefix(filename, &prefixed_path.prefix);
-                        
-                        // Skip if new filename would be the same
-                        if new_filename == filename {
-                            println!("   ⏭️  {} (no change needed)", filename);
-                            continue;
-                        }
-                        
-                        let new_path = old_path.with_file_name(&new_filename);
-                        
-                        // Check if target file already exists
-                        if new_path.exists() {
-                            error_count += 1;
-                            eprintln!("   ❌ Target file already exists: {}", new_filename);
-                            continue;
-                        }
-                        
-                        println!("   🔄 Renaming: {} → {}", filename, new_filename);
-                        
-                        match tracked_rename(db, old_path, &new_path, &prefixed_path.prefix, &operation_id) {
-                            Ok(_) => {
-                                success_count += 1;
-                                println!("   ✓ Success!");
-                            }
-                            Err(e) => {
-                                error_count += 1;
-                                eprintln!("   ❌ Failed: {}", e);
-                            }
+        loop {
+            println!("   Files ({} of {} selected):", selection.count(), group.renames.len());
+            
+            // Show preview of what files would look like after removal
+            for (index, (old_path, new_path)) in group.paths().enumerate() {
+                if let (Some(filename), Some(new_filename)) = (old_path.file_name(), new_path.file_name()) {
+                    let mark = if selection.is_selected(index) { "x" } else { " " };
+                    println!("   [{}] {:>2}. {} → {}", mark, index + 1, display_name(filename), display_name(new_filename));
+                }
+            }
+            
+            // Ask for confirmation
+            print!("\n💡 Remove {} {} from the {} selected files? (Y/n/s=skip, ?=select files, default=Y): ", 
+                   affix.name(), label, selection.count());
+            io::stdout().flush()?;
+            
+            let response = match settings.assume {
+                Some(yes) => assumed_answer(yes),
+                None => read_terminal_line()?,
+            };
+            let response = response.trim();
+            
+            match response.to_lowercase().as_str() {
+                "y" | "yes" | "" => {  // Empty string (just Enter) defaults to yes
+                    if selection.count() == 0 {
+                        println!("⏭️  No files selected, skipped {} {}", affix.name(), label);
+                        break;
+                    }
+                    
+                    println!("✅ Proceeding with {} removal...", affix.name());
+                    
+                    // Only the selected files become part of the recorded operation
+                    let selected = PlanGroup { renames: selection.filter(&group.renames), ..group.clone() };
+                    if settings.atomic {
+                        let conflicts = atomic_precheck(std::slice::from_ref(&selected), settings.on_collision);
+                        if conflicts > 0 {
+                            failures += conflicts;
+                            break;
                         }
                     }
+                    
+                    let operation_id = generate_operation_id();
+                    let started = Instant::now();
+                    db.begin_operation(&operation_id, &settings.context)?;
+                    let (success_count, error_count) = selected.execute(db, &operation_id, settings.on_collision);
+                    db.finish_operation(&operation_id, success_count, error_count, started.elapsed())?;
+                    
+                    println!("📊 Results: {} successful, {} failed", success_count, error_count);
+                    if settings.atomic && error_count > 0 {
+                        roll_back(db, &operation_id);
+                    } else if success_count > 0 {
+                        println!("💾 Operation ID: {} (use this to undo if needed)", operation_id);
+                    }
+                    failures += error_count;
+                    break;
                 }
-                
-                println!("📊 Results: {} successful, {} failed", success_count, error_count);
-                if success_count > 0 {
-                    println!("💾 Operation ID: {} (use this to undo if needed)", operation_id);
+                "n" | "no" => {
+                    println!("❌ Skipped {} removal for {}", affix.name(), label);
+                    break;
                 }
+                "s" | "skip" => {
+                    println!("⏭️  Skipped {} {}", affix.name(), label);
+                    break;
+                }
+                "?" | "h" | "help" => print_selection_help(),
+                _ => match parse_selection_command(response, group.renames.len()) {
+                    Ok(SelectionCommand::Rename(index, name)) => {
+                        match rename_entry(&mut group, index, &name) {
+                            Ok(()) => selection.apply(&SelectionCommand::Include(vec![index])),
+                            Err(e) => println!("❌ {}", e),
+                        }
+                    }
+                    Ok(command) => selection.apply(&command),
+                    Err(e) => println!("❓ {} (type ? for help)", e),
+                },
             }
-            "n" | "no" => {
-                println!("❌ Skipped prefix removal for [{}]", prefixed_path.prefix);
-            }
-            "s" | "skip" => {
-                println!("⏭️  Skipped prefix [{}]", prefixed_path.prefix);
-            }
-            _ => {
-                println!("❓ Unknown response '{}', skipping...", response);
-            }
+            println!();
         }
         
         println!();
     }
     
-    Ok(())
+    Ok(failures)
+}
+
+/// The answer given for `--yes` / `--assume-no`, echoed in place of user input
+fn assumed_answer(yes: bool) -> String {
+    let answer = if yes { "y" } else { "n" };
+    println!("{} (assumed)", answer);
+    answer.to_string()
 }
 
 /// List recent rename operations
 fn list_recent_operations(db: &RenameDatabase) -> Result<(), Box<dyn std::error::Error>> {
     println!("📋 Recent rename operations:");
     
-    let operations = db.get_recent_operations(20)?;
+    let query = HistoryQuery { limit: Some(LIST_LIMIT + 1), ..HistoryQuery::default() };
+    let mut operations = db.query_operations(&query)?;
     
     if operations.is_empty() {
         println!("   No operations found.");
         return Ok(());
     }
     
+    let more = operations.len() > LIST_LIMIT;
+    operations.truncate(LIST_LIMIT);
+    print_operations(db, &operations, 1)?;
+    
+    if more {
+        println!("📜 Only the {} most recent operations are shown; 'ftmi history' lists and filters all of them.", LIST_LIMIT);
+    }
+    println!("💡 Use 'ftmi rename --undo <operation_id>' to undo any operation");
+    println!("   and 'ftmi rename --redo <operation_id>' to reapply an undone one.");
+    println!("   'ftmi rename --show <operation_id>' shows who ran it and with which settings.");
+    
+    Ok(())
+}
+
+/// Print a summary of each operation, numbered from `first_number`
+pub(crate) fn print_operations(db: &RenameDatabase, operations: &[String], first_number: usize) -> Result<(), Box<dyn std::error::Error>> {
     for (i, op_id) in operations.iter().enumerate() {
         let records = db.get_operation_renames(op_id)?;
         if let Some(first_record) = records.first() {
-            println!("{}. Operation ID: {}", i + 1, op_id);
+            println!("{}. Operation ID: {}", first_number + i, op_id);
+            match db.operation_kind(op_id)? {
+                (OperationKind::Undo, Some(reverts)) => println!("   ↩️  Undo of {}", reverts),
+                (OperationKind::Redo, Some(reverts)) => println!("   ↪️  Redo (reverts {})", reverts),
+                _ => {}
+            }
+            if let Some(revert_id) = db.reverted_by(op_id)? {
+                println!("   State: undone by {}", revert_id);
+            }
             println!("   Timestamp: {}", first_record.timestamp.format("%Y-%m-%d %H:%M:%S UTC"));
-            println!("   Directory: {}", first_record.directory.display());
-            println!("   Prefix removed: [{}]", first_record.prefix_removed);
-            println!("   Files renamed: {}", records.len());
+            if let Some(operation) = db.operation(op_id)? {
+                if let Some(user) = &operation.context.user {
+                    println!("   By: {}", user_at_host(&operation.context, user));
+                }
+                if let Some(note) = &operation.context.note {
+                    println!("   Note: {}", note);
+                }
+                if !operation.context.tags.is_empty() {
+                    println!("   Tags: {}", operation.context.tags.join(", "));
+                }
+            }
+            println!("   Directory: {}", display_name(first_record.directory.as_os_str()));
+            println!("   Removed: {}", first_record.prefix_removed);
+            let undone = records.iter().filter(|record| record.reverted_by.is_some()).count();
+            if undone > 0 && undone < records.len() {
+                println!("   Files renamed: {} ({} undone)", records.len(), undone);
+            } else {
+                println!("   Files renamed: {}", records.len());
+            }
             
             // Show first few files as examples
             let show_count = std::cmp::min(3, records.len());
             for record in records.iter().take(show_count) {
-                if let (Some(old_name), Some(new_name)) = (
-                    record.old_path.file_name().and_then(|s| s.to_str()),
-                    record.new_path.file_name().and_then(|s| s.to_str())
-                ) {
-                    println!("     {} → {}", old_name, new_name);
+                if let (Some(old_name), Some(new_name)) = (record.old_path.file_name(), record.new_path.file_name()) {
+                    let mut notes = Vec::new();
+                    if let Some(strategy) = record.collision {
+                        notes.push(format!("collision: {}", strategy.name()));
+                    }
+                    if record.reverted_by.is_some() {
+                        notes.push("undone".to_string());
+                    }
+                    if notes.is_empty() {
+                        println!("     {} → {}", display_name(old_name), display_name(new_name));
+                    } else {
+                        println!("     {} → {} ({})", display_name(old_name), display_name(new_name), notes.join(", "));
+                    }
                 }
             }
             
@@ -566,98 +972,258 @@ fn list_recent_operations(db: &RenameDatabase) -> Result<(), Box<dyn std::error:
         }
     }
     
-    println!("💡 Use 'ftmi rename --undo <operation_id>' to undo any operation.");
-    
     Ok(())
 }
 
-/// Undo the most recent operation
-fn undo_most_recent_operation(db: &RenameDatabase) -> Result<(), Box<dyn std::error::Error>> {
-    println!("🔄 Finding most recent operation to undo...");
+/// `user@host`, or just the user when the host is unknown
+fn user_at_host(context: &OperationContext, user: &str) -> String {
+    match &context.hostname {
+        Some(hostname) => format!("{}@{}", user, hostname),
+        None => user.to_string(),
+    }
+}
+
+/// Print everything recorded about an operation
+fn show_operation(db: &RenameDatabase, operation_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let records = db.get_operation_renames(operation_id)?;
+    let operation = db.operation(operation_id)?;
+    if records.is_empty() && operation.is_none() {
+        eprintln!("❌ Operation ID '{}' not found.", operation_id);
+        return Ok(());
+    }
+    
+    println!("📋 Operation {}", operation_id);
+    match &operation {
+        Some(operation) => {
+            let context = &operation.context;
+            println!("   Kind: {}", operation.kind.name());
+            if let Some(reverts) = &operation.reverts {
+                println!("   Reverts: {}", reverts);
+            }
+            println!("   Started: {}", operation.timestamp.format("%Y-%m-%d %H:%M:%S UTC"));
+            if let Some(user) = &context.user {
+                println!("   By: {}", user_at_host(context, user));
+            }
+            if !context.command_line.is_empty() {
+                println!("   Command: {}", context.command_line);
+            }
+            if let Some(directory) = &context.working_directory {
+                println!("   Working directory: {}", directory);
+            }
+            if !context.version.is_empty() {
+                println!("   ftmi version: {}", context.version);
+            }
+            if let Some(settings) = &context.settings {
+                println!("   Settings: {}", settings);
+            }
+            if let (Some(success_count), Some(failure_count)) = (operation.success_count, operation.failure_count) {
+                println!("   Results: {} successful, {} failed", success_count, failure_count);
+            }
+            if let Some(duration_ms) = operation.duration_ms {
+                println!("   Duration: {} ms", duration_ms);
+            }
+            if let Some(note) = &context.note {
+                println!("   Note: {}", note);
+            }
+            if !context.tags.is_empty() {
+                println!("   Tags: {}", context.tags.join(", "));
+            }
+        }
+        None => println!("   Recorded before operation details were kept"),
+    }
     
-    let operations = db.get_recent_operations(1)?;
+    if let Some(revert_id) = db.reverted_by(operation_id)? {
+        println!("   State: undone by {}", revert_id);
+    }
+    println!("   Files renamed: {}", records.len());
+    for record in &records {
+        if let (Some(old_name), Some(new_name)) = (record.old_path.file_name(), record.new_path.file_name()) {
+            println!("     {} → {}", display_name(old_name), display_name(new_name));
+        }
+    }
     
-    if operations.is_empty() {
-        println!("❌ No operations found to undo.");
+    Ok(())
+}
+
+/// Split operations recorded under the same legacy ID by prefix and directory:
+/// the given one, or every legacy operation that spans several prefixes
+fn split_merged_operations(db: &RenameDatabase, operation_id: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let operation_ids = match operation_id {
+        Some(id) => vec![id.to_string()],
+        None => db.merged_operations()?,
+    };
+    
+    if operation_ids.is_empty() {
+        println!("✅ No merged operations found.");
         return Ok(());
     }
     
-    let most_recent_op_id = &operations[0];
+    for operation_id in &operation_ids {
+        let new_ids = db.split_operation(operation_id)?;
+        if new_ids.is_empty() {
+            println!("⏭️  {} has a single prefix, nothing to split", operation_id);
+        } else {
+            println!("✂️  Split {} into {}", operation_id, new_ids.join(", "));
+        }
+    }
+    
+    println!("💡 Use 'ftmi rename --list' to see the separate operations.");
+    Ok(())
+}
+
+/// Undo the most recent operation.
+/// Returns the number of files that could not be restored.
+fn undo_most_recent_operation(db: &RenameDatabase, only: Option<&str>, options: UndoOptions, assume: Option<bool>) -> Result<usize, Box<dyn std::error::Error>> {
+    println!("🔄 Finding most recent operation to undo...");
+    
+    // Undos are redone with --redo, and undone operations can't be undone again
+    let Some(most_recent_op_id) = latest_operation(db, |kind| kind != OperationKind::Undo)? else {
+        println!("❌ No operations found to undo.");
+        return Ok(0);
+    };
     println!("🎯 Most recent operation: {}", most_recent_op_id);
     
-    undo_operation(db, most_recent_op_id)
+    undo_operation(db, &most_recent_op_id, only, options, assume)
+}
+
+/// The most recent operation of a kind accepted by `accept` that is still in effect
+fn latest_operation<F: Fn(OperationKind) -> bool>(db: &RenameDatabase, accept: F) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    for operation_id in db.get_recent_operations(HISTORY_SEARCH_LIMIT)? {
+        if accept(db.operation_kind(&operation_id)?.0) && db.reverted_by(&operation_id)?.is_none() {
+            return Ok(Some(operation_id));
+        }
+    }
+    Ok(None)
 }
 
-/// Undo a specific operation
-fn undo_operation(db: &RenameDatabase, operation_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+/// Undo a specific operation, or only the files picked by `only` (see `select_records`).
+/// Returns the number of files that could not be restored.
+fn undo_operation(
+    db: &RenameDatabase,
+    operation_id: &str,
+    only: Option<&str>,
+    options: UndoOptions,
+    assume: Option<bool>,
+) -> Result<usize, Box<dyn std::error::Error>> {
     println!("🔄 Undoing operation: {}", operation_id);
     
+    if db.operation_kind(operation_id)?.0 == OperationKind::Undo {
+        eprintln!("❌ {} is an undo. Use 'ftmi rename --redo {}' to reapply what it restored.", operation_id, operation_id);
+        return Ok(0);
+    }
+    if let Some(revert_id) = db.reverted_by(operation_id)? {
+        eprintln!("❌ Operation {} was already undone by {}. Use 'ftmi rename --redo {}' to reapply it.",
+                  operation_id, revert_id, operation_id);
+        return Ok(0);
+    }
+    
     // First, get the operation details to show what will be undone
     let records = db.get_operation_renames(operation_id)?;
     
     if records.is_empty() {
         eprintln!("❌ Operation ID '{}' not found.", operation_id);
-        return Ok(());
+        return Ok(0);
+    }
+    
+    let selected = match only {
+        Some(spec) => match select_records(&records, spec) {
+            Ok(selected) => selected,
+            Err(e) => {
+                eprintln!("❌ Invalid --only: {}", e);
+                return Ok(0);
+            }
+        },
+        None => (0..records.len()).collect(),
+    };
+    let selected: Vec<usize> = selected.into_iter()
+        .filter(|&index| records[index].reverted_by.is_none())
+        .collect();
+    if selected.is_empty() {
+        eprintln!("❌ No files of operation {} left to undo{}.", operation_id,
+                  if only.is_some() { " match --only" } else { "" });
+        return Ok(0);
     }
     
     let first_record = &records[0];
-    println!("📂 Directory: {}", first_record.directory.display());
-    println!("🏷️  Prefix: [{}]", first_record.prefix_removed);
+    println!("📂 Directory: {}", display_name(first_record.directory.as_os_str()));
+    println!("🏷️  Removed: {}", first_record.prefix_removed);
     println!("📅 Original timestamp: {}", first_record.timestamp.format("%Y-%m-%d %H:%M:%S UTC"));
-    println!("📊 Files to restore: {}", records.len());
+    println!("📊 Files to restore: {} of {}", selected.len(), records.len());
     println!();
     
-    // Show preview of what will be restored
+    // Show preview of what will be restored, numbered like --only expects
     println!("🔄 Preview of restore operation:");
-    for record in &records {
-        if let (Some(current_name), Some(original_name)) = (
-            record.new_path.file_name().and_then(|s| s.to_str()),
-            record.old_path.file_name().and_then(|s| s.to_str())
-        ) {
-            println!("   {} → {}", current_name, original_name);
+    for &index in &selected {
+        let record = &records[index];
+        if let (Some(current_name), Some(original_name)) = (record.new_path.file_name(), record.old_path.file_name()) {
+            println!("   {}. {} → {}", index + 1, display_name(current_name), display_name(original_name));
         }
     }
     
-    // Ask for confirmation
-    print!("\n💡 Are you sure you want to undo this operation? (y/N): ");
-    io::stdout().flush()?;
-    
-    let mut response = String::new();
-    
-    // Read user input from terminal directly
-    #[cfg(unix)]
-    {
-        use std::fs::OpenOptions;
-        use std::io::BufReader;
-        let tty = OpenOptions::new().read(true).write(true).open("/dev/tty")?;
-        let mut tty_reader = BufReader::new(tty);
-        tty_reader.read_line(&mut response)?;
+    // Files renamed again by later operations are no longer at their new name
+    let mut moved = 0;
+    for &index in &selected {
+        let record = &records[index];
+        if record.new_path.symlink_metadata().is_ok() {
+            continue;
+        }
+        if let Some(last) = db.later_renames(record)?.last() {
+            moved += 1;
+            println!("   ⚠️  {} was renamed again to {} by {}",
+                     display_name(record.new_path.file_name().unwrap_or_default()),
+                     display_name(last.new_path.file_name().unwrap_or_default()),
+                     last.operation_id);
+        }
     }
     
-    #[cfg(not(unix))]
-    {
-        // On Windows, try to read from CONIN$
-        use std::fs::OpenOptions;
-        use std::io::BufReader;
-        match OpenOptions::new().read(true).open("CONIN$") {
-            Ok(con) => {
-                let mut con_reader = BufReader::new(con);
-                con_reader.read_line(&mut response)?;
+    let response = if moved == 0 {
+        print!("\n💡 Are you sure you want to undo this operation? (y/N): ");
+        io::stdout().flush()?;
+        match assume {
+            Some(yes) => assumed_answer(yes),
+            None => read_terminal_line()?,
+        }
+    } else {
+        let dependents = db.dependent_operations(operation_id)?;
+        println!();
+        println!("🔗 {} file(s) were renamed again by later operations: {}", moved, dependents.join(", "));
+        println!("   u = undo those operations first (most recent first), then this one");
+        println!("   f = restore the files from their current names");
+        print!("💡 How do you want to undo this operation? (u/f/N): ");
+        io::stdout().flush()?;
+        let response = match assume {
+            Some(true) => {
+                println!("f (assumed)");
+                "f".to_string()
             }
-            Err(_) => {
-                // Fallback to regular stdin
-                io::stdin().read_line(&mut response)?;
+            Some(false) => assumed_answer(false),
+            None => read_terminal_line()?,
+        };
+        
+        if matches!(response.trim().to_lowercase().as_str(), "u" | "unwind") {
+            let mut failures = 0;
+            for dependent in &dependents {
+                println!("↩️  Undoing later operation {}...", dependent);
+                failures += db.undo_with_options(dependent, &options)?.1;
             }
+            if failures > 0 {
+                eprintln!("❌ {} file(s) of the later operations could not be restored, stopping.", failures);
+                return Ok(failures);
+            }
+            "y".to_string()
+        } else {
+            response
         }
-    }
+    };
     
-    let response = response.trim().to_lowercase();
-    
-    match response.as_str() {
-        "y" | "yes" => {
+    let follow = matches!(response.trim().to_lowercase().as_str(), "f" | "follow");
+    match response.trim().to_lowercase().as_str() {
+        "y" | "yes" | "f" | "follow" => {
             println!("✅ Proceeding with undo...");
             
-            let (success_count, error_count) = db.undo_operation(operation_id)?;
+            let record_ids: Vec<i64> = selected.iter().map(|&index| records[index].id).collect();
+            let options = UndoOptions { only: Some(&record_ids), follow, ..options };
+            let (success_count, error_count) = db.undo_with_options(operation_id, &options)?;
             
             println!("📊 Undo results: {} successful, {} failed", success_count, error_count);
             
@@ -667,13 +1233,109 @@ fn undo_operation(db: &RenameDatabase, operation_id: &str) -> Result<(), Box<dyn
             if error_count > 0 {
                 println!("⚠️  Some files could not be restored (they may have been moved or modified).");
             }
+            Ok(error_count)
         }
         _ => {
             println!("❌ Undo cancelled.");
+            Ok(0)
         }
     }
+}
+
+/// Pick records of an operation for `--only`: 1-based positions such as
+/// `2,4-5`, or a glob matched against the old and the new file name.
+/// Returns the indices of the picked records.
+fn select_records(records: &[RenameRecord], spec: &str) -> Result<Vec<usize>, String> {
+    if spec.chars().all(|c| c.is_ascii_digit() || c == ',' || c == '-' || c == ' ') {
+        return parse_ranges(spec, records.len()).map_err(|e| e.to_string());
+    }
     
-    Ok(())
+    let pattern = glob::Pattern::new(spec).map_err(|e| e.to_string())?;
+    let matches = |path: &Path| path.file_name().is_some_and(|name| pattern.matches(&display_name(name)));
+    Ok(records.iter()
+        .enumerate()
+        .filter(|(_, record)| matches(&record.old_path) || matches(&record.new_path))
+        .map(|(index, _)| index)
+        .collect())
+}
+
+/// Redo the most recently undone operation.
+/// Returns the number of files that could not be renamed again.
+fn redo_most_recent_operation(db: &RenameDatabase, options: UndoOptions, assume: Option<bool>) -> Result<usize, Box<dyn std::error::Error>> {
+    println!("🔄 Finding most recent undo to redo...");
+    
+    let Some(undo_id) = latest_operation(db, |kind| kind == OperationKind::Undo)? else {
+        println!("❌ No undone operations found to redo.");
+        return Ok(0);
+    };
+    
+    redo_operation(db, &undo_id, options, assume)
+}
+
+/// Redo an undone operation. `operation_id` is either the undone operation or
+/// the undo itself. Returns the number of files that could not be renamed again.
+fn redo_operation(db: &RenameDatabase, operation_id: &str, options: UndoOptions, assume: Option<bool>) -> Result<usize, Box<dyn std::error::Error>> {
+    let undo_id = match db.operation_kind(operation_id)? {
+        (OperationKind::Undo, Some(_)) if db.reverted_by(operation_id)?.is_none() => operation_id.to_string(),
+        (OperationKind::Undo, _) => {
+            eprintln!("❌ Undo {} was already redone.", operation_id);
+            return Ok(0);
+        }
+        _ => match db.reverting_operations(operation_id)?.into_iter().next() {
+            Some(undo_id) => undo_id,
+            None => {
+                eprintln!("❌ Operation '{}' is not undone, nothing to redo.", operation_id);
+                return Ok(0);
+            }
+        },
+    };
+    let original_id = db.operation_kind(&undo_id)?.1.unwrap_or_default();
+    println!("🔄 Redoing operation: {} (undone by {})", original_id, undo_id);
+    
+    let records = db.get_operation_renames(&undo_id)?;
+    if records.is_empty() {
+        eprintln!("❌ Operation ID '{}' not found.", undo_id);
+        return Ok(0);
+    }
+    
+    println!("🏷️  Removed: {}", records[0].prefix_removed);
+    println!("📊 Files to rename again: {}", records.len());
+    println!();
+    println!("🔄 Preview of redo operation:");
+    for record in &records {
+        if let (Some(current_name), Some(new_name)) = (record.new_path.file_name(), record.old_path.file_name()) {
+            println!("   {} → {}", display_name(current_name), display_name(new_name));
+        }
+    }
+    
+    print!("\n💡 Are you sure you want to redo this operation? (y/N): ");
+    io::stdout().flush()?;
+    
+    let response = match assume {
+        Some(yes) => assumed_answer(yes),
+        None => read_terminal_line()?,
+    };
+    
+    match response.trim().to_lowercase().as_str() {
+        "y" | "yes" => {
+            println!("✅ Proceeding with redo...");
+            
+            let (success_count, error_count) = db.redo_with_options(&undo_id, &options)?;
+            
+            println!("📊 Redo results: {} successful, {} failed", success_count, error_count);
+            if success_count > 0 {
+                println!("✅ Operation successfully redone!");
+            }
+            if error_count > 0 {
+                println!("⚠️  Some files could not be renamed again (their names may be taken now).");
+            }
+            Ok(error_count)
+        }
+        _ => {
+            println!("❌ Redo cancelled.");
+            Ok(0)
+        }
+    }
 }
 
 fn print_help() {
@@ -687,10 +1349,42 @@ fn print_help() {
     println!("    -r, --regex PATTERN    Use custom regex to filter prefixes (default: \\[.*\\])");
     println!("    --no-filter           Accept all prefixes (no regex filtering)");
     println!("    -c, --continuous      Continuous mode: listen for pasted paths");
+    println!("    --recursive           Also process every subdirectory as its own group");
+    println!("    --max-depth NUM       Limit recursion depth (implies --recursive)");
+    println!("    --pool                Treat the whole tree as a single pool of files");
+    println!("    --normalize FORM      Unicode-normalize names (nfc or nfd) before comparing");
+    println!("    --suffix              Remove trailing tags before the extension instead of prefixes");
+    println!("    --plan FILE           Write the proposed renames to a JSON plan instead of renaming");
+    println!("    -e, --edit            Adjust the proposed names in $EDITOR before renaming");
+    println!("    --atomic              All or nothing: roll back an operation if any of its renames fails");
+    println!("    -y, --yes             Answer yes to every prompt (no terminal needed)");
+    println!("    --assume-no           Answer no to every prompt: show what would happen, rename nothing");
+    println!("    --policy POLICY       Groups to process per directory: all-groups (default) or first-group");
+    println!("    --on-collision STRATEGY");
+    println!("                          What to do when a new name is already taken (default: report an error):");
+    println!("                            skip       leave the file alone");
+    println!("                            number     add a number: 'Intro (2).mp3'");
+    println!("                            suffix     keep the removed text as a suffix: 'Intro - A.mp3'");
+    println!("                            subfolder  move the file into a subfolder: 'A/Intro.mp3'");
+    println!("                            backup     rename the existing file to 'Intro.mp3.bak' first");
     println!("    -u, --undo [ID]       Undo an operation (most recent if no ID given)");
+    println!("    --only FILES          With --undo: restore only some files, by number (2,4-5) or glob ('*Live*')");
+    println!("    --redo [ID]           Reapply an undone operation (most recently undone if no ID given)");
+    println!("    --force               With --undo or --redo: rename files back even if they changed since the rename");
     println!("    -l, --list            List recent rename operations");
+    println!("    --show ID             Show who ran an operation, where, with which settings and its results");
+    println!("    --note TEXT           Record a note with the operation (or with --annotate)");
+    println!("    --tag TAG             Tag the operation; may be repeated");
+    println!("    --annotate ID         Add --note and --tag to an earlier operation");
+    println!("    --split [ID]          Split an operation that mixes several prefixes into one per prefix");
+    println!("                          (all such operations recorded by older versions if no ID given)");
+    println!("    --db PATH             Use this history database (default: FTMI_DB, then a .ftmi directory in");
+    println!("                          the directory or a parent, then the user's history)");
     println!("    -h, --help            Show this help message");
     println!();
+    println!("EXIT STATUS:");
+    println!("    0 on success, 2 when some files could not be renamed or restored");
+    println!();
     println!("EXAMPLES:");
     println!("    # Default: Find bracket-delimited prefixes");
     println!("    ftmi rename ./music");
@@ -701,6 +1395,25 @@ fn print_help() {
     println!("    # No filter: Find all prefixes");
     println!("    ftmi rename --no-filter ./music");
     println!();
+    println!("    # Remove trailing tags like 'Song (Remastered 2011).mp3'");
+    println!("    ftmi rename --suffix --regex '\\(.*\\)' ./music");
+    println!();
+    println!("    # Process every album folder below ~/Music");
+    println!("    ftmi rename --recursive ~/Music");
+    println!();
+    println!("    # Hand-tweak the proposed names in your editor");
+    println!("    EDITOR=nano ftmi rename --edit ./music");
+    println!();
+    println!("    # Save a plan for review, then apply it later");
+    println!("    ftmi rename --plan plan.json ./music");
+    println!("    ftmi apply plan.json");
+    println!();
+    println!("    # Unattended, e.g. from cron or CI (no terminal needed)");
+    println!("    ftmi rename --yes --policy=first-group ./incoming");
+    println!();
+    println!("    # '[A] Intro.mp3' and '[B] Intro.mp3' become 'Intro.mp3' and 'Intro (2).mp3'");
+    println!("    ftmi rename --on-collision number ./music");
+    println!();
     println!("    # Continuous mode for pasting multiple paths");
     println!("    ftmi rename --continuous");
     println!();
@@ -716,32 +1429,3 @@ fn print_help() {
     println!("    # Pipe in directories with custom regex");
     println!("    echo './music' | ftmi rename --regex 'IMG_.*'");
 }
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn test_remove_bracket_prefix() {
-        assert_eq!(
-            remove_bracket_prefix("[Artist] Song.mp3", "Artist"),
-            "Song.mp3"
-        );
-        assert_eq!(
-            remove_bracket_prefix("[The Beatles] Hey Jude.mp3", "The Beatles"),
-            "Hey Jude.mp3"
-        );
-        assert_eq!(
-            remove_bracket_prefix("[Artist]_Song.mp3", "Artist"),
-            "Song.mp3"
-        );
-        assert_eq!(
-            remove_bracket_prefix("[Artist] - Song.mp3", "Artist"),
-            "- Song.mp3"
-        );
-        assert_eq!(
-            remove_bracket_prefix("No Prefix Song.mp3", "Artist"),
-            "No Prefix Song.mp3"
-        );
-    }
-}
\ No newline at end of file
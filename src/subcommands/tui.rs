@@ -0,0 +1,786 @@
+use crate::{
+    display_name, generate_operation_id, plan_directory, Affix, OperationContext, OperationKind, PlanGroup, PrefixOptions,
+    RenameDatabase, RenameOutcome
+};
+use crate::prefix_finder::directories_to_analyze;
+use crate::selection::{Selection, SelectionCommand};
+use crate::subcommands::{open_database, parse_db_flag, ScanFlags};
+use chrono::{DateTime, Local, Utc};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::env;
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// Number of operations shown in the history pane
+const HISTORY_LIMIT: usize = 50;
+
+pub fn tui_command(args: Vec<String>) -> Result<(), Box<dyn std::error::Error>> {
+    let mut options = PrefixOptions::default();
+    let mut directories: Vec<String> = Vec::new();
+    let mut scan_flags = ScanFlags::default();
+    let mut db_path: Option<PathBuf> = None;
+    let mut i = 2; // Skip "ftmi" and "tui"
+    
+    while i < args.len() {
+        match args[i].as_str() {
+            "--regex" | "-r" => {
+                if i + 1 < args.len() {
+                    options.filter_regex = Some(args[i + 1].clone());
+                    i += 2;
+                } else {
+                    eprintln!("❌ Error: --regex requires a pattern argument");
+                    return Ok(());
+                }
+            }
+            "--no-filter" => {
+                options.filter_regex = None;
+                i += 1;
+            }
+            "--min" => {
+                if i + 1 < args.len() {
+                    match args[i + 1].parse::<usize>() {
+                        Ok(min) => options.min_occurrences = min,
+                        Err(_) => eprintln!("Warning: Invalid --min value: {}", args[i + 1]),
+                    }
+                    i += 2;
+                } else {
+                    eprintln!("❌ Error: --min requires a number");
+                    return Ok(());
+                }
+            }
+            "--recursive" | "--max-depth" | "--pool" | "--normalize" => {
+                match scan_flags.parse(&args, i) {
+                    Ok(consumed) => i += consumed,
+                    Err(e) => {
+                        eprintln!("❌ Error: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            arg if arg == "--db" || arg.starts_with("--db=") => {
+                let Some((path, consumed)) = parse_db_flag(&args, i) else {
+                    return Ok(());
+                };
+                db_path = Some(path);
+                i += consumed;
+            }
+            "--help" | "-h" => {
+                print_help();
+                return Ok(());
+            }
+            arg if arg.starts_with("--") => {
+                eprintln!("❌ Unknown option: {}", arg);
+                print_help();
+                return Ok(());
+            }
+            _ => {
+                directories.push(args[i].clone());
+                i += 1;
+            }
+        }
+    }
+    
+    scan_flags.apply(&mut options);
+    
+    // Piped directories are read before the terminal is taken over;
+    // key presses are then read from the terminal itself
+    if !atty::is(atty::Stream::Stdin) {
+        let stdin = io::stdin();
+        directories.extend(stdin.lock().lines().collect::<Result<Vec<_>, _>>()?);
+    }
+    
+    let start = directories.first().map_or(Path::new("."), |dir| Path::new(dir.as_str()));
+    let db = open_database(db_path.as_deref(), start)?;
+    
+    let unfinished = db.journal_entries().map(|entries| entries.len()).unwrap_or(0);
+    let mut app = App::new(db, options);
+    for directory in &directories {
+        app.add_directory(directory);
+    }
+    app.load_history();
+    if unfinished > 0 {
+        app.status = format!("{} rename(s) from an interrupted run are not in the history yet, run 'ftmi recover'", unfinished);
+    }
+    
+    let mut terminal = ratatui::try_init()?;
+    let result = run(&mut terminal, &mut app);
+    ratatui::restore();
+    result
+}
+
+fn run(terminal: &mut DefaultTerminal, app: &mut App) -> Result<(), Box<dyn std::error::Error>> {
+    while !app.quit {
+        terminal.draw(|frame| draw(frame, app))?;
+        if let Event::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Press {
+                app.handle_key(key);
+            }
+        }
+    }
+    
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Pane {
+    Directories,
+    Groups,
+    Preview,
+    History,
+}
+
+impl Pane {
+    const ALL: [Pane; 4] = [Pane::Directories, Pane::Groups, Pane::Preview, Pane::History];
+    
+    fn next(self) -> Self {
+        Self::ALL[(self.index() + 1) % Self::ALL.len()]
+    }
+    
+    fn previous(self) -> Self {
+        Self::ALL[(self.index() + Self::ALL.len() - 1) % Self::ALL.len()]
+    }
+    
+    fn index(self) -> usize {
+        Self::ALL.iter().position(|pane| *pane == self).unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Mode {
+    Normal,
+    /// Waiting for y/n before running an action
+    Confirm(Action),
+    /// Typing a directory to add to the queue
+    Input(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Action {
+    /// Rename the selected files of the current group
+    Rename,
+    /// Undo an operation
+    Undo(String),
+    /// Redo an operation by reverting the given undo
+    Redo(String),
+}
+
+/// One operation of the history pane
+#[derive(Debug, Clone)]
+struct HistoryEntry {
+    operation_id: String,
+    timestamp: DateTime<Utc>,
+    directory: PathBuf,
+    removed: String,
+    files: usize,
+    kind: OperationKind,
+    /// The undo or redo currently reverting this operation
+    reverted_by: Option<String>,
+}
+
+/// State of the terminal UI, kept separate from drawing so it can be tested
+struct App {
+    db: RenameDatabase,
+    options: PrefixOptions,
+    /// Queue of directories to work through
+    directories: Vec<PathBuf>,
+    directory_index: usize,
+    /// Prefix groups of the current directory, found like `ftmi rename` does
+    groups: Vec<PlanGroup>,
+    group_index: usize,
+    /// Files of the current group that will be renamed
+    selection: Selection,
+    file_index: usize,
+    history: Vec<HistoryEntry>,
+    history_index: usize,
+    focus: Pane,
+    mode: Mode,
+    status: String,
+    quit: bool,
+}
+
+impl App {
+    fn new(db: RenameDatabase, options: PrefixOptions) -> Self {
+        Self {
+            db,
+            options,
+            directories: Vec::new(),
+            directory_index: 0,
+            groups: Vec::new(),
+            group_index: 0,
+            selection: Selection::all(0),
+            file_index: 0,
+            history: Vec::new(),
+            history_index: 0,
+            focus: Pane::Groups,
+            mode: Mode::Normal,
+            status: "Press Tab to switch panes, Enter to rename, u to undo, q to quit".to_string(),
+            quit: false,
+        }
+    }
+    
+    /// Add a directory (and its subdirectories when recursing) to the queue
+    fn add_directory(&mut self, directory: &str) {
+        let directory = directory.trim().trim_matches(|c| c == '"' || c == '\'');
+        if directory.is_empty() {
+            return;
+        }
+        
+        let path = Path::new(directory);
+        let path = if path.is_relative() {
+            env::current_dir().map(|cwd| cwd.join(path)).unwrap_or_else(|_| path.to_path_buf())
+        } else {
+            path.to_path_buf()
+        };
+        
+        if !path.is_dir() {
+            self.status = format!("Not a directory: {}", display_name(path.as_os_str()));
+            return;
+        }
+        
+        match directories_to_analyze(&path, &self.options) {
+            Ok(found) => {
+                let was_empty = self.directories.is_empty();
+                let added = found.len();
+                self.directories.extend(found);
+                self.status = format!("Added {} director{} to the queue", added, if added == 1 { "y" } else { "ies" });
+                if was_empty {
+                    self.scan();
+                }
+            }
+            Err(e) => self.status = format!("Error reading {}: {}", display_name(path.as_os_str()), e),
+        }
+    }
+    
+    /// Find the prefix groups of the current directory
+    fn scan(&mut self) {
+        self.groups.clear();
+        if let Some(directory) = self.directories.get(self.directory_index).cloned() {
+            match plan_directory(&directory, &self.options, Affix::Prefix) {
+                Ok(mut groups) => {
+                    groups.sort_by_key(|group| group.label());
+                    self.groups = groups;
+                }
+                Err(e) => self.status = format!("Error reading {}: {}", display_name(directory.as_os_str()), e),
+            }
+        }
+        
+        self.group_index = self.group_index.min(self.groups.len().saturating_sub(1));
+        self.select_group();
+    }
+    
+    /// Reset the file selection for the current group
+    fn select_group(&mut self) {
+        let count = self.current_group().map_or(0, |group| group.renames.len());
+        self.selection = Selection::all(count);
+        self.file_index = 0;
+    }
+    
+    fn current_group(&self) -> Option<&PlanGroup> {
+        self.groups.get(self.group_index)
+    }
+    
+    fn load_history(&mut self) {
+        match self.read_history() {
+            Ok(history) => self.history = history,
+            Err(e) => self.status = format!("Error reading history: {}", e),
+        }
+        self.history_index = self.history_index.min(self.history.len().saturating_sub(1));
+    }
+    
+    fn read_history(&self) -> rusqlite::Result<Vec<HistoryEntry>> {
+        let mut history = Vec::new();
+        for operation_id in self.db.get_recent_operations(HISTORY_LIMIT)? {
+            let records = self.db.get_operation_renames(&operation_id)?;
+            if let Some(first_record) = records.first() {
+                history.push(HistoryEntry {
+                    timestamp: first_record.timestamp,
+                    directory: first_record.directory.clone(),
+                    removed: first_record.prefix_removed.clone(),
+                    files: records.len(),
+                    kind: self.db.operation_kind(&operation_id)?.0,
+                    reverted_by: self.db.reverted_by(&operation_id)?,
+                    operation_id,
+                });
+            }
+        }
+        
+        Ok(history)
+    }
+    
+    fn handle_key(&mut self, key: KeyEvent) {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            self.quit = true;
+            return;
+        }
+        
+        match std::mem::replace(&mut self.mode, Mode::Normal) {
+            Mode::Normal => self.handle_normal_key(key),
+            Mode::Confirm(action) => match key.code {
+                KeyCode::Char('y') | KeyCode::Char('Y') => match action {
+                    Action::Rename => self.rename_group(),
+                    Action::Undo(operation_id) => self.undo(&operation_id),
+                    Action::Redo(undo_id) => self.redo(&undo_id),
+                },
+                _ => self.status = "Cancelled".to_string(),
+            },
+            Mode::Input(mut input) => match key.code {
+                KeyCode::Enter => self.add_directory(&input),
+                KeyCode::Esc => self.status = "Cancelled".to_string(),
+                KeyCode::Backspace => {
+                    input.pop();
+                    self.mode = Mode::Input(input);
+                }
+                KeyCode::Char(c) => {
+                    input.push(c);
+                    self.mode = Mode::Input(input);
+                }
+                _ => self.mode = Mode::Input(input),
+            },
+        }
+    }
+    
+    fn handle_normal_key(&mut self, key: KeyEvent) {
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Esc => self.quit = true,
+            KeyCode::Tab => self.focus = self.focus.next(),
+            KeyCode::BackTab => self.focus = self.focus.previous(),
+            KeyCode::Up | KeyCode::Char('k') => self.move_cursor(-1),
+            KeyCode::Down | KeyCode::Char('j') => self.move_cursor(1),
+            KeyCode::Char(' ') if self.focus == Pane::Preview => {
+                let command = if self.selection.is_selected(self.file_index) {
+                    SelectionCommand::Exclude(vec![self.file_index])
+                } else {
+                    SelectionCommand::Include(vec![self.file_index])
+                };
+                self.selection.apply(&command);
+            }
+            KeyCode::Char('i') => self.selection.apply(&SelectionCommand::Invert),
+            KeyCode::Enter | KeyCode::Char('r') => match self.current_group() {
+                Some(group) if self.selection.count() > 0 => {
+                    self.status = format!("Remove prefix {} from {} file(s)? (y/n)", group.label(), self.selection.count());
+                    self.mode = Mode::Confirm(Action::Rename);
+                }
+                Some(_) => self.status = "No files selected".to_string(),
+                None => self.status = "No prefix group to rename".to_string(),
+            },
+            KeyCode::Char('u') => match self.history.get(self.history_index) {
+                Some(entry) if entry.kind == OperationKind::Undo || entry.reverted_by.is_some() => {
+                    self.status = format!("{} is already undone, press R to redo it", entry.operation_id);
+                }
+                Some(entry) => {
+                    self.status = format!("Undo {} ({} file(s), removed {})? (y/n)", entry.operation_id, entry.files, entry.removed);
+                    self.mode = Mode::Confirm(Action::Undo(entry.operation_id.clone()));
+                }
+                None => self.status = "No operations to undo".to_string(),
+            },
+            KeyCode::Char('R') => match self.history.get(self.history_index) {
+                Some(entry) => {
+                    let undo_id = match (&entry.reverted_by, entry.kind) {
+                        (None, OperationKind::Undo) => Some(entry.operation_id.clone()),
+                        (Some(revert_id), _) if entry.kind != OperationKind::Undo => Some(revert_id.clone()),
+                        _ => None,
+                    };
+                    match undo_id {
+                        Some(undo_id) => {
+                            self.status = format!("Redo {} ({} file(s), removed {})? (y/n)", entry.operation_id, entry.files, entry.removed);
+                            self.mode = Mode::Confirm(Action::Redo(undo_id));
+                        }
+                        None => self.status = format!("{} is not undone, nothing to redo", entry.operation_id),
+                    }
+                }
+                None => self.status = "No operations to redo".to_string(),
+            },
+            KeyCode::Char('a') => self.mode = Mode::Input(String::new()),
+            KeyCode::Char('d') if self.directory_index < self.directories.len() => {
+                let removed = self.directories.remove(self.directory_index);
+                self.directory_index = self.directory_index.min(self.directories.len().saturating_sub(1));
+                self.status = format!("Removed {} from the queue", display_name(removed.as_os_str()));
+                self.scan();
+            }
+            _ => {}
+        }
+    }
+    
+    fn move_cursor(&mut self, delta: isize) {
+        let step = |index: usize, len: usize| index.saturating_add_signed(delta).min(len.saturating_sub(1));
+        match self.focus {
+            Pane::Directories => {
+                let index = step(self.directory_index, self.directories.len());
+                if index != self.directory_index {
+                    self.directory_index = index;
+                    self.group_index = 0;
+                    self.scan();
+                }
+            }
+            Pane::Groups => {
+                let index = step(self.group_index, self.groups.len());
+                if index != self.group_index {
+                    self.group_index = index;
+                    self.select_group();
+                }
+            }
+            Pane::Preview => {
+                let len = self.current_group().map_or(0, |group| group.renames.len());
+                self.file_index = step(self.file_index, len);
+            }
+            Pane::History => self.history_index = step(self.history_index, self.history.len()),
+        }
+    }
+    
+    /// Rename the selected files of the current group as one operation
+    fn rename_group(&mut self) {
+        let Some(group) = self.current_group() else {
+            return;
+        };
+        let selected = PlanGroup { renames: self.selection.filter(&group.renames), ..group.clone() };
+        let operation_id = generate_operation_id();
+        let context = OperationContext {
+            settings: Some(serde_json::json!({ "affix": selected.kind, "options": self.options }).to_string()),
+            ..OperationContext::current()
+        };
+        let started = Instant::now();
+        if let Err(e) = self.db.begin_operation(&operation_id, &context) {
+            self.status = format!("Error recording the operation: {}", e);
+            return;
+        }
+        
+        let mut problems = Vec::new();
+        let (success_count, error_count) = selected.execute_with(&self.db, &operation_id, None, |old_path, _, outcome| {
+            if let Some(problem) = describe_problem(outcome) {
+                problems.push(format!("{}: {}", display_name(old_path.file_name().unwrap_or_default()), problem));
+            }
+        });
+        let _ = self.db.finish_operation(&operation_id, success_count, error_count, started.elapsed());
+        
+        self.status = format!("Renamed {} file(s), {} failed (operation {})", success_count, error_count, operation_id);
+        if let Some(problem) = problems.first() {
+            self.status.push_str(&format!(" - {}", problem));
+        }
+        
+        self.history_index = 0;
+        self.load_history();
+        self.scan();
+        
+        // Move on once a directory has nothing left to rename
+        if self.groups.is_empty() && self.directory_index + 1 < self.directories.len() {
+            self.directory_index += 1;
+            self.group_index = 0;
+            self.scan();
+        }
+    }
+    
+    fn undo(&mut self, operation_id: &str) {
+        let mut problems = Vec::new();
+        let result = self.db.undo_operation_with(operation_id, |current, _, outcome| {
+            if let Some(problem) = describe_problem(outcome) {
+                problems.push(format!("{}: {}", display_name(current.file_name().unwrap_or_default()), problem));
+            }
+        });
+        
+        match result {
+            Ok((success_count, error_count)) => {
+                self.status = format!("Undid {}: {} restored, {} failed", operation_id, success_count, error_count);
+                if let Some(problem) = problems.first() {
+                    self.status.push_str(&format!(" - {}", problem));
+                }
+            }
+            Err(e) => self.status = format!("Error undoing {}: {}", operation_id, e),
+        }
+        
+        self.load_history();
+        self.scan();
+    }
+    
+    fn redo(&mut self, undo_id: &str) {
+        let mut problems = Vec::new();
+        let result = self.db.redo_operation_with(undo_id, |current, _, outcome| {
+            if let Some(problem) = describe_problem(outcome) {
+                problems.push(format!("{}: {}", display_name(current.file_name().unwrap_or_default()), problem));
+            }
+        });
+        
+        match result {
+            Ok((success_count, error_count)) => {
+                self.status = format!("Reverted {}: {} renamed again, {} failed", undo_id, success_count, error_count);
+                if let Some(problem) = problems.first() {
+                    self.status.push_str(&format!(" - {}", problem));
+                }
+            }
+            Err(e) => self.status = format!("Error redoing {}: {}", undo_id, e),
+        }
+        
+        self.load_history();
+        self.scan();
+    }
+}
+
+/// Short description of a failed rename, or `None` when nothing went wrong
+fn describe_problem(outcome: &RenameOutcome) -> Option<String> {
+    match outcome {
+        RenameOutcome::Renamed | RenameOutcome::Unchanged | RenameOutcome::Skipped => None,
+        RenameOutcome::SourceMissing => Some("file no longer exists".to_string()),
+        RenameOutcome::TargetExists => Some("target already exists".to_string()),
+        RenameOutcome::DuplicateTarget => Some("another file gets the same name".to_string()),
+        RenameOutcome::Failed(e) => Some(e.clone()),
+        RenameOutcome::IdentityMismatch(mismatch) => Some(mismatch.describe().to_string()),
+    }
+}
+
+fn draw(frame: &mut Frame, app: &App) {
+    let [main, status, help] = Layout::vertical([
+        Constraint::Min(0),
+        Constraint::Length(1),
+        Constraint::Length(1),
+    ]).areas(frame.area());
+    let [left, right] = Layout::horizontal([Constraint::Percentage(35), Constraint::Percentage(65)]).areas(main);
+    let [directories_area, groups_area] = Layout::vertical([Constraint::Percentage(40), Constraint::Percentage(60)]).areas(left);
+    let [preview_area, history_area] = Layout::vertical([Constraint::Percentage(60), Constraint::Percentage(40)]).areas(right);
+    
+    let directories: Vec<ListItem> = app.directories.iter()
+        .map(|directory| ListItem::new(display_name(directory.as_os_str()).into_owned()))
+        .collect();
+    render_list(frame, app, Pane::Directories, format!("Directories ({})", app.directories.len()),
+                directories, app.directory_index, directories_area);
+    
+    let groups: Vec<ListItem> = app.groups.iter()
+        .map(|group| ListItem::new(format!("{} ({} files)", group.label(), group.renames.len())))
+        .collect();
+    render_list(frame, app, Pane::Groups, format!("Prefix groups ({})", app.groups.len()),
+                groups, app.group_index, groups_area);
+    
+    let preview: Vec<ListItem> = app.current_group()
+        .map(|group| group.paths()
+            .enumerate()
+            .map(|(index, (old_path, new_path))| {
+                let mark = if app.selection.is_selected(index) { "x" } else { " " };
+                ListItem::new(format!("[{}] {} → {}", mark,
+                                      display_name(old_path.file_name().unwrap_or_default()),
+                                      display_name(new_path.file_name().unwrap_or_default())))
+            })
+            .collect())
+        .unwrap_or_default();
+    let preview_title = match app.current_group() {
+        Some(group) => format!("Preview: {} ({} of {} selected)", group.label(), app.selection.count(), group.renames.len()),
+        None => "Preview".to_string(),
+    };
+    render_list(frame, app, Pane::Preview, preview_title, preview, app.file_index, preview_area);
+    
+    let history: Vec<ListItem> = app.history.iter()
+        .map(|entry| {
+            let state = match (entry.kind, &entry.reverted_by) {
+                (_, Some(_)) => "  (undone)",
+                (OperationKind::Undo, None) => "  (undo)",
+                (OperationKind::Redo, None) => "  (redo)",
+                (OperationKind::Rename, None) => "",
+            };
+            ListItem::new(format!("{}  {}  {} file(s)  {}{}",
+                                  entry.timestamp.with_timezone(&Local).format("%Y-%m-%d %H:%M"),
+                                  entry.removed,
+                                  entry.files,
+                                  display_name(entry.directory.file_name().unwrap_or(entry.directory.as_os_str())),
+                                  state))
+        })
+        .collect();
+    render_list(frame, app, Pane::History, "History (u = undo, R = redo)".to_string(), history, app.history_index, history_area);
+    
+    let status_line = match &app.mode {
+        Mode::Input(input) => format!("Add directory: {}_", input),
+        _ => app.status.clone(),
+    };
+    frame.render_widget(Paragraph::new(status_line).style(Style::default().add_modifier(Modifier::BOLD)), status);
+    frame.render_widget(
+        Paragraph::new(Line::from("Tab pane  ↑↓ move  Space toggle  i invert  Enter rename  u undo  R redo  a add dir  d drop dir  q quit"))
+            .style(Style::default().fg(Color::DarkGray)),
+        help,
+    );
+}
+
+fn render_list(
+    frame: &mut Frame,
+    app: &App,
+    pane: Pane,
+    title: String,
+    items: Vec<ListItem>,
+    selected: usize,
+    area: ratatui::layout::Rect,
+) {
+    let border_style = if app.focus == pane {
+        Style::default().fg(Color::Yellow)
+    } else {
+        Style::default()
+    };
+    let has_items = !items.is_empty();
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).border_style(border_style).title(title))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED))
+        .highlight_symbol("> ");
+    let mut state = ListState::default().with_selected(has_items.then_some(selected));
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+fn print_help() {
+    println!("tui - Full-screen terminal UI for reviewing and removing prefixes");
+    println!();
+    println!("USAGE:");
+    println!("    ftmi tui [OPTIONS] [DIRECTORIES...]");
+    println!("    echo 'directory' | ftmi tui [OPTIONS]");
+    println!();
+    println!("OPTIONS:");
+    println!("    -r, --regex PATTERN Use custom regex to filter prefixes (default: \\[.*\\])");
+    println!("    --no-filter         Accept all prefixes (no regex filtering)");
+    println!("    --min N             Minimum number of files sharing a prefix (default: 2)");
+    println!("    --recursive         Queue every subdirectory as well");
+    println!("    --max-depth N       Limit recursion depth (implies --recursive)");
+    println!("    --pool              Analyze the whole tree as one group (implies --recursive)");
+    println!("    --normalize FORM    Compare names in Unicode form nfc or nfd");
+    println!("    --db PATH           Use this history database (see 'ftmi rename --help')");
+    println!("    -h, --help          Show this help message");
+    println!();
+    println!("PANES:");
+    println!("    Directories    Queue of directories to work through");
+    println!("    Prefix groups  Longest common prefixes of the current directory, as 'ftmi rename' finds them");
+    println!("    Preview        Old → new names of the selected group");
+    println!("    History        Recent rename operations");
+    println!();
+    println!("KEYS:");
+    println!("    Tab / Shift+Tab   Switch pane");
+    println!("    ↑ ↓ / k j         Move within the pane");
+    println!("    Space             Select or deselect a file (Preview pane)");
+    println!("    i                 Invert the file selection");
+    println!("    Enter / r         Remove the prefix from the selected files");
+    println!("    u                 Undo the operation selected in the History pane");
+    println!("    R                 Redo the undone operation (or undo) selected in the History pane");
+    println!("    a                 Add a directory to the queue");
+    println!("    d                 Drop the current directory from the queue");
+    println!("    q / Esc           Quit");
+    println!();
+    println!("DESCRIPTION:");
+    println!("    Renames are recorded like 'ftmi rename' does, so they can also be undone");
+    println!("    later with 'ftmi rename --undo'. Existing files are never overwritten.");
+    println!("    Use 'ftmi rename' for scripts and piped, line-based sessions.");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+    use std::fs::File;
+    use tempfile::TempDir;
+    
+    fn press(app: &mut App, keys: &[KeyCode]) {
+        for key in keys {
+            app.handle_key(KeyEvent::new(*key, KeyModifiers::NONE));
+        }
+    }
+    
+    fn test_app(temp_dir: &TempDir, names: &[&str]) -> Result<(App, PathBuf), Box<dyn std::error::Error>> {
+        let music = temp_dir.path().join("music");
+        std::fs::create_dir(&music)?;
+        for name in names {
+            File::create(music.join(name))?;
+        }
+        
+        let db = RenameDatabase::new(temp_dir.path().join("test.db"));
+        db.initialize()?;
+        let mut app = App::new(db, PrefixOptions::default());
+        app.add_directory(music.to_str().unwrap());
+        app.load_history();
+        Ok((app, music))
+    }
+    
+    #[test]
+    fn test_rename_and_undo_from_keys() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let (mut app, music) = test_app(&temp_dir, &["[Artist] One.mp3", "[Artist] Two.mp3"])?;
+        assert_eq!(app.groups[0].label(), "[Artist]");
+        
+        // Nothing happens until the rename is confirmed
+        press(&mut app, &[KeyCode::Enter, KeyCode::Char('n')]);
+        assert!(music.join("[Artist] One.mp3").exists());
+        
+        press(&mut app, &[KeyCode::Enter, KeyCode::Char('y')]);
+        assert!(music.join("One.mp3").exists());
+        assert!(music.join("Two.mp3").exists());
+        assert_eq!(app.history.len(), 1);
+        assert_eq!(app.history[0].files, 2);
+        
+        press(&mut app, &[KeyCode::Char('u'), KeyCode::Char('y')]);
+        assert!(music.join("[Artist] One.mp3").exists());
+        assert!(music.join("[Artist] Two.mp3").exists());
+        assert_eq!(app.groups[0].label(), "[Artist]");
+        
+        // The undo is in the history and can be redone
+        assert_eq!(app.history.len(), 2);
+        assert_eq!(app.history[0].kind, OperationKind::Undo);
+        assert!(app.history[1].reverted_by.is_some());
+        press(&mut app, &[KeyCode::Char('R'), KeyCode::Char('y')]);
+        assert!(music.join("One.mp3").exists());
+        assert!(music.join("Two.mp3").exists());
+        assert!(app.history.iter().all(|entry| entry.kind == OperationKind::Undo || entry.reverted_by.is_none()));
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_groups_are_delimited_prefixes() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let (mut app, music) = test_app(&temp_dir, &[
+            "[Tame Impala] Elephant.mp3",
+            "[Tame Impala] Let It Happen.mp3",
+            "[Tame Impala] The Less I Know The Better.mp3",
+            "[The Strokes] Last Nite.mp3",
+            "[The Strokes] Reptilia.mp3",
+            "[Mac DeMarco] Salad Days.mp3",
+            "[Mac DeMarco] Chamber of Reflection.mp3",
+        ])?;
+        assert!(!app.groups.is_empty());
+        assert!(app.groups.iter().all(|group| group.delimiter.is_some()));
+        assert_eq!(app.groups[0].label(), "[Tame Impala]");
+        
+        press(&mut app, &[KeyCode::Enter, KeyCode::Char('y')]);
+        assert!(music.join("Elephant.mp3").exists());
+        assert!(music.join("[The Strokes] Reptilia.mp3").exists());
+        
+        // The next artist is offered once the first one is renamed
+        assert!(!app.groups.is_empty());
+        assert!(app.groups.iter().all(|group| group.delimiter.is_some()));
+        assert!(app.groups.iter().all(|group| group.label() != "[T"));
+        Ok(())
+    }
+    
+    #[test]
+    fn test_deselected_files_are_not_renamed() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let (mut app, music) = test_app(&temp_dir, &["[Artist] One.mp3", "[Artist] Two.mp3"])?;
+        
+        press(&mut app, &[KeyCode::Tab, KeyCode::Char(' '), KeyCode::Enter, KeyCode::Char('y')]);
+        assert!(music.join("[Artist] One.mp3").exists());
+        assert!(music.join("Two.mp3").exists());
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_draw_shows_panes() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let (app, _) = test_app(&temp_dir, &["[Artist] One.mp3", "[Artist] Two.mp3"])?;
+        
+        let mut terminal = Terminal::new(TestBackend::new(120, 30))?;
+        terminal.draw(|frame| draw(frame, &app))?;
+        let screen: String = terminal.backend().buffer().content().iter().map(|cell| cell.symbol()).collect();
+        
+        assert!(screen.contains("Directories (1)"));
+        assert!(screen.contains("[Artist] (2 files)"));
+        assert!(screen.contains("[x] [Artist] One.mp3 → One.mp3"));
+        assert!(screen.contains("History"));
+        
+        Ok(())
+    }
+}
@@ -0,0 +1,408 @@
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+use crate::os_names::{name_from_bytes, name_to_bytes};
+use crate::prefix_finder::{find_longest_affix, normalized_forms, scan_files, PrefixMode, PrefixOptions};
+use crate::rename_plan::Affix;
+
+type DelimitedKey = (String, Option<(String, String)>);
+
+/// Characters stripped from the end of the name once a suffix is removed
+const SUFFIX_SEPARATORS: &[char] = &[' ', '_', '-', '.'];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommonSuffix {
+    pub suffix: String,
+    pub delimiter: Option<(String, String)>,
+    pub occurrences: usize,
+    pub files: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SuffixedPath {
+    pub paths: Vec<PathBuf>,
+    pub suffix: String,
+    pub delimiter: Option<(String, String)>,
+}
+
+/// Find common suffixes (trailing tags before the extension) among the files of a directory.
+/// `PrefixOptions` is shared with prefix detection: `SpecificPrefixes` lists the
+/// suffixes to look for and delimiters are matched at the end of the name.
+/// As with prefixes, suffixes containing undecodable bytes are never reported.
+pub fn find_common_suffix(directory: &Path, options: &PrefixOptions) -> Result<Vec<CommonSuffix>, std::io::Error> {
+    let mut suffix_map: HashMap<String, Vec<PathBuf>> = HashMap::new();
+    let mut delimiter_suffix_map: HashMap<DelimitedKey, Vec<PathBuf>> = HashMap::new();
+    
+    let entries = scan_files(directory, options)?;
+    
+    for (relative, filename) in &entries {
+        let (stem, _) = split_extension(filename);
+        match &options.mode {
+            PrefixMode::DelimiterOnly { delimiters } => {
+                for (open, close) in delimiters {
+                    if let Some(suffix) = extract_suffix_with_delimiter(stem, open, close) {
+                        let key = (suffix, Some((open.clone(), close.clone())));
+                        delimiter_suffix_map.entry(key).or_default().push(relative.clone());
+                    }
+                }
+            },
+            PrefixMode::SpecificPrefixes { prefixes } => {
+                // The configured strings are treated as suffixes here
+                for suffix in prefixes {
+                    if stem.ends_with(suffix.as_str()) && stem.len() > suffix.len() {
+                        suffix_map.entry(suffix.clone()).or_default().push(relative.clone());
+                    }
+                }
+            },
+            PrefixMode::DetectAll { delimiters } => {
+                for (open, close) in delimiters {
+                    if let Some(suffix) = extract_suffix_with_delimiter(stem, open, close) {
+                        let key = (suffix, Some((open.clone(), close.clone())));
+                        delimiter_suffix_map.entry(key).or_default().push(relative.clone());
+                    }
+                }
+                
+                for suffix in generate_suffix_candidates(stem) {
+                    suffix_map.entry(suffix).or_default().push(relative.clone());
+                }
+            }
+        }
+    }
+    
+    let mut results = Vec::new();
+    
+    // Process delimiter-based suffixes
+    for ((suffix, delimiter), files) in delimiter_suffix_map {
+        if files.len() >= options.min_occurrences && !suffix.contains(char::REPLACEMENT_CHARACTER) {
+            results.push(CommonSuffix {
+                suffix,
+                delimiter,
+                occurrences: files.len(),
+                files,
+            });
+        }
+    }
+    
+    // Keep ties in a stable order regardless of hash map iteration
+    results.sort_by(|a, b| (&a.suffix, &a.delimiter).cmp(&(&b.suffix, &b.delimiter)));
+    
+    // Process non-delimiter suffixes
+    let mut non_delimiter_results: Vec<CommonSuffix> = Vec::new();
+    for (suffix, mut files) in suffix_map {
+        files.sort();
+        files.dedup();
+        
+        if files.len() < options.min_occurrences {
+            continue;
+        }
+        
+        // Skip suffixes already covered by a delimiter-based suffix
+        let covered = results.iter().any(|cs| {
+            cs.delimiter.is_some() && files.iter().all(|f| cs.files.contains(f))
+        });
+        
+        // Skip suffixes that start with a close delimiter
+        if covered || suffix.starts_with([']', ')', '}', '"', '\'']) || suffix.contains(char::REPLACEMENT_CHARACTER) {
+            continue;
+        }
+        
+        non_delimiter_results.push(CommonSuffix {
+            suffix,
+            delimiter: None,
+            occurrences: files.len(),
+            files,
+        });
+    }
+    
+    // Remove redundant suffixes (e.g., keep "FINAL_v2" rather than also "v2")
+    non_delimiter_results.sort_by(|a, b| {
+        b.suffix.len().cmp(&a.suffix.len())
+            .then(b.occurrences.cmp(&a.occurrences))
+            .then_with(|| a.suffix.cmp(&b.suffix))
+    });
+    
+    let mut filtered_results: Vec<CommonSuffix> = Vec::new();
+    for candidate in non_delimiter_results {
+        let is_subset = filtered_results.iter().any(|selected| {
+            candidate.files.iter().all(|f| selected.files.contains(f)) &&
+            selected.suffix.ends_with(&candidate.suffix)
+        });
+        
+        if !is_subset {
+            filtered_results.push(candidate);
+        }
+    }
+    
+    results.extend(filtered_results);
+    results.sort_by_key(|suffix| std::cmp::Reverse(suffix.occurrences));
+    
+    Ok(results)
+}
+
+/// Longest text after the last dot that is still taken as an extension
+const MAX_EXTENSION_LEN: usize = 8;
+
+/// Split a filename into its stem and extension (including the dot).
+/// Leading dots (hidden files) are not treated as an extension.
+fn split_extension(filename: &str) -> (&str, &str) {
+    filename.split_at(extension_start(filename.as_bytes()))
+}
+
+/// Where the extension of a file name starts, or the length of the name if it
+/// has none. Only a short run of letters and digits with at least one letter
+/// counts, so that dots inside tags like `v1.2` or `(Vol. 2)` are kept.
+fn extension_start(name: &[u8]) -> usize {
+    match name.iter().rposition(|b| *b == b'.') {
+        Some(pos) if pos > 0 => {
+            let extension = &name[pos + 1..];
+            let valid = (1..=MAX_EXTENSION_LEN).contains(&extension.len())
+                && extension.iter().all(u8::is_ascii_alphanumeric)
+                && extension.iter().any(u8::is_ascii_alphabetic);
+            if valid { pos } else { name.len() }
+        }
+        _ => name.len(),
+    }
+}
+
+/// Extract a delimited suffix such as `(Remastered 2011)` from the end of a stem
+fn extract_suffix_with_delimiter(stem: &str, open: &str, close: &str) -> Option<String> {
+    let inner = stem.trim_end().strip_suffix(close)?;
+    let open_pos = inner.rfind(open)?;
+    let suffix = &inner[open_pos + open.len()..];
+    
+    // The suffix must not make up the whole name
+    if suffix.is_empty() || inner[..open_pos].trim().is_empty() {
+        return None;
+    }
+    
+    Some(suffix.to_string())
+}
+
+fn generate_suffix_candidates(stem: &str) -> Vec<String> {
+    let mut candidates = Vec::new();
+    
+    for separator in ['_', '-', '.', ' '] {
+        let parts: Vec<&str> = stem.split(separator).collect();
+        // Try suffixes of increasing length, never the whole stem
+        for i in (1..parts.len()).rev() {
+            if parts[..i].join("").is_empty() {
+                continue;
+            }
+            let suffix = parts[i..].join(&separator.to_string());
+            if !suffix.is_empty() {
+                candidates.push(suffix);
+            }
+        }
+    }
+    
+    candidates
+}
+
+/// Remove a suffix from the stem of a filename, keeping the extension
+pub fn remove_suffix(filename: &str, suffix: &str) -> String {
+    remove_suffix_span(filename, suffix)
+}
+
+/// Remove a delimited suffix from the stem of a filename, keeping the extension
+pub fn remove_suffix_with_delimiter(filename: &str, suffix: &str, open: &str, close: &str) -> String {
+    remove_suffix_span(filename, &format!("{}{}{}", open, suffix, close))
+}
+
+fn remove_suffix_span(filename: &str, span: &str) -> String {
+    // Also try the NFC/NFD forms in case the suffix was detected on normalized names
+    for form in normalized_forms(filename) {
+        let (stem, extension) = split_extension(&form);
+        if let Some(remaining) = stem.trim_end().strip_suffix(span) {
+            let remaining = remaining.trim_end_matches(SUFFIX_SEPARATORS);
+            if !remaining.is_empty() {
+                return format!("{}{}", remaining, extension);
+            }
+            break;
+        }
+    }
+    
+    filename.to_string()
+}
+
+/// Remove a suffix from a file name that may not be valid UTF-8.
+/// Valid names behave like `remove_suffix`; other names are matched byte for byte.
+pub fn remove_suffix_from_name(filename: &OsStr, suffix: &str) -> OsString {
+    if let Some(filename) = filename.to_str() {
+        return OsString::from(remove_suffix(filename, suffix));
+    }
+    
+    let bytes = name_to_bytes(filename);
+    let (stem, extension) = bytes.split_at(extension_start(&bytes));
+    let stem_end = stem.iter().rposition(|b| *b != b' ').map_or(0, |pos| pos + 1);
+    if let Some(remaining) = stem[..stem_end].strip_suffix(suffix.as_bytes()) {
+        let end = remaining.iter()
+            .rposition(|b| !SUFFIX_SEPARATORS.contains(&(*b as char)))
+            .map_or(0, |pos| pos + 1);
+        if end > 0 {
+            return name_from_bytes([&remaining[..end], extension].concat());
+        }
+    }
+    
+    filename.to_os_string()
+}
+
+/// Find the most common suffixes for a directory and return structured results.
+/// Mirrors `find_longest_prefix`: the regex filter is applied to the full suffix
+/// (including delimiters) and ties in occurrence count return multiple results.
+pub fn find_longest_suffix(directory: &Path, options: &PrefixOptions) -> Result<Vec<SuffixedPath>, std::io::Error> {
+    let results = find_longest_affix(directory, options, Affix::Suffix)?
+        .into_iter()
+        .map(|group| SuffixedPath {
+            paths: group.files.iter().map(|filename| directory.join(filename)).collect(),
+            suffix: group.affix,
+            delimiter: group.delimiter,
+        })
+        .collect();
+    
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use tempfile::TempDir;
+    
+    #[test]
+    fn test_extract_suffix_with_delimiter() {
+        assert_eq!(
+            extract_suffix_with_delimiter("Song (Remastered 2011)", "(", ")"),
+            Some("Remastered 2011".to_string())
+        );
+        assert_eq!(
+            extract_suffix_with_delimiter("clip [1080p]", "[", "]"),
+            Some("1080p".to_string())
+        );
+        assert_eq!(extract_suffix_with_delimiter("[Artist] Song", "[", "]"), None);
+        assert_eq!(extract_suffix_with_delimiter("(Only)", "(", ")"), None);
+    }
+    
+    #[test]
+    fn test_split_extension() {
+        assert_eq!(split_extension("Song (Live).mp3"), ("Song (Live)", ".mp3"));
+        assert_eq!(split_extension("archive.tar.gz"), ("archive.tar", ".gz"));
+        assert_eq!(split_extension(".hidden"), (".hidden", ""));
+        // Dots inside a tag do not start an extension
+        assert_eq!(split_extension("report_v1.2"), ("report_v1.2", ""));
+        assert_eq!(split_extension("Song (Vol. 2)"), ("Song (Vol. 2)", ""));
+        assert_eq!(split_extension("Song (Vol. 2).flac"), ("Song (Vol. 2)", ".flac"));
+    }
+    
+    #[test]
+    fn test_suffixes_with_dots_in_extensionless_names() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let dir_path = temp_dir.path();
+        for name in ["report_v1.2", "memo_v1.2", "Song A (Vol. 2)", "Song B (Vol. 2)"] {
+            File::create(dir_path.join(name))?;
+        }
+        
+        let results = find_common_suffix(dir_path, &PrefixOptions::no_filter())?;
+        assert!(results.iter().any(|cs| cs.suffix == "Vol. 2" && cs.delimiter.is_some() && cs.occurrences == 2));
+        assert!(results.iter().any(|cs| cs.suffix == "v1.2" && cs.occurrences == 2));
+        
+        assert_eq!(remove_suffix("report_v1.2", "v1.2"), "report");
+        assert_eq!(remove_suffix_with_delimiter("Song A (Vol. 2)", "Vol. 2", "(", ")"), "Song A");
+        assert_eq!(remove_suffix_from_name(OsStr::new("memo_v1.2"), "v1.2"), OsStr::new("memo"));
+        Ok(())
+    }
+    
+    #[test]
+    fn test_generate_suffix_candidates() {
+        let candidates = generate_suffix_candidates("report_FINAL_v2");
+        assert!(candidates.contains(&"v2".to_string()));
+        assert!(candidates.contains(&"FINAL_v2".to_string()));
+        assert!(!candidates.contains(&"report_FINAL_v2".to_string()));
+    }
+    
+    #[test]
+    fn test_find_common_suffix_detect_all() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let dir_path = temp_dir.path();
+        
+        File::create(dir_path.join("Song A (Remastered 2011).mp3"))?;
+        File::create(dir_path.join("Song B (Remastered 2011).mp3"))?;
+        File::create(dir_path.join("report_FINAL_v2.pdf"))?;
+        File::create(dir_path.join("memo_FINAL_v2.pdf"))?;
+        File::create(dir_path.join("other.txt"))?;
+        
+        let options = PrefixOptions::no_filter();
+        let results = find_common_suffix(dir_path, &options)?;
+        
+        assert!(results.iter().any(|cs| cs.suffix == "Remastered 2011" && cs.delimiter.is_some() && cs.occurrences == 2));
+        assert!(results.iter().any(|cs| cs.suffix == "FINAL_v2" && cs.delimiter.is_none()));
+        // "v2" is redundant with "FINAL_v2"
+        assert!(!results.iter().any(|cs| cs.suffix == "v2"));
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_find_common_suffix_orders_ties() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let dir_path = temp_dir.path();
+        for name in ["a (Live).mp3", "b (Live).mp3", "c (Demo).mp3", "d (Demo).mp3", "e (Edit).mp3", "f (Edit).mp3"] {
+            File::create(dir_path.join(name))?;
+        }
+        
+        let results = find_longest_suffix(dir_path, &PrefixOptions::paren_only())?;
+        let suffixes: Vec<&str> = results.iter().map(|result| result.suffix.as_str()).collect();
+        assert_eq!(suffixes, vec!["Demo", "Edit", "Live"]);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_find_longest_suffix_specific() -> Result<(), Box<dyn std::error::Error>> {
+        let temp_dir = TempDir::new()?;
+        let dir_path = temp_dir.path();
+        
+        File::create(dir_path.join("intro_final.wav"))?;
+        File::create(dir_path.join("outro_final.wav"))?;
+        File::create(dir_path.join("bridge.wav"))?;
+        
+        let options = PrefixOptions {
+            mode: PrefixMode::SpecificPrefixes { prefixes: vec!["_final".to_string()] },
+            min_occurrences: 1,
+            filter_regex: None,
+            recursion: None,
+            normalization: None,
+        };
+        let results = find_longest_suffix(dir_path, &options)?;
+        
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].suffix, "_final");
+        assert_eq!(results[0].paths.len(), 2);
+        
+        Ok(())
+    }
+    
+    #[test]
+    fn test_remove_suffix() {
+        assert_eq!(remove_suffix("report_FINAL_v2.pdf", "FINAL_v2"), "report.pdf");
+        assert_eq!(remove_suffix("intro_final.wav", "_final"), "intro.wav");
+        assert_eq!(remove_suffix("FINAL_v2.pdf", "FINAL_v2"), "FINAL_v2.pdf");
+        assert_eq!(remove_suffix("no_match.txt", "v2"), "no_match.txt");
+        assert_eq!(
+            remove_suffix_with_delimiter("Song (Remastered 2011).mp3", "Remastered 2011", "(", ")"),
+            "Song.mp3"
+        );
+        assert_eq!(
+            remove_suffix_with_delimiter("clip [1080p].mkv", "1080p", "[", "]"),
+            "clip.mkv"
+        );
+    }
+    
+    #[cfg(unix)]
+    #[test]
+    fn test_remove_suffix_from_name_non_utf8() {
+        let name = name_from_bytes(b"Caf\xe9 (Live).mp3".to_vec());
+        assert_eq!(remove_suffix_from_name(&name, "(Live)"), name_from_bytes(b"Caf\xe9.mp3".to_vec()));
+        assert_eq!(remove_suffix_from_name(&name, "(Demo)"), name);
+        assert_eq!(remove_suffix_from_name(OsStr::new("Song (Live).mp3"), "(Live)"), OsStr::new("Song.mp3"));
+    }
+}
@@ -0,0 +1,17 @@
+-- renames.db as written by the first release: paths stored as TEXT and no
+-- journal, operations or schema version
+CREATE TABLE renames (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    timestamp TEXT NOT NULL,
+    old_path TEXT NOT NULL,
+    new_path TEXT NOT NULL,
+    directory TEXT NOT NULL,
+    prefix_removed TEXT NOT NULL,
+    operation_id TEXT NOT NULL
+);
+CREATE INDEX idx_operation_id ON renames(operation_id);
+CREATE INDEX idx_timestamp ON renames(timestamp);
+
+INSERT INTO renames (timestamp, old_path, new_path, directory, prefix_removed, operation_id) VALUES
+    ('2023-11-14T22:13:20+00:00', '/music/[Artist] One.mp3', '/music/One.mp3', '/music', 'Artist', 'op_1700000000'),
+    ('2023-11-14T22:13:20+00:00', '/music/[Artist] Two.mp3', '/music/Two.mp3', '/music', 'Artist', 'op_1700000000');
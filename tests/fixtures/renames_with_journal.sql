@@ -0,0 +1,31 @@
+-- renames.db after the rename journal was added: paths stored as BLOB, an
+-- interrupted rename left in the journal, no schema version
+CREATE TABLE renames (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    timestamp TEXT NOT NULL,
+    old_path BLOB NOT NULL,
+    new_path BLOB NOT NULL,
+    directory BLOB NOT NULL,
+    prefix_removed TEXT NOT NULL,
+    operation_id TEXT NOT NULL
+);
+CREATE INDEX idx_operation_id ON renames(operation_id);
+CREATE INDEX idx_timestamp ON renames(timestamp);
+CREATE TABLE journal (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    timestamp TEXT NOT NULL,
+    old_path BLOB NOT NULL,
+    new_path BLOB NOT NULL,
+    temp_path BLOB,
+    parked INTEGER NOT NULL DEFAULT 0,
+    directory BLOB NOT NULL,
+    prefix_removed TEXT NOT NULL,
+    operation_id TEXT NOT NULL
+);
+
+INSERT INTO renames (timestamp, old_path, new_path, directory, prefix_removed, operation_id) VALUES
+    ('2025-09-14T22:35:53+00:00', CAST('/music/[Artist] Two.mp3' AS BLOB), CAST('/music/Two.mp3' AS BLOB),
+     CAST('/music' AS BLOB), '[Artist]', 'op_1757889353');
+INSERT INTO journal (timestamp, old_path, new_path, temp_path, parked, directory, prefix_removed, operation_id) VALUES
+    ('2025-09-14T22:35:53+00:00', CAST('/music/[Artist] One.mp3' AS BLOB), CAST('/music/One.mp3' AS BLOB),
+     CAST('/music/.ftmi-tmp-0-One.mp3' AS BLOB), 1, CAST('/music' AS BLOB), '[Artist]', 'op_1757889353');
@@ -0,0 +1,41 @@
+-- renames.db after undo and redo were recorded as operations: collision
+-- strategies, an operation that was undone, no schema version
+CREATE TABLE renames (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    timestamp TEXT NOT NULL,
+    old_path BLOB NOT NULL,
+    new_path BLOB NOT NULL,
+    directory BLOB NOT NULL,
+    prefix_removed TEXT NOT NULL,
+    operation_id TEXT NOT NULL,
+    collision TEXT,
+    reverted_by TEXT
+);
+CREATE INDEX idx_operation_id ON renames(operation_id);
+CREATE INDEX idx_timestamp ON renames(timestamp);
+CREATE TABLE journal (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    timestamp TEXT NOT NULL,
+    old_path BLOB NOT NULL,
+    new_path BLOB NOT NULL,
+    temp_path BLOB,
+    parked INTEGER NOT NULL DEFAULT 0,
+    directory BLOB NOT NULL,
+    prefix_removed TEXT NOT NULL,
+    operation_id TEXT NOT NULL,
+    collision TEXT
+);
+CREATE TABLE operations (
+    id TEXT PRIMARY KEY,
+    timestamp TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    reverts TEXT
+);
+
+INSERT INTO renames (timestamp, old_path, new_path, directory, prefix_removed, operation_id, collision, reverted_by) VALUES
+    ('2025-09-14T22:35:53+00:00', CAST('/music/[B] Intro.mp3' AS BLOB), CAST('/music/Intro (2).mp3' AS BLOB),
+     CAST('/music' AS BLOB), '[B]', 'op_01K55YJ7X8Q3N4V6B2C9D0E1F2', 'number', 'op_01K55YJ7X8Q3N4V6B2C9D0E1F3'),
+    ('2025-09-14T22:40:00+00:00', CAST('/music/Intro (2).mp3' AS BLOB), CAST('/music/[B] Intro.mp3' AS BLOB),
+     CAST('/music' AS BLOB), '[B]', 'op_01K55YJ7X8Q3N4V6B2C9D0E1F3', NULL, NULL);
+INSERT INTO operations (id, timestamp, kind, reverts) VALUES
+    ('op_01K55YJ7X8Q3N4V6B2C9D0E1F3', '2025-09-14T22:40:00+00:00', 'undo', 'op_01K55YJ7X8Q3N4V6B2C9D0E1F2');
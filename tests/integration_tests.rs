@@ -88,6 +88,538 @@ fn test_no_paths() {
     assert_eq!(output.trim(), "");
 }
 
+#[test]
+fn test_rename_plan_and_apply() {
+    let home = tempfile::TempDir::new().unwrap();
+    let music = home.path().join("music");
+    std::fs::create_dir_all(&music).unwrap();
+    std::fs::File::create(music.join("[Artist] One.mp3")).unwrap();
+    std::fs::File::create(music.join("[Artist] Two.mp3")).unwrap();
+    let plan = home.path().join("plan.json");
+    
+    // Writing a plan does not rename anything
+    run_ftmi_in(home.path(), &["rename", "--plan", plan.to_str().unwrap(), music.to_str().unwrap()]);
+    assert!(plan.exists());
+    assert!(music.join("[Artist] One.mp3").exists());
+    
+    let output = run_ftmi_in(home.path(), &["apply", plan.to_str().unwrap()]);
+    assert!(output.contains("2 successful, 0 failed"));
+    assert!(music.join("One.mp3").exists());
+    assert!(music.join("Two.mp3").exists());
+}
+
+#[test]
+fn test_rename_yes_without_terminal() {
+    let home = tempfile::TempDir::new().unwrap();
+    let music = home.path().join("music");
+    std::fs::create_dir_all(&music).unwrap();
+    std::fs::File::create(music.join("[Artist] One.mp3")).unwrap();
+    std::fs::File::create(music.join("[Artist] Two.mp3")).unwrap();
+    
+    // --assume-no shows the groups but renames nothing
+    run_ftmi_in(home.path(), &["rename", "--assume-no", music.to_str().unwrap()]);
+    assert!(music.join("[Artist] One.mp3").exists());
+    
+    let output = run_ftmi_in(home.path(), &["rename", "--yes", music.to_str().unwrap()]);
+    assert!(output.contains("2 successful, 0 failed"));
+    assert!(music.join("One.mp3").exists());
+    assert!(music.join("Two.mp3").exists());
+    
+    // The renames are recorded and can be undone without a terminal too
+    run_ftmi_in(home.path(), &["rename", "--undo", "--yes"]);
+    assert!(music.join("[Artist] One.mp3").exists());
+    assert!(music.join("[Artist] Two.mp3").exists());
+}
+
+#[test]
+fn test_undo_then_redo() {
+    let home = tempfile::TempDir::new().unwrap();
+    let music = home.path().join("music");
+    std::fs::create_dir_all(&music).unwrap();
+    std::fs::File::create(music.join("[Artist] One.mp3")).unwrap();
+    std::fs::File::create(music.join("[Artist] Two.mp3")).unwrap();
+    
+    run_ftmi_in(home.path(), &["rename", "--yes", music.to_str().unwrap()]);
+    run_ftmi_in(home.path(), &["rename", "--undo", "--yes"]);
+    assert!(music.join("[Artist] One.mp3").exists());
+    
+    let output = run_ftmi_in(home.path(), &["rename", "--list"]);
+    assert!(output.contains("↩️  Undo of op_"));
+    assert!(output.contains("State: undone by op_"));
+    
+    // Nothing is left to undo, but the undone operation can be redone
+    let output = run_ftmi_in(home.path(), &["rename", "--undo", "--yes"]);
+    assert!(output.contains("No operations found to undo"));
+    let output = run_ftmi_in(home.path(), &["rename", "--redo", "--yes"]);
+    assert!(output.contains("Redo results: 2 successful, 0 failed"));
+    assert!(music.join("One.mp3").exists());
+    assert!(music.join("Two.mp3").exists());
+}
+
+#[test]
+fn test_undo_only_some_files() {
+    let home = tempfile::TempDir::new().unwrap();
+    let music = home.path().join("music");
+    std::fs::create_dir_all(&music).unwrap();
+    for name in ["[Artist] One.mp3", "[Artist] Two (Live).mp3", "[Artist] Three.mp3"] {
+        std::fs::File::create(music.join(name)).unwrap();
+    }
+    
+    run_ftmi_in(home.path(), &["rename", "--yes", music.to_str().unwrap()]);
+    run_ftmi_in(home.path(), &["rename", "--undo", "--only", "*Live*", "--yes"]);
+    assert!(music.join("[Artist] Two (Live).mp3").exists());
+    assert!(music.join("One.mp3").exists());
+    
+    let output = run_ftmi_in(home.path(), &["rename", "--list"]);
+    assert!(output.contains("Files renamed: 3 (1 undone)"));
+    
+    // The most recent rename is still only partly undone, so a plain undo finishes it
+    run_ftmi_in(home.path(), &["rename", "--undo", "--yes"]);
+    assert!(music.join("[Artist] One.mp3").exists());
+    assert!(music.join("[Artist] Three.mp3").exists());
+}
+
+#[test]
+fn test_undo_follows_later_renames() {
+    let home = tempfile::TempDir::new().unwrap();
+    let music = home.path().join("music");
+    std::fs::create_dir_all(&music).unwrap();
+    std::fs::File::create(music.join("[Disc 1] [Artist] One.mp3")).unwrap();
+    std::fs::File::create(music.join("[Disc 1] [Artist] Two.mp3")).unwrap();
+    
+    // The first run removes [Disc 1], the second one [Artist]
+    run_ftmi_in(home.path(), &["rename", "--yes", "--policy=first-group", music.to_str().unwrap()]);
+    run_ftmi_in(home.path(), &["rename", "--yes", music.to_str().unwrap()]);
+    assert!(music.join("One.mp3").exists());
+    
+    let output = run_ftmi_in(home.path(), &["rename", "--list"]);
+    let first_op = output.lines()
+        .rev()
+        .find_map(|line| line.split("Operation ID: ").nth(1))
+        .unwrap()
+        .to_string();
+    
+    let output = run_ftmi_in(home.path(), &["rename", "--undo", &first_op, "--yes"]);
+    assert!(output.contains("renamed again by later operations"));
+    assert!(music.join("[Disc 1] [Artist] One.mp3").exists());
+    assert!(music.join("[Disc 1] [Artist] Two.mp3").exists());
+}
+
+#[test]
+fn test_undo_refuses_changed_files_without_force() {
+    let home = tempfile::TempDir::new().unwrap();
+    let music = home.path().join("music");
+    std::fs::create_dir_all(&music).unwrap();
+    std::fs::write(music.join("[Artist] One.mp3"), "one").unwrap();
+    std::fs::write(music.join("[Artist] Two.mp3"), "two").unwrap();
+    
+    run_ftmi_in(home.path(), &["rename", "--yes", music.to_str().unwrap()]);
+    std::fs::write(music.join("One.mp3"), "one, retagged").unwrap();
+    
+    let output = ftmi_in(home.path())
+        .args(["rename", "--undo", "--yes"])
+        .stdin(Stdio::null())
+        .output()
+        .expect("Failed to run ftmi");
+    
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Not undoing"));
+    assert!(music.join("One.mp3").exists());
+    assert!(music.join("[Artist] Two.mp3").exists());
+    
+    run_ftmi_in(home.path(), &["rename", "--undo", "--yes", "--force"]);
+    assert_eq!(std::fs::read_to_string(music.join("[Artist] One.mp3")).unwrap(), "one, retagged");
+}
+
+#[test]
+fn test_operation_note_tags_and_details() {
+    let home = tempfile::TempDir::new().unwrap();
+    let music = home.path().join("music");
+    std::fs::create_dir_all(&music).unwrap();
+    std::fs::File::create(music.join("[Artist] One.mp3")).unwrap();
+    std::fs::File::create(music.join("[Artist] Two.mp3")).unwrap();
+    
+    run_ftmi_in(home.path(), &["rename", "--yes", "--note", "weekly cleanup", "--tag", "music", music.to_str().unwrap()]);
+    
+    let output = run_ftmi_in(home.path(), &["rename", "--list"]);
+    assert!(output.contains("Note: weekly cleanup"));
+    assert!(output.contains("Tags: music"));
+    let operation_id = output.lines()
+        .find_map(|line| line.split("Operation ID: ").nth(1))
+        .unwrap()
+        .to_string();
+    
+    run_ftmi_in(home.path(), &["rename", "--annotate", &operation_id, "--tag", "audited"]);
+    let output = run_ftmi_in(home.path(), &["rename", "--show", &operation_id]);
+    assert!(output.contains("Command: "));
+    assert!(output.contains("--note weekly cleanup"));
+    assert!(output.contains(&format!("ftmi version: {}", env!("CARGO_PKG_VERSION"))));
+    assert!(output.contains(r#""filter_regex":"\\[.*\\]""#));
+    assert!(output.contains("Results: 2 successful, 0 failed"));
+    assert!(output.contains("Tags: music, audited"));
+}
+
+#[test]
+fn test_project_history_is_found_from_subdirectories() {
+    let home = tempfile::TempDir::new().unwrap();
+    let library = home.path().join("nas").join("library");
+    let album = library.join("album");
+    std::fs::create_dir_all(library.join(".ftmi")).unwrap();
+    std::fs::create_dir_all(&album).unwrap();
+    std::fs::File::create(album.join("[Artist] One.mp3")).unwrap();
+    std::fs::File::create(album.join("[Artist] Two.mp3")).unwrap();
+    
+    let output = ftmi_in(home.path())
+        .args(["rename", "--yes", album.to_str().unwrap()])
+        .stdin(Stdio::null())
+        .output()
+        .expect("Failed to run ftmi");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Using project history"));
+    assert!(library.join(".ftmi").join("renames.db").exists());
+    
+    // The user's own history does not know the operation
+    let output = run_ftmi_in(home.path(), &["rename", "--list"]);
+    assert!(output.contains("No operations found"));
+    
+    // Anyone working inside the library sees it and can undo it
+    let output = ftmi_in(home.path())
+        .args(["rename", "--undo", "--yes"])
+        .current_dir(&album)
+        .stdin(Stdio::null())
+        .output()
+        .expect("Failed to run ftmi");
+    assert!(output.status.success(), "ftmi failed: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(album.join("[Artist] One.mp3").exists());
+}
+
+#[test]
+fn test_database_location_flag_environment_and_xdg() {
+    let home = tempfile::TempDir::new().unwrap();
+    let music = home.path().join("music");
+    std::fs::create_dir_all(&music).unwrap();
+    std::fs::File::create(music.join("[Artist] One.mp3")).unwrap();
+    std::fs::File::create(music.join("[Artist] Two.mp3")).unwrap();
+    let custom = home.path().join("shared").join("history.db");
+    
+    run_ftmi_in(home.path(), &["rename", "--yes", "--db", custom.to_str().unwrap(), music.to_str().unwrap()]);
+    assert!(custom.exists());
+    
+    let output = ftmi_in(home.path())
+        .args(["rename", "--list"])
+        .env("FTMI_DB", &custom)
+        .output()
+        .expect("Failed to run ftmi");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Files renamed: 2"));
+    
+    // Without either, new histories go to the XDG data directory
+    let data_home = home.path().join("data");
+    let output = ftmi_in(home.path())
+        .args(["rename", "--list"])
+        .env("XDG_DATA_HOME", &data_home)
+        .output()
+        .expect("Failed to run ftmi");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("No operations found"));
+    assert!(data_home.join("ftmi").join("renames.db").exists());
+}
+
+#[test]
+fn test_history_export_and_import() {
+    let home = tempfile::TempDir::new().unwrap();
+    let music = home.path().join("music");
+    std::fs::create_dir_all(&music).unwrap();
+    std::fs::File::create(music.join("[Artist] One.mp3")).unwrap();
+    std::fs::File::create(music.join("[Artist] Two's.mp3")).unwrap();
+    run_ftmi_in(home.path(), &["rename", "--yes", music.to_str().unwrap()]);
+    
+    let csv = run_ftmi_in(home.path(), &["history", "export", "--format", "csv"]);
+    assert_eq!(csv.lines().count(), 3);
+    assert!(csv.starts_with("operation_id,kind,timestamp,old_path,new_path"));
+    
+    let json = run_ftmi_in(home.path(), &["history", "export"]);
+    let export_file = home.path().join("history.json");
+    std::fs::write(&export_file, &json).unwrap();
+    
+    // A script that renames the files back
+    let script = home.path().join("revert.sh");
+    run_ftmi_in(home.path(), &["history", "export", "--format", "sh", "--revert", "-o", script.to_str().unwrap()]);
+    let status = Command::new("sh").arg(&script).status().expect("Failed to run sh");
+    assert!(status.success());
+    assert!(music.join("[Artist] One.mp3").exists());
+    assert!(music.join("[Artist] Two's.mp3").exists());
+    
+    // Running it again skips every file and reports it
+    let status = Command::new("sh").arg(&script).stderr(Stdio::null()).status().expect("Failed to run sh");
+    assert_eq!(status.code(), Some(2));
+    
+    // Replay the renames, then import the history elsewhere and undo from there
+    let output = run_ftmi_in(home.path(), &["history", "export", "--format", "sh"]);
+    let status = Command::new("sh").arg("-c").arg(&output).status().expect("Failed to run sh");
+    assert!(status.success());
+    assert!(music.join("One.mp3").exists());
+    
+    let other = home.path().join("other.db");
+    let output = run_ftmi_in(home.path(), &["history", "import", "--db", other.to_str().unwrap(), export_file.to_str().unwrap()]);
+    assert!(output.contains("imported 1 operation(s) with 2 rename(s)"));
+    let output = run_ftmi_in(home.path(), &["history", "import", "--db", other.to_str().unwrap(), export_file.to_str().unwrap()]);
+    assert!(output.contains("Skipped 1 operation(s)"));
+    
+    run_ftmi_in(home.path(), &["rename", "--undo", "--yes", "--db", other.to_str().unwrap()]);
+    assert!(music.join("[Artist] One.mp3").exists());
+    assert!(music.join("[Artist] Two's.mp3").exists());
+}
+
+#[test]
+fn test_history_filters_and_trace() {
+    let home = tempfile::TempDir::new().unwrap();
+    let music = home.path().join("music");
+    let docs = home.path().join("docs");
+    std::fs::create_dir_all(&music).unwrap();
+    std::fs::create_dir_all(&docs).unwrap();
+    std::fs::File::create(music.join("[Artist] One.mp3")).unwrap();
+    std::fs::File::create(music.join("[Artist] Two.mp3")).unwrap();
+    std::fs::File::create(docs.join("(Draft) Plan.txt")).unwrap();
+    std::fs::File::create(docs.join("(Draft) Notes.txt")).unwrap();
+    run_ftmi_in(home.path(), &["rename", "--yes", "--tag", "music", music.to_str().unwrap()]);
+    run_ftmi_in(home.path(), &["rename", "--yes", docs.to_str().unwrap()]);
+    
+    let output = run_ftmi_in(home.path(), &["history", "--dir", music.to_str().unwrap()]);
+    assert!(output.contains("Removed: [Artist]"));
+    assert!(!output.contains("Removed: (Draft)"));
+    
+    let output = run_ftmi_in(home.path(), &["history", "--name", "*.txt"]);
+    assert!(output.contains("Removed: (Draft)"));
+    assert!(!output.contains("Removed: [Artist]"));
+    
+    let output = run_ftmi_in(home.path(), &["history", "--tag", "music", "--prefix", "Art"]);
+    assert!(output.contains("Removed: [Artist]"));
+    let output = run_ftmi_in(home.path(), &["history", "--until", "2000-01-01"]);
+    assert!(output.contains("No operations found"));
+    
+    // One operation per page, most recent first
+    let output = run_ftmi_in(home.path(), &["history", "--limit", "1"]);
+    assert!(output.contains("Removed: (Draft)"));
+    assert!(output.contains("--page 2"));
+    let output = run_ftmi_in(home.path(), &["history", "--limit", "1", "--page", "2"]);
+    assert!(output.contains("2. Operation ID"));
+    assert!(output.contains("Removed: [Artist]"));
+    assert!(!output.contains("--page 3"));
+    
+    let output = ftmi_in(home.path())
+        .args(["history", "trace", "One.mp3"])
+        .current_dir(&music)
+        .output()
+        .expect("Failed to run ftmi");
+    let output = String::from_utf8_lossy(&output.stdout);
+    assert!(output.contains("Earlier names:"));
+    assert!(output.contains("[Artist] One.mp3"));
+}
+
+#[test]
+fn test_history_trace_from_relative_directory() {
+    let home = tempfile::TempDir::new().unwrap();
+    std::fs::create_dir_all(home.path().join("d")).unwrap();
+    std::fs::File::create(home.path().join("d").join("[A] one.mp3")).unwrap();
+    std::fs::File::create(home.path().join("d").join("[A] two.mp3")).unwrap();
+    let output = ftmi_in(home.path())
+        .args(["rename", "--yes", "./d"])
+        .current_dir(home.path())
+        .stdin(Stdio::null())
+        .output()
+        .expect("Failed to run ftmi");
+    assert!(output.status.success());
+    assert!(home.path().join("d").join("one.mp3").exists());
+    
+    let output = ftmi_in(home.path())
+        .args(["history", "trace", "d/one.mp3"])
+        .current_dir(home.path())
+        .output()
+        .expect("Failed to run ftmi");
+    let output = String::from_utf8_lossy(&output.stdout);
+    assert!(output.contains("Earlier names:"));
+    assert!(output.contains("[A] one.mp3"));
+}
+
+#[test]
+fn test_history_prune_and_stats() {
+    let home = tempfile::TempDir::new().unwrap();
+    let music = home.path().join("music");
+    std::fs::create_dir_all(&music).unwrap();
+    std::fs::File::create(music.join("[Artist] One.mp3")).unwrap();
+    std::fs::File::create(music.join("[Artist] Two.mp3")).unwrap();
+    run_ftmi_in(home.path(), &["rename", "--yes", music.to_str().unwrap()]);
+    
+    let output = run_ftmi_in(home.path(), &["history", "stats"]);
+    assert!(output.contains("Operations: 1 rename, 0 undo, 0 redo"));
+    assert!(output.contains("Files renamed: 2"));
+    assert!(output.contains("Undo rate: 0%"));
+    assert!(output.contains("[Artist]"));
+    
+    // The operation can still be undone, so it is only pruned when forced
+    let output = run_ftmi_in(home.path(), &["history", "prune", "--keep-last", "0"]);
+    assert!(output.contains("Nothing to prune"));
+    assert!(output.contains("Kept 1 operation(s)"));
+    let output = run_ftmi_in(home.path(), &["history", "prune", "--older-than", "0d", "--force", "--dry-run"]);
+    assert!(output.contains("Would remove 1 operation(s) with 2 rename(s)"));
+    let output = run_ftmi_in(home.path(), &["rename", "--list"]);
+    assert!(output.contains("Files renamed: 2"));
+    
+    let output = run_ftmi_in(home.path(), &["history", "prune", "--older-than", "0d", "--force"]);
+    assert!(output.contains("Removed 1 operation(s) with 2 rename(s)"));
+    assert!(output.contains("Compacted the history"));
+    let output = run_ftmi_in(home.path(), &["rename", "--list"]);
+    assert!(output.contains("No operations found"));
+}
+
+#[test]
+fn test_invalid_max_depth_stops_before_renaming() {
+    let home = tempfile::TempDir::new().unwrap();
+    let music = home.path().join("music");
+    let album = music.join("album");
+    std::fs::create_dir_all(&album).unwrap();
+    std::fs::File::create(album.join("[Artist] One.mp3")).unwrap();
+    std::fs::File::create(album.join("[Artist] Two.mp3")).unwrap();
+    
+    for args in [vec!["rename", "--yes", "--max-depth", "abc", music.to_str().unwrap()], vec!["rename", "--yes", music.to_str().unwrap(), "--max-depth"]] {
+        let output = ftmi_in(home.path())
+            .args(&args)
+            .stdin(Stdio::null())
+            .output()
+            .expect("Failed to run ftmi");
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stderr).contains("--max-depth"));
+        assert!(album.join("[Artist] One.mp3").exists());
+    }
+}
+
+#[test]
+fn test_rename_invalid_arguments_exit_code() {
+    let home = tempfile::TempDir::new().unwrap();
+    let music = home.path().join("music");
+    std::fs::create_dir_all(&music).unwrap();
+    std::fs::File::create(music.join("[Artist] One.mp3")).unwrap();
+    std::fs::File::create(music.join("[Artist] Two.mp3")).unwrap();
+    let dir = music.to_str().unwrap();
+    
+    for args in [
+        vec!["rename", "--yes", "--policy", "bogus", dir],
+        vec!["rename", "--yes", dir, "--regex"],
+        vec!["rename", "--yes", "--on-collision", "bogus", dir],
+        vec!["rename", "--yes", "--bogus", dir],
+        vec!["rename", "--only", "1", dir],
+    ] {
+        let output = ftmi_in(home.path()).args(&args).output().unwrap();
+        assert_eq!(output.status.code(), Some(1), "{:?}", args);
+    }
+    
+    // Nothing was renamed
+    assert!(music.join("[Artist] One.mp3").exists());
+}
+
+#[test]
+fn test_rename_on_collision_number() {
+    let home = tempfile::TempDir::new().unwrap();
+    let music = home.path().join("music");
+    std::fs::create_dir_all(&music).unwrap();
+    for name in ["[A] Intro.mp3", "[A] Outro.mp3", "[B] Intro.mp3", "[B] Outro.mp3"] {
+        std::fs::write(music.join(name), name).unwrap();
+    }
+    
+    let output = run_ftmi_in(home.path(), &["rename", "--yes", "--on-collision", "number", music.to_str().unwrap()]);
+    assert!(output.contains("[B] Intro.mp3 → Intro (2).mp3"));
+    assert_eq!(std::fs::read_to_string(music.join("Intro.mp3")).unwrap(), "[A] Intro.mp3");
+    assert_eq!(std::fs::read_to_string(music.join("Intro (2).mp3")).unwrap(), "[B] Intro.mp3");
+    assert_eq!(std::fs::read_to_string(music.join("Outro (2).mp3")).unwrap(), "[B] Outro.mp3");
+    
+    let output = run_ftmi_in(home.path(), &["rename", "--list"]);
+    assert!(output.contains("[B] Intro.mp3 → Intro (2).mp3 (collision: number)"));
+}
+
+#[test]
+fn test_rename_yes_exit_code_on_partial_failure() {
+    let home = tempfile::TempDir::new().unwrap();
+    let music = home.path().join("music");
+    std::fs::create_dir_all(&music).unwrap();
+    std::fs::File::create(music.join("[Artist] One.mp3")).unwrap();
+    std::fs::File::create(music.join("[Artist] Two.mp3")).unwrap();
+    std::fs::File::create(music.join("One.mp3")).unwrap();
+    
+    let output = ftmi_in(home.path())
+        .args(["rename", "--yes", music.to_str().unwrap()])
+        .stdin(Stdio::null())
+        .output()
+        .expect("Failed to run ftmi");
+    
+    assert_eq!(output.status.code(), Some(2));
+    assert!(music.join("[Artist] One.mp3").exists());
+    assert!(music.join("Two.mp3").exists());
+}
+
+#[test]
+fn test_apply_atomic_renames_nothing_on_conflict() {
+    let home = tempfile::TempDir::new().unwrap();
+    let music = home.path().join("music");
+    std::fs::create_dir_all(&music).unwrap();
+    std::fs::File::create(music.join("[Artist] One.mp3")).unwrap();
+    std::fs::File::create(music.join("[Artist] Two.mp3")).unwrap();
+    let plan = home.path().join("plan.json");
+    run_ftmi_in(home.path(), &["rename", "--plan", plan.to_str().unwrap(), music.to_str().unwrap()]);
+    
+    // One target is taken after the plan was written
+    std::fs::File::create(music.join("One.mp3")).unwrap();
+    let output = ftmi_in(home.path())
+        .args(["apply", "--atomic", plan.to_str().unwrap()])
+        .stdin(Stdio::null())
+        .output()
+        .expect("Failed to run ftmi");
+    
+    assert_eq!(output.status.code(), Some(2));
+    assert!(music.join("[Artist] One.mp3").exists());
+    assert!(music.join("[Artist] Two.mp3").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_rename_edit_applies_edited_names() {
+    let home = tempfile::TempDir::new().unwrap();
+    let music = home.path().join("music");
+    std::fs::create_dir_all(&music).unwrap();
+    std::fs::File::create(music.join("[Artist] One.mp3")).unwrap();
+    std::fs::File::create(music.join("[Artist] Two.mp3")).unwrap();
+    
+    // A non-interactive "editor" that tweaks one of the proposed names
+    let output = ftmi_in(home.path())
+        .args(["rename", "--edit", music.to_str().unwrap()])
+        .env_remove("VISUAL")
+        .env("EDITOR", "perl -pi -e s/One/Uno/")
+        .stdin(Stdio::null())
+        .output()
+        .expect("Failed to run ftmi");
+    
+    assert!(output.status.success(), "ftmi failed: {}", String::from_utf8_lossy(&output.stderr));
+    assert!(music.join("Uno.mp3").exists());
+    assert!(music.join("Two.mp3").exists());
+}
+
+// Helper function to build an ftmi command with HOME pointing at a test directory
+// and no database location inherited from the environment
+fn ftmi_in(home: &std::path::Path) -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_ftmi"));
+    command.env("HOME", home).env_remove("XDG_DATA_HOME").env_remove("FTMI_DB");
+    command
+}
+
+// Helper function to run an ftmi subcommand with HOME pointing at a test directory
+fn run_ftmi_in(home: &std::path::Path, args: &[&str]) -> String {
+    let output = ftmi_in(home)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .expect("Failed to run ftmi");
+    
+    assert!(output.status.success(), "ftmi failed: {}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
 // Helper function to run ftmi extract-paths with input
 fn run_ftmi(input: &str) -> String {
     // Use the main ftmi binary with extract-paths subcommand